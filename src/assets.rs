@@ -0,0 +1,339 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::mem::size_of;
+use std::path::Path;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::loader::{Job, Loaded, Loader};
+use crate::mesh::{Material, Mesh, Texture, Vertex};
+use crate::utils::common::{missingImage, placeholderTexture, reloadTexture, uploadTexture, ColourSpace};
+use crate::utils::error::AssetError;
+use crate::utils::maths::genSphere;
+use crate::utils::model::{MeshData, Model};
+use crate::utils::shader::Shader;
+
+/// GPU texture shared through the asset manager, deleted once the last handle drops
+pub struct TextureResource {
+  pub id: u32,
+  pub path: String,
+  pub colourSpace: ColourSpace,
+  bytes: Cell<usize>
+}
+
+pub type TextureHandle = Rc<TextureResource>;
+pub type ModelHandle = Rc<Model>;
+pub type ShaderHandle = Rc<Shader>;
+
+impl Drop for TextureResource {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteTextures(1, &self.id) }
+  }
+}
+
+/// Result of a background model load, filled in by `AssetManager::update` once every mesh is uploaded
+#[derive(Clone)]
+pub struct PendingModel(Rc<RefCell<Option<Result<ModelHandle, AssetError>>>>);
+
+impl PendingModel {
+  /// The finished model, handed out once to whichever caller asks first
+  pub fn take(&self) -> Option<Result<ModelHandle, AssetError>> {
+    self.0.borrow_mut().take()
+  }
+}
+
+// Parsed model whose meshes are being uploaded a few at a time
+struct ModelUpload {
+  path: String,
+  remaining: VecDeque<MeshData>,
+  meshes: Vec<Mesh>
+}
+
+#[derive(Clone, Copy)]
+pub struct LoadProgress {
+  pub completed: usize,
+  pub requested: usize
+}
+
+/// Loads each file at most once while something still holds a handle to it. Files are read and
+/// decoded on worker threads, with the GL uploads spread over frames by `update`
+pub struct AssetManager {
+  textures: HashMap<(String, ColourSpace), Weak<TextureResource>>,
+  models: HashMap<String, Weak<Model>>,
+  shaders: HashMap<(String, String), Weak<Shader>>,
+  loader: Loader,
+  loadingModels: HashMap<String, PendingModel>,
+  uploads: VecDeque<ModelUpload>,
+  progress: LoadProgress,
+  /// Why each texture showing the checkerboard failed, until a reload fixes it
+  textureErrors: RefCell<HashMap<String, String>>
+}
+
+#[derive(Default)]
+pub struct MemoryReport {
+  pub textures: usize,
+  pub textureBytes: usize,
+  pub meshes: usize,
+  pub meshBytes: usize,
+  pub shaders: usize
+}
+
+impl AssetManager {
+  pub fn new() -> AssetManager {
+    AssetManager {
+      textures: HashMap::default(),
+      models: HashMap::default(),
+      shaders: HashMap::default(),
+      loader: Loader::new(),
+      loadingModels: HashMap::default(),
+      uploads: VecDeque::new(),
+      progress: LoadProgress { completed: 0, requested: 0 },
+      textureErrors: RefCell::new(HashMap::default())
+    }
+  }
+
+  /// Returns straight away with a grey placeholder that the decoded image replaces in place. A
+  /// missing or broken file shows the checkerboard instead until a reload fixes it
+  pub fn texture(&mut self, path: &str, colourSpace: ColourSpace) -> TextureHandle {
+    let key = (path.to_string(), colourSpace);
+    if let Some(texture) = self.textures.get(&key).and_then(Weak::upgrade) {
+      return texture;
+    }
+
+    let id = unsafe { placeholderTexture() };
+    let texture = Rc::new(TextureResource { id, path: path.into(), colourSpace, bytes: Cell::new(unsafe { textureBytes(id) }) });
+    self.textures.insert(key, Rc::downgrade(&texture));
+    self.request(Job::Texture(path.into()));
+    texture
+  }
+
+  /// Starts loading a model in the background, see `PendingModel`
+  pub fn model(&mut self, path: &str) -> PendingModel {
+    if let Some(pending) = self.loadingModels.get(path) {
+      return pending.clone();
+    }
+
+    let pending = PendingModel(Rc::new(RefCell::new(None)));
+    match self.models.get(path).and_then(Weak::upgrade) {
+      Some(model) => *pending.0.borrow_mut() = Some(Ok(model)),
+      None => {
+        self.loadingModels.insert(path.into(), pending.clone());
+        self.request(Job::Model(path.into()));
+      }
+    }
+    pending
+  }
+
+  /// Untextured sphere for entities to show while their model loads
+  pub fn placeholderModel(&mut self) -> ModelHandle {
+    if let Some(model) = self.models.get("placeholder").and_then(Weak::upgrade) {
+      return model;
+    }
+
+    let resource = Rc::new(TextureResource { id: unsafe { placeholderTexture() }, path: String::new(), colourSpace: ColourSpace::Linear, bytes: Cell::new(0) });
+    let textures = vec![
+      Texture { resource: resource.clone(), type_: "texture_diffuse".into() },
+      Texture { resource, type_: "texture_specular".into() }
+    ];
+    let (vertices, indices) = genSphere(8, 12);
+    let mut mesh = Mesh::new(vertices, indices, textures);
+    mesh.material = Some(Material::default());
+    self.addModel("placeholder", Model::fromMeshes(vec![mesh]))
+  }
+
+  pub fn progress(&self) -> LoadProgress {
+    self.progress
+  }
+
+  /// Uploads finished background work until `budget` is used up, so loading never stalls a frame for long
+  pub fn update(&mut self, budget: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < budget {
+      match self.loader.tryRecv() {
+        Some(loaded) => self.finish(loaded),
+        None => if !self.uploadNextMesh() { break }
+      }
+    }
+  }
+
+  fn request(&mut self, job: Job) {
+    self.progress.requested += 1;
+    self.loader.request(job);
+  }
+
+  fn finish(&mut self, loaded: Loaded) {
+    match loaded {
+      Loaded::Texture(path, result) => {
+        self.progress.completed += 1;
+        // The same file can be in use as both sRGB and linear, and there's nothing to do for
+        // either once every handle was dropped while it was decoding
+        let live: Vec<TextureHandle> = self.textures.iter()
+          .filter(|((p, _), _)| *p == path)
+          .filter_map(|(_, t)| t.upgrade())
+          .collect();
+        if live.is_empty() {
+          return;
+        }
+        let data = result.unwrap_or_else(|e| {
+          println!("{}", e);
+          self.textureErrors.borrow_mut().insert(path.clone(), e.to_string());
+          missingImage()
+        });
+        for texture in live {
+          unsafe {
+            uploadTexture(texture.id, &data, texture.colourSpace);
+            texture.bytes.set(textureBytes(texture.id));
+          }
+        }
+      }
+      Loaded::Model(path, Ok(meshes)) => {
+        self.uploads.push_back(ModelUpload { path, remaining: meshes.into_iter().collect(), meshes: vec![] });
+      }
+      Loaded::Model(path, Err(e)) => {
+        self.progress.completed += 1;
+        if let Some(pending) = self.loadingModels.remove(&path) {
+          *pending.0.borrow_mut() = Some(Err(e));
+        }
+      }
+    }
+  }
+
+  /// Creates the GL objects for one mesh of the oldest model in the queue, returning false if there was none
+  fn uploadNextMesh(&mut self) -> bool {
+    let mut upload = match self.uploads.pop_front() {
+      Some(upload) => upload,
+      None => return false
+    };
+
+    if let Some(data) = upload.remaining.pop_front() {
+      let mesh = data.upload(self);
+      upload.meshes.push(mesh);
+    }
+
+    if upload.remaining.is_empty() {
+      self.progress.completed += 1;
+      let model = Rc::new(Model::fromMeshes(upload.meshes));
+      self.models.insert(upload.path.clone(), Rc::downgrade(&model));
+      if let Some(pending) = self.loadingModels.remove(&upload.path) {
+        *pending.0.borrow_mut() = Some(Ok(model));
+      }
+    } else {
+      self.uploads.push_front(upload);
+    }
+    true
+  }
+
+  /// Registers meshes built in code under `name` so they show up in the memory report
+  pub fn addModel(&mut self, name: &str, model: Model) -> ModelHandle {
+    let model = Rc::new(model);
+    self.models.insert(name.into(), Rc::downgrade(&model));
+    model
+  }
+
+  pub fn shader(&mut self, vertexPath: &str, fragmentPath: &str) -> ShaderHandle {
+    let key = (vertexPath.to_string(), fragmentPath.to_string());
+    if let Some(shader) = self.shaders.get(&key).and_then(Weak::upgrade) {
+      return shader;
+    }
+
+    let shader = Rc::new(Shader::new(vertexPath, fragmentPath));
+    self.shaders.insert(key, Rc::downgrade(&shader));
+    shader
+  }
+
+  /// Rebuilds every live shader and texture read from `path`
+  pub fn reload(&self, path: &Path) {
+    for shader in self.shaders.values().filter_map(Weak::upgrade).filter(|s| s.watches(path)) {
+      shader.reload();
+    }
+    for texture in self.textures.values().filter_map(Weak::upgrade).filter(|t| Path::new(&t.path) == path) {
+      match unsafe { reloadTexture(texture.id, &texture.path, texture.colourSpace) } {
+        Ok(()) => {
+          texture.bytes.set(unsafe { textureBytes(texture.id) });
+          self.textureErrors.borrow_mut().remove(&texture.path);
+        }
+        Err(e) => {
+          println!("{}", e);
+          self.textureErrors.borrow_mut().insert(texture.path.clone(), e.to_string());
+        }
+      }
+    }
+  }
+
+  /// Failures of live shaders and textures still waiting on a fix, one message each
+  pub fn errors(&self) -> Vec<String> {
+    let mut errors: Vec<String> = self.shaders.values()
+      .filter_map(Weak::upgrade)
+      .filter_map(|s| s.error.borrow().as_ref().map(|e| e.to_string()))
+      .collect();
+    // Shaders sharing a broken include all fail the same way
+    errors.sort();
+    errors.dedup();
+    let textureErrors = self.textureErrors.borrow();
+    let mut paths: Vec<&String> = textureErrors.keys().collect();
+    paths.sort();
+    errors.extend(paths.into_iter().map(|p| textureErrors[p].clone()));
+    errors
+  }
+
+  /// Source files of every live shader and texture, for the file watcher
+  pub fn files(&self) -> Vec<String> {
+    let mut files: Vec<String> = self.textures.values().filter_map(Weak::upgrade).map(|t| t.path.clone()).collect();
+    for shader in self.shaders.values().filter_map(Weak::upgrade) {
+      files.extend(shader.sourcePaths());
+    }
+    files
+  }
+
+  /// Forgets assets nobody holds any more and totals up what's left
+  pub fn report(&mut self) -> MemoryReport {
+    self.textures.retain(|_, t| t.upgrade().is_some());
+    self.models.retain(|_, m| m.upgrade().is_some());
+    self.shaders.retain(|_, s| s.upgrade().is_some());
+
+    let mut report = MemoryReport { shaders: self.shaders.len(), ..MemoryReport::default() };
+    for texture in self.textures.values().filter_map(Weak::upgrade) {
+      report.textures += 1;
+      report.textureBytes += texture.bytes.get();
+    }
+    for model in self.models.values().filter_map(Weak::upgrade) {
+      for mesh in &model.meshes {
+        report.meshes += 1;
+        report.meshBytes += meshBytes(mesh);
+      }
+    }
+    report
+  }
+}
+
+impl fmt::Display for MemoryReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mb = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+    write!(f, "{} textures ({:.1} MB), {} meshes ({:.1} MB), {} shaders",
+      self.textures, mb(self.textureBytes), self.meshes, mb(self.meshBytes), self.shaders)
+  }
+}
+
+fn meshBytes(mesh: &Mesh) -> usize {
+  mesh.vertices.len() * size_of::<Vertex>() + mesh.lods.iter().map(|l| l.count).sum::<usize>() * size_of::<u32>()
+}
+
+/// Estimate from the top level's size, assuming four bytes per texel when uncompressed, plus a
+/// third again for the mip chain
+unsafe fn textureBytes(id: u32) -> usize {
+  let (mut width, mut height, mut compressed, mut compressedSize) = (0, 0, 0, 0);
+  gl::BindTexture(gl::TEXTURE_2D, id);
+  gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_WIDTH, &mut width);
+  gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, &mut height);
+  gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_COMPRESSED, &mut compressed);
+  if compressed != 0 {
+    gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_COMPRESSED_IMAGE_SIZE, &mut compressedSize);
+  }
+  gl::BindTexture(gl::TEXTURE_2D, 0);
+  let bytes = if compressed != 0 { compressedSize as usize } else { (width * height) as usize * 4 };
+  bytes * 4 / 3
+}
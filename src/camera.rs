@@ -10,6 +10,7 @@ const PITCH: f32 = 0.0;
 const SPEED: f32 = 80.0;
 const SENSITIVTY: f32 = 0.1;
 const ZOOM: f32 = 45.0;
+const MIN_ZOOM: f32 = 1.0;
 
 pub enum CameraMovement {
   FORWARD,
@@ -30,7 +31,9 @@ pub struct Camera {
   pub pitch: f32,
   pub movementSpeed: f32,
   pub mouseSensitivity: f32,
+  /// Vertical field of view in degrees, which scrolling narrows down from `maxZoom`
   pub zoom: f32,
+  pub maxZoom: f32,
 }
 
 impl Default for Camera {
@@ -46,6 +49,7 @@ impl Default for Camera {
       movementSpeed: SPEED,
       mouseSensitivity: SENSITIVTY,
       zoom: ZOOM,
+      maxZoom: ZOOM,
     };
     camera.updateCameraVectors();
     camera
@@ -88,19 +92,18 @@ impl Camera {
     self.updateCameraVectors();
   }
 
+  /// Mirrors the view vertically, used with a camera moved below a reflective surface
+  pub fn invertPitch(&mut self) {
+    self.pitch = -self.pitch;
+    self.updateCameraVectors();
+  }
+
   pub fn processMouseScroll(&mut self, yoffset: f32) {
-    if self.zoom >= 1.0 && self.zoom <= 45.0 {
-      self.zoom -= yoffset;
-    }
-    if self.zoom <= 1.0 {
-      self.zoom = 1.0;
-    }
-    if self.zoom >= 45.0 {
-      self.zoom = 45.0;
-    }
+    self.zoom = (self.zoom - yoffset).clamp(MIN_ZOOM, self.maxZoom.max(MIN_ZOOM));
   }
 
-  fn updateCameraVectors(&mut self) {
+  /// Recomputes the basis from `yaw` and `pitch` after setting them directly
+  pub fn updateCameraVectors(&mut self) {
     let front = Vector3 {
       x: self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
       y: self.pitch.to_radians().sin(),
@@ -111,3 +114,19 @@ impl Camera {
     self.up = self.right.cross(self.front).normalize();
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn scrollKeepsConfiguredFov() {
+    let mut camera = Camera { zoom: 70.0, maxZoom: 70.0, ..Camera::default() };
+    camera.processMouseScroll(1.0);
+    assert_eq!(camera.zoom, 69.0);
+    camera.processMouseScroll(-5.0);
+    assert_eq!(camera.zoom, 70.0);
+    camera.processMouseScroll(100.0);
+    assert_eq!(camera.zoom, MIN_ZOOM);
+  }
+}
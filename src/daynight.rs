@@ -0,0 +1,140 @@
+#![allow(non_snake_case)]
+use cgmath::{vec3, InnerSpace};
+
+use crate::types::*;
+
+pub const NOON: f32 = 12.0;
+// Game hours that pass each real second, so a full day takes four minutes
+const DAY_SPEED: f32 = 0.1;
+// Game hours per real second while scrubbing
+const SCRUB_SPEED: f32 = 3.0;
+// Tilts the sun's path towards +z so it never passes straight overhead
+const TILT: f32 = 0.4;
+
+// Keyframes against the sun's height above the horizon (the y of its direction), from deepest
+// night to noon
+const SUN_COLOUR: [(f32, [f32; 3]); 3] = [(-0.05, [0.0, 0.0, 0.0]), (0.05, [1.0, 0.35, 0.1]), (0.3, [1.275, 1.205, 1.12])];
+const MOON_COLOUR: [(f32, [f32; 3]); 2] = [(0.05, [0.0, 0.0, 0.0]), (-0.2, [0.08, 0.1, 0.16])];
+const AMBIENT: [(f32, [f32; 3]); 3] = [(-0.3, [0.25, 0.3, 0.5]), (0.0, [0.55, 0.45, 0.45]), (0.3, [1.0, 1.0, 1.0])];
+// The day values are the linear equivalent of the skybox's sRGB horizon (0.698, 0.8, 0.898)
+const FOG_COLOUR: [(f32, [f32; 3]); 4] = [(-0.3, [0.01, 0.015, 0.03]), (-0.05, [0.05, 0.06, 0.12]), (0.05, [0.6, 0.35, 0.25]), (0.3, [0.454, 0.612, 0.789])];
+const SKY_TINT: [(f32, [f32; 3]); 3] = [(-0.3, [0.03, 0.04, 0.08]), (0.0, [0.5, 0.35, 0.3]), (0.3, [1.0, 1.0, 1.0])];
+
+/// Lighting and colours for one moment of the day
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sky {
+  /// Unit vector towards the sun, or the moon once the sun has set
+  pub lightDirection: Vector3,
+  pub lightColour: Vector3,
+  /// Tints the ambient light, fading to blue moonlight at night
+  pub ambient: Vector3,
+  pub fogColour: Vector3,
+  /// Multiplies the skybox texture
+  pub skyTint: Vector3
+}
+
+/// Clock driving the sun across the sky. Hours run from 0 to 24, sunrise at 6 and sunset at 18
+pub struct TimeOfDay {
+  pub hours: f32,
+  pub paused: bool
+}
+
+impl TimeOfDay {
+  pub fn new(hours: f32) -> TimeOfDay {
+    TimeOfDay { hours: hours.rem_euclid(24.0), paused: false }
+  }
+
+  /// Advances the clock unless paused. `scrub` is -1, 0 or 1 to wind it back or forward, which works
+  /// even while paused
+  pub fn update(&mut self, deltaTime: f32, scrub: f32) {
+    let speed = if self.paused { 0.0 } else { DAY_SPEED } + scrub * SCRUB_SPEED;
+    self.hours = (self.hours + speed * deltaTime).rem_euclid(24.0);
+  }
+
+  /// Unit vector towards the sun, rising in +x and setting in -x
+  pub fn sunDirection(&self) -> Vector3 {
+    let angle = (self.hours - 6.0) / 24.0 * 2.0 * std::f32::consts::PI;
+    vec3(angle.cos(), angle.sin() * TILT.cos(), angle.sin() * TILT.sin())
+  }
+
+  pub fn sky(&self) -> Sky {
+    let sun = self.sunDirection();
+    let height = sun.y;
+    let (lightDirection, lightColour) = if height > 0.0 {
+      (sun, gradient(&SUN_COLOUR, height))
+    } else {
+      (-sun, gradient(&MOON_COLOUR, height))
+    };
+    Sky {
+      lightDirection: lightDirection.normalize(),
+      lightColour,
+      ambient: gradient(&AMBIENT, height),
+      fogColour: gradient(&FOG_COLOUR, height),
+      skyTint: gradient(&SKY_TINT, height)
+    }
+  }
+
+  /// `hh:mm` on a 24 hour clock
+  pub fn clock(&self) -> String {
+    let minutes = (self.hours * 60.0) as u32 % (24 * 60);
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+  }
+}
+
+/// Piecewise linear interpolation between keyframes sorted by position in either direction,
+/// holding the end values outside them
+fn gradient(keys: &[(f32, [f32; 3])], x: f32) -> Vector3 {
+  let colour = |c: [f32; 3]| vec3(c[0], c[1], c[2]);
+  let ascending = keys[0].0 <= keys[keys.len() - 1].0;
+  let before = |key: f32, x: f32| if ascending { x <= key } else { x >= key };
+  if before(keys[0].0, x) {
+    return colour(keys[0].1);
+  }
+  for pair in keys.windows(2) {
+    let ((x0, c0), (x1, c1)) = (pair[0], pair[1]);
+    if before(x1, x) {
+      let t = (x - x0) / (x1 - x0);
+      return colour(c0) + (colour(c1) - colour(c0)) * t;
+    }
+  }
+  colour(keys[keys.len() - 1].1)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sunFollowsTheClock() {
+    assert!(TimeOfDay::new(NOON).sunDirection().y > 0.9);
+    assert!(TimeOfDay::new(0.0).sunDirection().y < -0.9);
+    assert!(TimeOfDay::new(6.0).sunDirection().x > 0.99);
+    assert!(TimeOfDay::new(18.0).sunDirection().x < -0.99);
+
+    let mut time = TimeOfDay::new(23.5);
+    time.update(10.0, 0.0);
+    assert!((time.hours - 0.5).abs() < 1e-4);
+    time.paused = true;
+    time.update(1.0, -1.0);
+    assert!((time.hours - 21.5).abs() < 1e-4);
+    assert_eq!(time.clock(), "21:30");
+  }
+
+  #[test]
+  fn skyBlendsBetweenKeys() {
+    assert_eq!(gradient(&AMBIENT, -1.0), vec3(0.25, 0.3, 0.5));
+    assert_eq!(gradient(&AMBIENT, 1.0), vec3(1.0, 1.0, 1.0));
+    let halfway = gradient(&AMBIENT, 0.15);
+    assert!((halfway.x - 0.775).abs() < 1e-6);
+    // Keys can run downwards, as they do for the moon
+    assert_eq!(gradient(&MOON_COLOUR, 0.5), vec3(0.0, 0.0, 0.0));
+    assert_eq!(gradient(&MOON_COLOUR, -0.5), vec3(0.08, 0.1, 0.16));
+
+    let noon = TimeOfDay::new(NOON).sky();
+    assert_eq!(noon.lightColour, vec3(1.275, 1.205, 1.12));
+    assert_eq!(noon.fogColour, vec3(0.454, 0.612, 0.789));
+    let midnight = TimeOfDay::new(0.0).sky();
+    assert!(midnight.lightDirection.y > 0.9, "the moon is up");
+    assert_eq!(midnight.ambient, vec3(0.25, 0.3, 0.5));
+  }
+}
@@ -0,0 +1,255 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::mem::{size_of, size_of_val};
+use std::os::raw::c_void;
+use std::ptr;
+
+use cgmath::{vec3, vec4, InnerSpace, SquareMatrix};
+
+use crate::assets::{AssetManager, ShaderHandle};
+use crate::types::*;
+
+pub const RED: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+pub const GREEN: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+pub const BLUE: [f32; 4] = [0.3, 0.5, 1.0, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+const CIRCLE_SEGMENTS: usize = 32;
+// Corners of a unit cube ordered so that bit 0 is x, bit 1 is y and bit 2 is z
+const BOX_EDGES: [(usize, usize); 12] = [
+  (0, 1), (2, 3), (4, 5), (6, 7),
+  (0, 2), (1, 3), (4, 6), (5, 7),
+  (0, 4), (1, 5), (2, 6), (3, 7)
+];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DebugVertex {
+  position: [f32; 3],
+  colour: [f32; 4]
+}
+
+/// World-space label, projected to the screen when flushed
+struct Label {
+  position: Vector3,
+  text: String,
+  colour: [f32; 4]
+}
+
+/// Shapes kept around for longer than a frame by `DebugDraw::keep`
+struct Timed {
+  remaining: f32,
+  vertices: Vec<DebugVertex>,
+  labels: Vec<Label>
+}
+
+/// A label in framebuffer pixels from the top left, ready to hand to the debug UI
+pub struct ScreenLabel {
+  pub x: f32,
+  pub y: f32,
+  pub text: String,
+  pub colour: [f32; 4]
+}
+
+/// Batches coloured lines queued from anywhere during a frame and draws them all with one call.
+/// Everything is cleared by `flush` unless it was queued inside `keep`
+pub struct DebugDraw {
+  shader: ShaderHandle,
+  vertices: Vec<DebugVertex>,
+  labels: Vec<Label>,
+  timed: Vec<Timed>,
+  /// Drawn after everything else without depth testing
+  overlay: Vec<DebugVertex>,
+  VAO: u32,
+  VBO: u32
+}
+
+impl DebugDraw {
+  pub fn new(assets: &mut AssetManager) -> DebugDraw {
+    let mut debug = DebugDraw {
+      shader: assets.shader("src/shaders/lineVertex.vs", "src/shaders/lineFragment.fs"),
+      vertices: vec![],
+      labels: vec![],
+      timed: vec![],
+      overlay: vec![],
+      VAO: 0,
+      VBO: 0
+    };
+    unsafe { debug.setup() };
+    debug
+  }
+
+  pub fn line(&mut self, a: Vector3, b: Vector3, colour: [f32; 4]) {
+    self.vertices.push(DebugVertex { position: [a.x, a.y, a.z], colour });
+    self.vertices.push(DebugVertex { position: [b.x, b.y, b.z], colour });
+  }
+
+  /// Small 3D cross marking a point such as a collision contact
+  pub fn point(&mut self, p: Vector3, size: f32, colour: [f32; 4]) {
+    let h = size / 2.0;
+    self.line(p - vec3(h, 0.0, 0.0), p + vec3(h, 0.0, 0.0), colour);
+    self.line(p - vec3(0.0, h, 0.0), p + vec3(0.0, h, 0.0), colour);
+    self.line(p - vec3(0.0, 0.0, h), p + vec3(0.0, 0.0, h), colour);
+  }
+
+  /// Box from `min` to `max` in the space `transform` maps to world space, so it can be oriented
+  pub fn aabb(&mut self, min: Vector3, max: Vector3, transform: &Matrix4, colour: [f32; 4]) {
+    let corners: Vec<Vector3> = (0..8).map(|i| {
+      let corner = vec4(
+        if i & 1 == 0 { min.x } else { max.x },
+        if i & 2 == 0 { min.y } else { max.y },
+        if i & 4 == 0 { min.z } else { max.z },
+        1.0);
+      (transform * corner).truncate()
+    }).collect();
+    self.edges(&corners, colour);
+  }
+
+  /// Three great circles, one around each axis
+  pub fn sphere(&mut self, centre: Vector3, radius: f32, colour: [f32; 4]) {
+    for axis in &[vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)] {
+      self.circle(centre, *axis, radius, colour);
+    }
+  }
+
+  /// Circle around `axis`, which has to be normalised
+  pub fn circle(&mut self, centre: Vector3, axis: Vector3, radius: f32, colour: [f32; 4]) {
+    let helper = if axis.x.abs() < 0.9 { vec3(1.0, 0.0, 0.0) } else { vec3(0.0, 1.0, 0.0) };
+    let u = axis.cross(helper).normalize() * radius;
+    let v = axis.cross(u);
+    let point = |i: usize| {
+      let angle = 2.0 * std::f32::consts::PI * i as f32 / CIRCLE_SEGMENTS as f32;
+      centre + u * angle.cos() + v * angle.sin()
+    };
+    for i in 0..CIRCLE_SEGMENTS {
+      self.line(point(i), point(i + 1), colour);
+    }
+  }
+
+  /// Outline of the volume a camera with this view-projection matrix can see. Nothing draws a
+  /// second camera yet, but it's there for checking culling and shadow volumes
+  #[allow(dead_code)]
+  pub fn frustum(&mut self, viewProjection: &Matrix4, colour: [f32; 4]) {
+    let inverse = match viewProjection.invert() {
+      Some(inverse) => inverse,
+      None => return
+    };
+    let corners: Vec<Vector3> = (0..8).map(|i| {
+      let ndc = vec4(
+        if i & 1 == 0 { -1.0 } else { 1.0 },
+        if i & 2 == 0 { -1.0 } else { 1.0 },
+        if i & 4 == 0 { -1.0 } else { 1.0 },
+        1.0);
+      let world = inverse * ndc;
+      world.truncate() / world.w
+    }).collect();
+    self.edges(&corners, colour);
+  }
+
+  /// X, Y and Z axes of `transform` in red, green and blue
+  pub fn axes(&mut self, transform: &Matrix4, size: f32) {
+    let origin = (transform * vec4(0.0, 0.0, 0.0, 1.0)).truncate();
+    for (axis, colour) in [(vec4(size, 0.0, 0.0, 1.0), RED), (vec4(0.0, size, 0.0, 1.0), GREEN), (vec4(0.0, 0.0, size, 1.0), BLUE)].iter() {
+      self.line(origin, (transform * axis).truncate(), *colour);
+    }
+  }
+
+  pub fn text(&mut self, position: Vector3, text: &str, colour: [f32; 4]) {
+    self.labels.push(Label { position, text: text.into(), colour });
+  }
+
+  /// Keeps whatever `draw` queues on screen for `seconds` rather than a single frame
+  pub fn keep<F: FnOnce(&mut DebugDraw)>(&mut self, seconds: f32, draw: F) {
+    let (vertexStart, labelStart) = (self.vertices.len(), self.labels.len());
+    draw(self);
+    let vertices = self.vertices.split_off(vertexStart);
+    let labels = self.labels.split_off(labelStart);
+    self.timed.push(Timed { remaining: seconds, vertices, labels });
+  }
+
+  /// Draws whatever `draw` queues over the scene rather than hidden behind it, e.g. for gizmos
+  pub fn onTop<F: FnOnce(&mut DebugDraw)>(&mut self, draw: F) {
+    let start = self.vertices.len();
+    draw(self);
+    let vertices = self.vertices.split_off(start);
+    self.overlay.extend(vertices);
+  }
+
+  /// Draws every queued line, depth tested against the bound framebuffer, with the camera in the
+  /// frame uniforms. Returns the labels in front of the camera placed on a `screen` sized
+  /// framebuffer, then clears everything that was only meant to last this frame
+  pub unsafe fn flush(&mut self, deltaTime: f32, viewProjection: &Matrix4, screen: (i32, i32)) -> Vec<ScreenLabel> {
+    for timed in &self.timed {
+      self.vertices.extend_from_slice(&timed.vertices);
+    }
+
+    self.drawLines(&self.vertices);
+    gl::Disable(gl::DEPTH_TEST);
+    self.drawLines(&self.overlay);
+    gl::Enable(gl::DEPTH_TEST);
+
+    let (width, height) = (screen.0 as f32, screen.1 as f32);
+    let labels = self.labels.iter().chain(self.timed.iter().flat_map(|t| t.labels.iter()))
+      .filter_map(|label| {
+        let clip = viewProjection * label.position.extend(1.0);
+        if clip.w <= 0.0 {
+          return None;
+        }
+        let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+        Some(ScreenLabel { x: (x + 1.0) / 2.0 * width, y: (1.0 - y) / 2.0 * height, text: label.text.clone(), colour: label.colour })
+      })
+      .collect();
+
+    self.vertices.clear();
+    self.overlay.clear();
+    self.labels.clear();
+    for timed in self.timed.iter_mut() {
+      timed.remaining -= deltaTime;
+    }
+    self.timed.retain(|t| t.remaining > 0.0);
+    labels
+  }
+
+  unsafe fn drawLines(&self, vertices: &[DebugVertex]) {
+    if vertices.is_empty() {
+      return;
+    }
+    self.shader.useProgram();
+    gl::BindVertexArray(self.VAO);
+    gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
+    let size = size_of_val(vertices) as isize;
+    gl::BufferData(gl::ARRAY_BUFFER, size, vertices.as_ptr() as *const c_void, gl::STREAM_DRAW);
+    gl::DrawArrays(gl::LINES, 0, vertices.len() as i32);
+    gl::BindVertexArray(0);
+  }
+
+  fn edges(&mut self, corners: &[Vector3], colour: [f32; 4]) {
+    for &(a, b) in BOX_EDGES.iter() {
+      self.line(corners[a], corners[b], colour);
+    }
+  }
+
+  unsafe fn setup(&mut self) {
+    gl::GenVertexArrays(1, &mut self.VAO);
+    gl::GenBuffers(1, &mut self.VBO);
+    gl::BindVertexArray(self.VAO);
+    gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
+    let stride = size_of::<DebugVertex>() as i32;
+    gl::EnableVertexAttribArray(0);
+    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+    gl::EnableVertexAttribArray(1);
+    gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, (3 * size_of::<f32>()) as *const c_void);
+    gl::BindVertexArray(0);
+  }
+}
+
+impl Drop for DebugDraw {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteBuffers(1, &self.VBO);
+      gl::DeleteVertexArrays(1, &self.VAO);
+    }
+  }
+}
@@ -0,0 +1,396 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::os::raw::c_void;
+
+use crate::assets::AssetManager;
+use crate::utils::font8x8::{glyphIndex, GLYPHS};
+use crate::utils::quadbatch::{pushQuad, QuadBatch, UiVertex};
+use crate::types::*;
+
+const GLYPH_SIZE: f32 = 8.0;
+const ATLAS_COLUMNS: usize = 16;
+// Glyphs plus one solid cell used for untextured quads
+const ATLAS_CELLS: usize = GLYPHS.len() + 1;
+const ATLAS_WIDTH: usize = ATLAS_COLUMNS * 8;
+const ATLAS_HEIGHT: usize = ATLAS_CELLS.div_ceil(ATLAS_COLUMNS) * 8;
+const SOLID_CELL: usize = GLYPHS.len();
+
+// Layout in glyph cells, scaled up with the framebuffer height
+const PANEL_COLUMNS: f32 = 46.0;
+const LABEL_COLUMNS: f32 = 14.0;
+const ROW_HEIGHT: f32 = 1.5;
+const PADDING: f32 = 0.5;
+const GRAPH_ROWS: f32 = 3.0;
+/// Framebuffer height at which the font is drawn at its native 8 pixels
+const BASE_HEIGHT: f32 = 720.0;
+
+const PANEL: [f32; 4] = [0.08, 0.08, 0.1, 0.85];
+const TITLE: [f32; 4] = [0.2, 0.3, 0.5, 1.0];
+const FIELD: [f32; 4] = [0.2, 0.2, 0.25, 1.0];
+const FIELD_HOT: [f32; 4] = [0.3, 0.3, 0.38, 1.0];
+const FIELD_ACTIVE: [f32; 4] = [0.3, 0.45, 0.7, 1.0];
+const TEXT: [f32; 4] = [0.92, 0.92, 0.92, 1.0];
+const ERROR_PANEL: [f32; 4] = [0.45, 0.05, 0.05, 0.9];
+// Rows of errors shown before the rest are cut off
+const ERROR_ROWS: usize = 24;
+
+/// Mouse state for one frame, in framebuffer pixels from the top left
+#[derive(Clone, Copy, Default)]
+pub struct UiInput {
+  pub mouse: (f32, f32),
+  pub down: bool
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+  x: f32,
+  y: f32,
+  w: f32,
+  h: f32
+}
+
+impl Rect {
+  fn contains(&self, (x, y): (f32, f32)) -> bool {
+    x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+  }
+}
+
+/// Immediate mode debug panel drawn over the finished frame. Widgets are declared every frame
+/// between `begin` and `end`, each returning whether the user changed its value, and are told
+/// apart by their label so labels within a panel must be unique
+pub struct DebugUi {
+  pub visible: bool,
+  batch: QuadBatch,
+  fontTexture: u32,
+  vertices: Vec<UiVertex>,
+  input: UiInput,
+  previous: UiInput,
+  /// Widget being dragged, kept until the button is released even if the mouse leaves it
+  active: Option<u64>,
+  scale: f32,
+  screen: (f32, f32),
+  panel: Rect,
+  panelStart: usize,
+  cursorY: f32,
+  hovered: bool
+}
+
+impl DebugUi {
+  pub fn new(assets: &mut AssetManager) -> DebugUi {
+    let mut ui = DebugUi {
+      visible: true,
+      batch: QuadBatch::new(assets),
+      fontTexture: 0,
+      vertices: vec![],
+      input: UiInput::default(),
+      previous: UiInput::default(),
+      active: None,
+      scale: 1.0,
+      screen: (1.0, 1.0),
+      panel: Rect { x: 0.0, y: 0.0, w: 0.0, h: 0.0 },
+      panelStart: 0,
+      cursorY: 0.0,
+      hovered: false
+    };
+    unsafe { ui.setup() };
+    ui
+  }
+
+  /// True while the pointer is over the panel or dragging one of its widgets, so the camera
+  /// should ignore the mouse
+  pub fn wantsMouse(&self) -> bool {
+    self.visible && (self.hovered || self.active.is_some())
+  }
+
+  pub fn begin(&mut self, title: &str, input: UiInput, screen: (i32, i32)) {
+    self.previous = self.input;
+    self.input = input;
+    if !input.down {
+      self.active = None;
+    }
+    self.vertices.clear();
+    self.screen = (screen.0 as f32, screen.1 as f32);
+    self.scale = (self.screen.1 / BASE_HEIGHT).floor().max(1.0);
+    if !self.visible {
+      return;
+    }
+
+    let cell = self.cell();
+    self.panel = Rect { x: cell, y: cell, w: PANEL_COLUMNS * cell, h: 0.0 };
+    self.panelStart = self.vertices.len();
+    self.cursorY = self.panel.y;
+
+    let bar = Rect { x: self.panel.x, y: self.cursorY, w: self.panel.w, h: ROW_HEIGHT * cell };
+    self.quad(&bar, TITLE);
+    self.text(self.panel.x + PADDING * cell, self.cursorY, title, TEXT);
+    self.cursorY += (ROW_HEIGHT + PADDING) * cell;
+  }
+
+  /// Closes the panel, putting its background behind everything drawn since `begin`
+  pub fn end(&mut self) {
+    if !self.visible {
+      self.hovered = false;
+      return;
+    }
+    self.panel.h = self.cursorY - self.panel.y + PADDING * self.cell();
+    let panel = self.panel;
+    let contents = self.vertices.split_off(self.panelStart);
+    self.quad(&panel, PANEL);
+    self.vertices.extend(contents);
+    self.hovered = panel.contains(self.input.mouse);
+  }
+
+  pub fn label(&mut self, text: &str) {
+    if !self.visible {
+      return;
+    }
+    let x = self.panel.x + PADDING * self.cell();
+    self.text(x, self.cursorY, text, TEXT);
+    self.cursorY += ROW_HEIGHT * self.cell();
+  }
+
+  /// Blank row to separate groups of widgets
+  pub fn spacing(&mut self) {
+    if self.visible {
+      self.cursorY += ROW_HEIGHT * self.cell() / 2.0;
+    }
+  }
+
+  /// Bar chart across the panel, one bar per value, scaled so `max` fills its height
+  pub fn graph<I: ExactSizeIterator<Item = f32>>(&mut self, values: I, max: f32) {
+    if !self.visible {
+      return;
+    }
+    let cell = self.cell();
+    let rect = Rect { x: self.panel.x + PADDING * cell, y: self.cursorY, w: self.panel.w - 2.0 * PADDING * cell, h: GRAPH_ROWS * ROW_HEIGHT * cell };
+    self.quad(&rect, FIELD);
+    let barWidth = rect.w / values.len().max(1) as f32;
+    for (i, value) in values.enumerate() {
+      let h = (value / max).clamp(0.0, 1.0) * rect.h;
+      self.quad(&Rect { x: rect.x + i as f32 * barWidth, y: rect.y + rect.h - h, w: barWidth, h }, FIELD_ACTIVE);
+    }
+    self.cursorY += rect.h + PADDING * cell;
+  }
+
+  pub fn button(&mut self, label: &str) -> bool {
+    if !self.visible {
+      return false;
+    }
+    let rect = self.fieldRect(0, 1);
+    let id = widgetId(label, 0);
+    let clicked = self.pressed() && rect.contains(self.input.mouse);
+    let colour = self.fieldColour(&rect, id);
+    self.quad(&rect, colour);
+    self.text(rect.x + PADDING * self.cell(), rect.y, label, TEXT);
+    self.cursorY += ROW_HEIGHT * self.cell();
+    clicked
+  }
+
+  pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+    if !self.visible {
+      return false;
+    }
+    self.rowLabel(label);
+    let cell = self.cell();
+    let rect = Rect { x: self.fieldX(), y: self.cursorY, w: ROW_HEIGHT * cell, h: ROW_HEIGHT * cell - 2.0 * self.scale };
+    let clicked = self.pressed() && rect.contains(self.input.mouse);
+    if clicked {
+      *value = !*value;
+    }
+    let colour = self.fieldColour(&rect, widgetId(label, 0));
+    self.quad(&rect, colour);
+    if *value {
+      let inset = cell / 2.0;
+      self.quad(&Rect { x: rect.x + inset, y: rect.y + inset, w: rect.w - 2.0 * inset, h: rect.h - 2.0 * inset }, TEXT);
+    }
+    self.cursorY += ROW_HEIGHT * cell;
+    clicked
+  }
+
+  /// Number field that changes by `speed` per pixel dragged horizontally
+  pub fn dragFloat(&mut self, label: &str, value: &mut f32, speed: f32) -> bool {
+    if !self.visible {
+      return false;
+    }
+    self.rowLabel(label);
+    let changed = self.dragField(label, 0, 1, value, speed);
+    self.cursorY += ROW_HEIGHT * self.cell();
+    changed
+  }
+
+  pub fn dragVector3(&mut self, label: &str, value: &mut Vector3, speed: f32) -> bool {
+    if !self.visible {
+      return false;
+    }
+    self.rowLabel(label);
+    let changed = self.dragField(label, 0, 3, &mut value.x, speed)
+      | self.dragField(label, 1, 3, &mut value.y, speed)
+      | self.dragField(label, 2, 3, &mut value.z, speed);
+    self.cursorY += ROW_HEIGHT * self.cell();
+    changed
+  }
+
+  /// Text centred on a point in framebuffer pixels, shown even while the panel is hidden
+  pub fn screenText(&mut self, x: f32, y: f32, text: &str, colour: [f32; 4]) {
+    let cell = self.cell();
+    self.text(x - text.chars().count() as f32 * cell / 2.0, y - ROW_HEIGHT * cell / 2.0, text, colour);
+  }
+
+  /// Red panel in the top right listing asset errors a line at a time, shown even while the
+  /// debug panel is hidden. Nothing is drawn once the list is empty
+  pub fn errorPanel(&mut self, errors: &[String]) {
+    if errors.is_empty() {
+      return;
+    }
+    let cell = self.cell();
+    let columns = (self.screen.0 / cell - 2.0 * (1.0 + PADDING)).max(1.0) as usize;
+    let mut rows: Vec<String> = errors.iter()
+      .flat_map(|e| e.lines())
+      .map(|line| line.chars().take(columns).collect())
+      .collect();
+    if rows.len() > ERROR_ROWS {
+      let hidden = rows.len() - ERROR_ROWS + 1;
+      rows.truncate(ERROR_ROWS - 1);
+      rows.push(format!("... {} more lines", hidden));
+    }
+
+    let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0) as f32 * cell + 2.0 * PADDING * cell;
+    // Below the frame rate readout
+    let panel = Rect { x: self.screen.0 - width - cell, y: 6.0 * cell, w: width, h: (rows.len() as f32 * ROW_HEIGHT + 2.0 * PADDING) * cell };
+    self.quad(&panel, ERROR_PANEL);
+    for (i, row) in rows.iter().enumerate() {
+      self.text(panel.x + PADDING * cell, panel.y + (PADDING + i as f32 * ROW_HEIGHT) * cell, row, TEXT);
+    }
+  }
+
+  /// Draws everything declared since `begin` over whatever framebuffer is bound
+  pub unsafe fn render(&self) {
+    self.batch.render(&self.vertices, self.fontTexture, self.screen);
+  }
+
+  fn cell(&self) -> f32 {
+    GLYPH_SIZE * self.scale
+  }
+
+  fn pressed(&self) -> bool {
+    self.input.down && !self.previous.down
+  }
+
+  fn fieldX(&self) -> f32 {
+    self.panel.x + (PADDING + LABEL_COLUMNS) * self.cell()
+  }
+
+  /// Slot `index` of `count` equal fields filling the row after the label
+  fn fieldRect(&self, index: usize, count: usize) -> Rect {
+    let cell = self.cell();
+    let (start, end) = (self.fieldX(), self.panel.x + self.panel.w - PADDING * cell);
+    let gap = PADDING * cell;
+    let w = (end - start - gap * (count - 1) as f32) / count as f32;
+    Rect { x: start + index as f32 * (w + gap), y: self.cursorY, w, h: ROW_HEIGHT * cell - 2.0 * self.scale }
+  }
+
+  fn fieldColour(&self, rect: &Rect, id: u64) -> [f32; 4] {
+    if self.active == Some(id) {
+      FIELD_ACTIVE
+    } else if rect.contains(self.input.mouse) {
+      FIELD_HOT
+    } else {
+      FIELD
+    }
+  }
+
+  fn rowLabel(&mut self, label: &str) {
+    let x = self.panel.x + PADDING * self.cell();
+    self.text(x, self.cursorY, label, TEXT);
+  }
+
+  fn dragField(&mut self, label: &str, index: usize, count: usize, value: &mut f32, speed: f32) -> bool {
+    let rect = self.fieldRect(index, count);
+    let id = widgetId(label, index);
+    if self.pressed() && rect.contains(self.input.mouse) {
+      self.active = Some(id);
+    }
+
+    let mut changed = false;
+    if self.active == Some(id) {
+      let dx = self.input.mouse.0 - self.previous.mouse.0;
+      if dx != 0.0 {
+        *value += dx / self.scale * speed;
+        changed = true;
+      }
+    }
+    let colour = self.fieldColour(&rect, id);
+    self.quad(&rect, colour);
+    let text = format!("{:.3}", value);
+    self.text(rect.x + PADDING * self.cell(), rect.y, &text, TEXT);
+    changed
+  }
+
+  /// One row of text with its top left at `(x, y)`, vertically centred in the row
+  fn text(&mut self, x: f32, y: f32, text: &str, colour: [f32; 4]) {
+    let cell = self.cell();
+    let y = y + (ROW_HEIGHT - 1.0) * cell / 2.0;
+    for (i, c) in text.chars().enumerate() {
+      let rect = Rect { x: x + i as f32 * cell, y, w: cell, h: cell };
+      let (u, v) = cellOrigin(glyphIndex(c));
+      let (du, dv) = (8.0 / ATLAS_WIDTH as f32, 8.0 / ATLAS_HEIGHT as f32);
+      self.pushQuad(&rect, [u, v, u + du, v + dv], colour);
+    }
+  }
+
+  fn quad(&mut self, rect: &Rect, colour: [f32; 4]) {
+    // Sample the middle of the solid cell so filtering never reaches a neighbour
+    let (u, v) = cellOrigin(SOLID_CELL);
+    let (u, v) = (u + 4.0 / ATLAS_WIDTH as f32, v + 4.0 / ATLAS_HEIGHT as f32);
+    self.pushQuad(rect, [u, v, u, v], colour);
+  }
+
+  fn pushQuad(&mut self, rect: &Rect, uv: [f32; 4], colour: [f32; 4]) {
+    pushQuad(&mut self.vertices, (rect.x, rect.y, rect.x + rect.w, rect.y + rect.h), uv, colour);
+  }
+
+  unsafe fn setup(&mut self) {
+    // Single channel atlas, row 0 of the texture holding the top row of the first glyphs
+    let mut atlas = vec![0u8; ATLAS_WIDTH * ATLAS_HEIGHT];
+    let solid = [0xFF; 8];
+    for (cell, glyph) in GLYPHS.iter().chain(Some(&solid)).enumerate() {
+      let (cx, cy) = (cell % ATLAS_COLUMNS * 8, cell / ATLAS_COLUMNS * 8);
+      for (row, bits) in glyph.iter().enumerate() {
+        for column in 0..8 {
+          if bits >> column & 1 == 1 {
+            atlas[(cy + row) * ATLAS_WIDTH + cx + column] = 255;
+          }
+        }
+      }
+    }
+    gl::GenTextures(1, &mut self.fontTexture);
+    gl::BindTexture(gl::TEXTURE_2D, self.fontTexture);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, ATLAS_WIDTH as i32, ATLAS_HEIGHT as i32, 0, gl::RED, gl::UNSIGNED_BYTE, atlas.as_ptr() as *const c_void);
+    gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+  }
+}
+
+impl Drop for DebugUi {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteTextures(1, &self.fontTexture) }
+  }
+}
+
+fn cellOrigin(cell: usize) -> (f32, f32) {
+  ((cell % ATLAS_COLUMNS * 8) as f32 / ATLAS_WIDTH as f32, (cell / ATLAS_COLUMNS * 8) as f32 / ATLAS_HEIGHT as f32)
+}
+
+fn widgetId(label: &str, index: usize) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  (label, index).hash(&mut hasher);
+  hasher.finish()
+}
@@ -0,0 +1,185 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ffi::CStr;
+
+use cgmath::{vec2, SquareMatrix};
+
+use crate::camera::Camera;
+use crate::entity::Entity;
+use crate::light::Light;
+use crate::mesh::Mesh;
+use crate::profiler::Profiler;
+use crate::skybox::Fog;
+use crate::terrain::Terrain;
+use crate::utils::maths::genSphere;
+use crate::utils::shader::Shader;
+use crate::utils::target::{RenderTarget, DepthAttachment, RG8, RGBA8, RGBA16F};
+use crate::water::Water;
+use crate::types::*;
+use crate::c_str;
+
+const G_ALBEDO: usize = 0;
+const G_NORMAL: usize = 1;
+const G_SPECULAR: usize = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RenderPath {
+  Forward,
+  Deferred
+}
+
+/// G-buffer pass followed by a fullscreen pass for unbounded lights and one additive
+/// sphere per bounded light, so shading cost scales with the pixels each light covers
+pub struct DeferredRenderer {
+  gBuffer: RenderTarget,
+  geometryShader: Shader,
+  terrainShader: Shader,
+  ambientShader: Shader,
+  lightShader: Shader,
+  sphere: Mesh,
+  VAO: u32
+}
+
+impl DeferredRenderer {
+  pub fn new(width: i32, height: i32, fog: &Fog, water: &Water) -> DeferredRenderer {
+    let (sphereVertices, sphereIndices) = genSphere(16, 24);
+    let mut VAO = 0;
+    unsafe { gl::GenVertexArrays(1, &mut VAO) };
+    let renderer = DeferredRenderer {
+      gBuffer: RenderTarget::new(width, height, &[RGBA8, RGBA16F, RG8], DepthAttachment::Texture),
+      geometryShader: Shader::new("src/shaders/gbufferVertex.vs", "src/shaders/gbufferFragment.fs"),
+      terrainShader: Shader::new("src/shaders/gbufferVertex.vs", "src/shaders/gbufferTerrFragment.fs"),
+      ambientShader: Shader::new("src/shaders/fullscreenVertex.vs", "src/shaders/deferredFragment.fs"),
+      lightShader: Shader::new("src/shaders/lightVolumeVertex.vs", "src/shaders/lightVolumeFragment.fs"),
+      sphere: Mesh::new(sphereVertices, sphereIndices, vec![]),
+      VAO
+    };
+
+    unsafe {
+      renderer.geometryShader.useProgram();
+      renderer.geometryShader.setFloat(c_str!("tiling"), 1.0);
+      renderer.terrainShader.useProgram();
+      renderer.terrainShader.setFloat(c_str!("tiling"), 100.0);
+
+      for shader in &[&renderer.ambientShader, &renderer.lightShader] {
+        shader.loadFog(&fog.colour, fog.density, fog.gradient);
+        shader.loadWater(water.height, &water.tint);
+        shader.setInt(c_str!("gAlbedo"), 0);
+        shader.setInt(c_str!("gNormal"), 1);
+        shader.setInt(c_str!("gSpecular"), 2);
+        shader.setInt(c_str!("gDepth"), 3);
+      }
+      renderer.lightShader.setVector2(c_str!("screenSize"), &vec2(width as f32, height as f32));
+    }
+
+    renderer
+  }
+
+  pub fn resize(&mut self, width: i32, height: i32) {
+    self.gBuffer.resize(width, height);
+    unsafe {
+      self.lightShader.useProgram();
+      self.lightShader.setVector2(c_str!("screenSize"), &vec2(width as f32, height as f32));
+    }
+  }
+
+  /// Follows the fog colour as it changes through the day
+  pub fn setFog(&self, fog: &Fog) {
+    for shader in &[&self.ambientShader, &self.lightShader] {
+      shader.loadFog(&fog.colour, fog.density, fog.gradient);
+    }
+  }
+
+  pub fn shaders(&self) -> Vec<&Shader> {
+    vec![&self.geometryShader, &self.terrainShader, &self.ambientShader, &self.lightShader]
+  }
+
+  pub unsafe fn geometryPass(&self, terrain: &Terrain, entities: &[&Entity], camera: &Camera, profiler: &mut Profiler) {
+    self.gBuffer.bind();
+    gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+    profiler.begin("terrain");
+    terrain.entity.draw(&self.terrainShader, camera);
+    profiler.end();
+    profiler.begin("entities");
+    for entity in entities {
+      entity.draw(&self.geometryShader, camera);
+    }
+    profiler.end();
+  }
+
+  /// Depth of the last geometry pass, e.g. for ambient occlusion before lighting
+  pub fn depthTexture(&self) -> u32 {
+    self.gBuffer.depthTexture
+  }
+
+  /// Shades the G-buffer into `target` and copies its depth across so forward passes
+  /// (sky, water, debug lines) can still be depth tested against the scene. The unbounded
+  /// lights are read from the frame uniforms, `lights` is only used for the light volumes.
+  /// `ambientOcclusion` is a texture darkening the ambient term, if there is one
+  pub unsafe fn lightingPass(&self, target: &RenderTarget, lights: &[Light], view: &Matrix4, projection: &Matrix4, ambientOcclusion: Option<u32>) {
+    let invViewProjection = (projection * view).invert().unwrap();
+
+    target.bind();
+    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    gl::Disable(gl::DEPTH_TEST);
+    gl::DepthMask(gl::FALSE);
+    for (i, texture) in [G_ALBEDO, G_NORMAL, G_SPECULAR].iter().enumerate() {
+      gl::ActiveTexture(gl::TEXTURE0 + i as u32);
+      gl::BindTexture(gl::TEXTURE_2D, self.gBuffer.colourTextures[*texture]);
+    }
+    gl::ActiveTexture(gl::TEXTURE3);
+    gl::BindTexture(gl::TEXTURE_2D, self.gBuffer.depthTexture);
+    gl::ActiveTexture(gl::TEXTURE4);
+    gl::BindTexture(gl::TEXTURE_2D, ambientOcclusion.unwrap_or(0));
+
+    // Unbounded lights, fog and water tint in a single fullscreen pass
+    self.ambientShader.useProgram();
+    self.ambientShader.setMat4(c_str!("invViewProjection"), &invViewProjection);
+    self.ambientShader.setInt(c_str!("ambientOcclusion"), 4);
+    self.ambientShader.setBool(c_str!("useAmbientOcclusion"), ambientOcclusion.is_some());
+    gl::BindVertexArray(self.VAO);
+    gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    gl::BindVertexArray(0);
+
+    // Light volumes, back faces only so they still rasterise with the camera inside them
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::ONE, gl::ONE);
+    gl::Enable(gl::CULL_FACE);
+    gl::CullFace(gl::FRONT);
+    self.lightShader.useProgram();
+    self.lightShader.setMat4(c_str!("invViewProjection"), &invViewProjection);
+    for light in lights.iter().filter(|l| l.radius().is_some()) {
+      let model = Matrix4::from_translation(light.position) * Matrix4::from_scale(light.radius().unwrap());
+      self.lightShader.setMat4(c_str!("model"), &model);
+      self.lightShader.setVector3(c_str!("lightPosition"), &light.position);
+      self.lightShader.setVector3(c_str!("lightColour"), &light.colour);
+      self.lightShader.setVector3(c_str!("attenuation"), &light.attenuation);
+      self.sphere.draw(&self.lightShader);
+    }
+    gl::CullFace(gl::BACK);
+    gl::Disable(gl::CULL_FACE);
+    gl::Disable(gl::BLEND);
+
+    for i in 0..5 {
+      gl::ActiveTexture(gl::TEXTURE0 + i);
+      gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::DepthMask(gl::TRUE);
+    gl::Enable(gl::DEPTH_TEST);
+
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.gBuffer.FBO);
+    gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.FBO);
+    gl::BlitFramebuffer(0, 0, self.gBuffer.width, self.gBuffer.height, 0, 0, target.width, target.height, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+    target.bind();
+  }
+}
+
+impl Drop for DeferredRenderer {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteVertexArrays(1, &self.VAO) }
+  }
+}
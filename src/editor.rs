@@ -0,0 +1,511 @@
+#![allow(non_snake_case)]
+extern crate glfw;
+use self::glfw::{Action, Key, MouseButtonLeft, Window};
+
+use cgmath::{vec3, InnerSpace, MetricSpace, Rad};
+
+use crate::camera::Camera;
+use crate::debugdraw::{self, DebugDraw};
+use crate::entity::{Entity, Transform};
+use crate::mesh::Line;
+use crate::terrain::{Terrain, DEADZONE, BOUND_MAX};
+use crate::types::*;
+
+// Gizmo length as a fraction of its distance from the camera, so it keeps its size on screen
+const GIZMO_SCALE: f32 = 0.15;
+// How close the cursor's ray has to pass a handle to grab it, relative to the gizmo length
+const PICK_TOLERANCE: f32 = 0.06;
+// Plane handles are squares starting this far from the pivot, relative to the gizmo length
+const PLANE_START: f32 = 0.25;
+const PLANE_END: f32 = 0.5;
+const TIP_SIZE: f32 = 0.08;
+const MAX_HISTORY: usize = 100;
+// Cosine of the furthest the cursor's ray can turn between press and release for it to count as a click
+const CLICK_ALIGNMENT: f32 = 0.9999;
+const AXIS_COLOURS: [[f32; 4]; 3] = [debugdraw::RED, debugdraw::GREEN, debugdraw::BLUE];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoMode {
+  Translate,
+  Rotate,
+  Scale
+}
+
+/// Part of the gizmo under the cursor, indexed by world axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Handle {
+  Axis(usize),
+  /// The plane the other two axes span
+  Plane(usize),
+  Ring(usize)
+}
+
+#[derive(Clone, Copy)]
+struct Drag {
+  mode: GizmoMode,
+  handle: Handle,
+  entity: usize,
+  start: Transform,
+  pivot: Vector3,
+  size: f32,
+  /// Where the cursor first hit the handle's line or plane, as a distance along the axis or a point
+  along: f32,
+  hit: Vector3
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Edit {
+  entity: usize,
+  before: Transform,
+  after: Transform
+}
+
+/// Finished edits that can be stepped back through and forward again. Recording a new edit
+/// forgets anything that was undone
+#[derive(Default)]
+pub struct History {
+  undone: Vec<Edit>,
+  done: Vec<Edit>
+}
+
+impl History {
+  pub fn record(&mut self, entity: usize, before: Transform, after: Transform) {
+    if before == after {
+      return;
+    }
+    self.done.push(Edit { entity, before, after });
+    if self.done.len() > MAX_HISTORY {
+      self.done.remove(0);
+    }
+    self.undone.clear();
+  }
+
+  /// The entity to change and the transform it had before the last edit
+  pub fn undo(&mut self) -> Option<(usize, Transform)> {
+    let edit = self.done.pop()?;
+    self.undone.push(edit);
+    Some((edit.entity, edit.before))
+  }
+
+  pub fn redo(&mut self) -> Option<(usize, Transform)> {
+    let edit = self.undone.pop()?;
+    self.done.push(edit);
+    Some((edit.entity, edit.after))
+  }
+}
+
+/// Editor mode (Tab): left clicking selects an entity and drags the gizmo around it to move, rotate
+/// or scale it along one axis or plane at a time
+pub struct Editor {
+  pub enabled: bool,
+  pub mode: GizmoMode,
+  /// Keeps a translated entity on the ground
+  pub snapToTerrain: bool,
+  pub selected: Option<usize>,
+  pub history: History,
+  drag: Option<Drag>,
+  hovered: Option<Handle>,
+  mouseHeld: bool,
+  /// Cursor direction when the button went down away from the gizmo
+  clickRay: Option<Vector3>,
+  /// Entity being pushed by right clicks outside the editor, and its transform before the push
+  pushStart: Option<(usize, Transform)>,
+  keysHeld: Vec<Key>
+}
+
+impl Editor {
+  pub fn new() -> Editor {
+    Editor {
+      enabled: false,
+      mode: GizmoMode::Translate,
+      snapToTerrain: true,
+      selected: None,
+      history: History::default(),
+      drag: None,
+      hovered: None,
+      mouseHeld: false,
+      clickRay: None,
+      pushStart: None,
+      keysHeld: vec![]
+    }
+  }
+
+  /// Drops the selection and history, which refer to entities by index, when the world is replaced
+  pub fn reset(&mut self) {
+    self.selected = None;
+    self.history = History::default();
+    self.drag = None;
+    self.hovered = None;
+    self.clickRay = None;
+    self.pushStart = None;
+  }
+
+  /// Turns each right click push into one undoable edit. Call it once a frame before the push is
+  /// applied, with whether there is one this frame and a lookup of entity transforms
+  pub fn trackPush(&mut self, pushing: bool, active: usize, transformOf: &dyn Fn(usize) -> Option<Transform>) {
+    match self.pushStart {
+      None if pushing => self.pushStart = transformOf(active).map(|before| (active, before)),
+      Some((entity, before)) if !pushing => {
+        self.pushStart = None;
+        if let Some(after) = transformOf(entity) {
+          self.history.record(entity, before, after);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// True while a gizmo handle is under the cursor or being dragged, so the camera should ignore the mouse
+  pub fn wantsMouse(&self) -> bool {
+    self.enabled && (self.hovered.is_some() || self.drag.is_some())
+  }
+
+  /// Handles the editor's keys and the left mouse button. `ray` runs from the camera through the
+  /// cursor, and `mouseFree` is false while something else such as the debug UI owns the mouse
+  pub fn update(&mut self, window: &Window, ray: &Line, entities: &mut [&mut Entity], terrain: &Terrain, camera: &Camera, mouseFree: bool) {
+    if self.keyPressed(window, Key::Tab) {
+      self.enabled = !self.enabled;
+      self.drag = None;
+      self.hovered = None;
+      self.clickRay = None;
+    }
+    if !self.enabled {
+      return;
+    }
+
+    if self.keyPressed(window, Key::Num1) { self.mode = GizmoMode::Translate }
+    if self.keyPressed(window, Key::Num2) { self.mode = GizmoMode::Rotate }
+    if self.keyPressed(window, Key::Num3) { self.mode = GizmoMode::Scale }
+    if self.keyPressed(window, Key::T) { self.snapToTerrain = !self.snapToTerrain }
+    let control = window.get_key(Key::LeftControl) == Action::Press || window.get_key(Key::RightControl) == Action::Press;
+    let shift = window.get_key(Key::LeftShift) == Action::Press || window.get_key(Key::RightShift) == Action::Press;
+    let (undo, redo) = (self.keyPressed(window, Key::Z), self.keyPressed(window, Key::Y));
+    if control && self.drag.is_none() {
+      let step = if undo && !shift { self.history.undo() } else if redo || (undo && shift) { self.history.redo() } else { None };
+      if let Some((index, transform)) = step {
+        if let Some(entity) = entities.get_mut(index) {
+          entity.setTransform(transform);
+          self.selected = Some(index);
+        }
+      }
+    }
+
+    let down = window.get_mouse_button(MouseButtonLeft) == Action::Press;
+    let pressed = down && !self.mouseHeld;
+    self.mouseHeld = down;
+    // Selection waits for the button to come up, so dragging to turn the camera doesn't change it
+    if !down {
+      if let Some(direction) = self.clickRay.take() {
+        if direction.dot(ray.dir) > CLICK_ALIGNMENT {
+          self.selected = pick(ray, entities);
+        }
+      }
+    }
+
+    if let Some(drag) = self.drag {
+      let entity = &mut entities[drag.entity];
+      if down {
+        if let Some(transform) = self.dragged(&drag, ray, &|x, z| terrain.getHeight(x, z)) {
+          entity.setTransform(transform);
+        }
+      } else {
+        self.history.record(drag.entity, drag.start, entity.transform());
+        self.drag = None;
+      }
+      return;
+    }
+
+    let selected = self.selected.filter(|&i| i < entities.len());
+    self.hovered = match selected {
+      Some(i) if mouseFree => {
+        let pivot = position(entities[i]);
+        self.handleUnder(ray, pivot, gizmoSize(pivot, camera))
+      }
+      _ => None
+    };
+    if !pressed || !mouseFree {
+      return;
+    }
+
+    match (self.hovered, selected) {
+      (Some(handle), Some(i)) => {
+        let pivot = position(entities[i]);
+        let size = gizmoSize(pivot, camera);
+        let (along, hit) = match handle {
+          Handle::Axis(axis) => (closestOnAxis(pivot, unitAxis(axis), ray).unwrap_or(0.0), pivot),
+          Handle::Plane(axis) | Handle::Ring(axis) => (0.0, rayPlane(ray, pivot, unitAxis(axis)).unwrap_or(pivot))
+        };
+        self.drag = Some(Drag { mode: self.mode, handle, entity: i, start: entities[i].transform(), pivot, size, along, hit });
+      }
+      _ => self.clickRay = Some(ray.dir)
+    }
+  }
+
+  /// Queues the selected entity's highlighted bounds and its gizmo, drawn over the scene
+  pub fn draw(&self, debug: &mut DebugDraw, entities: &[&Entity], camera: &Camera) {
+    if !self.enabled {
+      return;
+    }
+    let entity = match self.selected.and_then(|i| entities.get(i)) {
+      Some(entity) => entity,
+      None => return
+    };
+    entity.drawBoundingBox(debug, debugdraw::YELLOW);
+
+    let pivot = position(entity);
+    let size = self.drag.map_or_else(|| gizmoSize(pivot, camera), |d| d.size);
+    let active = self.drag.map(|d| d.handle).or(self.hovered);
+    let colour = |handle: Handle, axis: usize| if active == Some(handle) { debugdraw::YELLOW } else { AXIS_COLOURS[axis] };
+    let mode = self.drag.map_or(self.mode, |d| d.mode);
+    debug.onTop(|debug| {
+      for axis in 0..3 {
+        let direction = unitAxis(axis);
+        match mode {
+          GizmoMode::Translate | GizmoMode::Scale => {
+            let tip = pivot + direction * size;
+            let handleColour = colour(Handle::Axis(axis), axis);
+            debug.line(pivot, tip, handleColour);
+            let half = size * TIP_SIZE / 2.0;
+            debug.aabb(tip - vec3(half, half, half), tip + vec3(half, half, half), &Matrix4::from_scale(1.0), handleColour);
+            if mode == GizmoMode::Translate {
+              let (u, v) = (unitAxis((axis + 1) % 3), unitAxis((axis + 2) % 3));
+              let (a, b) = (size * PLANE_START, size * PLANE_END);
+              let corners = [pivot + u * a + v * a, pivot + u * b + v * a, pivot + u * b + v * b, pivot + u * a + v * b];
+              let planeColour = colour(Handle::Plane(axis), axis);
+              for i in 0..4 {
+                debug.line(corners[i], corners[(i + 1) % 4], planeColour);
+              }
+            }
+          }
+          GizmoMode::Rotate => debug.circle(pivot, direction, size, colour(Handle::Ring(axis), axis))
+        }
+      }
+    });
+  }
+
+  fn handleUnder(&self, ray: &Line, pivot: Vector3, size: f32) -> Option<Handle> {
+    let tolerance = size * PICK_TOLERANCE;
+    // The nearest handle along the ray wins where they overlap on screen
+    let mut best: Option<(f32, Handle)> = None;
+    let mut consider = |t: f32, handle: Handle| {
+      if t > 0.0 && best.is_none_or(|(bestT, _)| t < bestT) {
+        best = Some((t, handle));
+      }
+    };
+    for axis in 0..3 {
+      let direction = unitAxis(axis);
+      match self.mode {
+        GizmoMode::Translate | GizmoMode::Scale => {
+          if let Some(s) = closestOnAxis(pivot, direction, ray) {
+            let point = pivot + direction * s.max(0.0).min(size);
+            if distanceToRay(point, ray) < tolerance {
+              consider((point - ray.start).dot(ray.dir), Handle::Axis(axis));
+            }
+          }
+          if self.mode == GizmoMode::Translate {
+            if let Some(hit) = rayPlane(ray, pivot, direction) {
+              let offset = hit - pivot;
+              let (u, v) = (offset.dot(unitAxis((axis + 1) % 3)), offset.dot(unitAxis((axis + 2) % 3)));
+              let inside = |x: f32| x >= size * PLANE_START && x <= size * PLANE_END;
+              if inside(u) && inside(v) {
+                consider(hit.distance(ray.start), Handle::Plane(axis));
+              }
+            }
+          }
+        }
+        GizmoMode::Rotate => {
+          if let Some(hit) = rayPlane(ray, pivot, direction) {
+            if (hit.distance(pivot) - size).abs() < tolerance {
+              consider(hit.distance(ray.start), Handle::Ring(axis));
+            }
+          }
+        }
+      }
+    }
+    best.map(|(_, handle)| handle)
+  }
+
+  /// The entity's transform for the cursor's current position during `drag`, with `groundHeight`
+  /// giving the terrain height at an x, z position
+  fn dragged(&self, drag: &Drag, ray: &Line, groundHeight: &dyn Fn(f32, f32) -> f32) -> Option<Transform> {
+    let mut transform = drag.start;
+    match (drag.mode, drag.handle) {
+      (GizmoMode::Translate, Handle::Axis(axis)) => {
+        let along = closestOnAxis(drag.pivot, unitAxis(axis), ray)?;
+        transform.position += unitAxis(axis) * (along - drag.along);
+      }
+      (GizmoMode::Translate, Handle::Plane(axis)) => {
+        let hit = rayPlane(ray, drag.pivot, unitAxis(axis))?;
+        transform.position += hit - drag.hit;
+      }
+      (GizmoMode::Rotate, Handle::Ring(axis)) => {
+        let hit = rayPlane(ray, drag.pivot, unitAxis(axis))?;
+        let (from, to) = (drag.hit - drag.pivot, hit - drag.pivot);
+        let angle = unitAxis(axis).dot(from.cross(to)).atan2(from.dot(to));
+        match axis {
+          0 => transform.orientation.x += Rad(angle),
+          1 => transform.orientation.y += Rad(angle),
+          _ => transform.orientation.z += Rad(angle)
+        }
+      }
+      // Entities only scale uniformly, so every axis does the same, one gizmo length doubling the size
+      (GizmoMode::Scale, Handle::Axis(axis)) => {
+        let along = closestOnAxis(drag.pivot, unitAxis(axis), ray)?;
+        transform.scale = (drag.start.scale * (1.0 + (along - drag.along) / drag.size)).max(0.01);
+      }
+      _ => return None
+    }
+    // Only moves across the ground snap to it, so the y handles can still lift an entity off it
+    let horizontal = match drag.handle {
+      Handle::Axis(axis) => axis != 1,
+      Handle::Plane(axis) => axis == 1,
+      Handle::Ring(_) => false
+    };
+    if drag.mode == GizmoMode::Translate && horizontal && self.snapToTerrain {
+      transform.position.x = transform.position.x.clamp(DEADZONE, BOUND_MAX);
+      transform.position.z = transform.position.z.clamp(DEADZONE, BOUND_MAX);
+      transform.position.y = groundHeight(transform.position.x, transform.position.z);
+    }
+    Some(transform)
+  }
+
+  fn keyPressed(&mut self, window: &Window, key: Key) -> bool {
+    let down = window.get_key(key) == Action::Press;
+    let held = self.keysHeld.contains(&key);
+    if down && !held {
+      self.keysHeld.push(key);
+    } else if !down && held {
+      self.keysHeld.retain(|&k| k != key);
+    }
+    down && !held
+  }
+}
+
+/// Index of the entity whose bounds the ray enters first
+fn pick(ray: &Line, entities: &[&mut Entity]) -> Option<usize> {
+  entities.iter().enumerate()
+    .filter_map(|(i, entity)| {
+      let nearest = entity.intersect(ray).into_iter().map(|(tMin, _)| tMin).filter(|&t| t > 0.0).fold(f32::MAX, f32::min);
+      if nearest < f32::MAX { Some((nearest, i)) } else { None }
+    })
+    .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+    .map(|(_, i)| i)
+}
+
+fn position(entity: &Entity) -> Vector3 {
+  vec3(entity.worldPos.x, entity.worldPos.y, entity.worldPos.z)
+}
+
+fn gizmoSize(pivot: Vector3, camera: &Camera) -> f32 {
+  (pivot.distance(vec3(camera.position.x, camera.position.y, camera.position.z)) * GIZMO_SCALE).max(0.1)
+}
+
+fn unitAxis(axis: usize) -> Vector3 {
+  let mut v = vec3(0.0, 0.0, 0.0);
+  v[axis] = 1.0;
+  v
+}
+
+/// Distance along the line through `origin` in `direction` of its closest point to the ray, None
+/// when they're parallel
+fn closestOnAxis(origin: Vector3, direction: Vector3, ray: &Line) -> Option<f32> {
+  let b = direction.dot(ray.dir);
+  let denominator = 1.0 - b * b;
+  if denominator.abs() < 1e-6 {
+    return None;
+  }
+  let w = origin - ray.start;
+  Some((b * ray.dir.dot(w) - direction.dot(w)) / denominator)
+}
+
+fn distanceToRay(point: Vector3, ray: &Line) -> f32 {
+  let along = (point - ray.start).dot(ray.dir);
+  point.distance(ray.start + ray.dir * along)
+}
+
+/// Where the ray crosses the plane through `origin` facing `normal`, if it does in front of the start
+fn rayPlane(ray: &Line, origin: Vector3, normal: Vector3) -> Option<Vector3> {
+  let facing = normal.dot(ray.dir);
+  if facing.abs() < 1e-6 {
+    return None;
+  }
+  let t = normal.dot(origin - ray.start) / facing;
+  if t < 0.0 { None } else { Some(ray.start + ray.dir * t) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cgmath::Point3;
+
+  fn transform(x: f32) -> Transform {
+    Transform { position: Point3::new(x, 0.0, 0.0), orientation: vec3(Rad(0.0), Rad(0.0), Rad(0.0)), scale: 1.0 }
+  }
+
+  #[test]
+  fn undoRedoOrder() {
+    let mut history = History::default();
+    history.record(0, transform(0.0), transform(1.0));
+    history.record(1, transform(5.0), transform(6.0));
+    history.record(0, transform(2.0), transform(2.0));
+
+    assert_eq!(history.undo(), Some((1, transform(5.0))));
+    assert_eq!(history.undo(), Some((0, transform(0.0))));
+    assert_eq!(history.undo(), None);
+    assert_eq!(history.redo(), Some((0, transform(1.0))));
+
+    // A new edit drops what's left to redo
+    history.record(0, transform(1.0), transform(3.0));
+    assert_eq!(history.redo(), None);
+    assert_eq!(history.undo(), Some((0, transform(1.0))));
+  }
+
+  #[test]
+  fn pushesAreUndoable() {
+    let mut editor = Editor::new();
+    let position = std::cell::Cell::new(0.0);
+    let lookup = |_: usize| Some(transform(position.get()));
+    editor.trackPush(false, 0, &lookup);
+    // Pushed over three frames, then let go
+    for x in &[0.0, 1.0, 2.0] {
+      position.set(*x);
+      editor.trackPush(true, 0, &lookup);
+    }
+    position.set(3.0);
+    editor.trackPush(false, 0, &lookup);
+    assert_eq!(editor.history.undo(), Some((0, transform(0.0))));
+    assert_eq!(editor.history.undo(), None);
+    assert_eq!(editor.history.redo(), Some((0, transform(3.0))));
+  }
+
+  #[test]
+  fn rayConstraints() {
+    // Looking down from above the x axis, offset along z
+    let ray = Line::new(vec3(4.0, 10.0, 2.0), vec3(4.0, 0.0, 2.0));
+    assert_eq!(closestOnAxis(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), &ray), Some(4.0));
+    assert_eq!(closestOnAxis(vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), &ray), None);
+    assert_eq!(distanceToRay(vec3(4.0, 0.0, 0.0), &ray), 2.0);
+
+    assert_eq!(rayPlane(&ray, vec3(0.0, 3.0, 0.0), vec3(0.0, 1.0, 0.0)), Some(vec3(4.0, 3.0, 2.0)));
+    assert_eq!(rayPlane(&ray, vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)), None);
+    // Planes behind the start of the ray aren't hit
+    assert_eq!(rayPlane(&ray, vec3(0.0, 20.0, 0.0), vec3(0.0, 1.0, 0.0)), None);
+  }
+
+  #[test]
+  fn snapLeavesVerticalMoves() {
+    let editor = Editor::new();
+    assert!(editor.snapToTerrain);
+    let ground = |_: f32, _: f32| 2.0;
+    let start = Transform { position: Point3::new(10.0, 2.0, 10.0), orientation: vec3(Rad(0.0), Rad(0.0), Rad(0.0)), scale: 1.0 };
+    let drag = |handle: Handle| Drag { mode: GizmoMode::Translate, handle, entity: 0, start, pivot: vec3(10.0, 2.0, 10.0), size: 1.0, along: 0.0, hit: vec3(10.0, 2.0, 10.0) };
+    // Grabbed at the pivot, then the cursor's ray passes 3 higher and 4 further along x
+    let ray = Line::new(vec3(0.0, 5.0, 14.0), vec3(20.0, 5.0, 14.0));
+    let lifted = editor.dragged(&drag(Handle::Axis(1)), &ray, &ground).unwrap();
+    assert_eq!(lifted.position, Point3::new(10.0, 5.0, 10.0));
+
+    let ray = Line::new(vec3(14.0, 30.0, 0.0), vec3(14.0, 30.0, 20.0));
+    let moved = editor.dragged(&drag(Handle::Axis(0)), &ray, &ground).unwrap();
+    assert_eq!(moved.position, Point3::new(14.0, 2.0, 10.0));
+  }
+}
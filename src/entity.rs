@@ -2,22 +2,34 @@
 extern crate glfw;
 use self::glfw::Key;
 
-use cgmath::{vec3, Rad};
+use cgmath::{vec3, vec4, Angle, Deg, InnerSpace, MetricSpace, Rad};
 
+use crate::assets::ModelHandle;
+use crate::camera::Camera;
+use crate::debugdraw::DebugDraw;
 use crate::mesh::*;
-use crate::utils::maths::{computeBoundingBox, computeBoundingBoxTransform};
+use crate::utils::maths::computeBoundingBoxTransform;
+use crate::utils::model::LOD_SCREEN_SIZES;
 use crate::utils::shader::Shader;
 use crate::terrain::{Terrain, DEADZONE, BOUND_MAX};
 use crate::types::*;
 
 pub struct Entity {
-  meshes: Vec<Mesh>,
+  model: ModelHandle,
   boundingBoxes: Vec<BoundingBox>,
   boundingTransforms: Vec<Matrix4>,
   pub worldPos: Point3,
   pub orientation: cgmath::Vector3<Rad<f32>>,
   pub scale: f32,
-  speed: f32
+  pub speed: f32
+}
+
+/// Placement of an entity in the world, everything the editor can change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+  pub position: Point3,
+  pub orientation: cgmath::Vector3<Rad<f32>>,
+  pub scale: f32
 }
 
 struct BoundingBox {
@@ -30,15 +42,37 @@ impl BoundingBox {
 }
 
 impl Entity {
-  pub fn new(meshes: Vec<Mesh>, worldPos: Point3, orientation: cgmath::Vector3<Rad<f32>>, scale: f32, speed: f32) -> Entity {
-    let mut boundingBoxes = Vec::with_capacity(meshes.len());
-    let mut boundingTransforms = Vec::with_capacity(meshes.len());
-    for m in &meshes { 
-      let (min, max) = computeBoundingBox(&m.vertices);
+  pub fn new(model: ModelHandle, worldPos: Point3, orientation: cgmath::Vector3<Rad<f32>>, scale: f32, speed: f32) -> Entity {
+    let mut boundingBoxes = Vec::with_capacity(model.meshes.len());
+    let mut boundingTransforms = Vec::with_capacity(model.meshes.len());
+    for m in &model.meshes { 
+      let (min, max) = m.bounds;
       boundingBoxes.push(BoundingBox::new(min, max));
       boundingTransforms.push(computeBoundingBoxTransform(min, max)); 
     }
-    Entity { meshes, boundingBoxes, boundingTransforms, worldPos, orientation, scale, speed: speed }
+    Entity { model, boundingBoxes, boundingTransforms, worldPos, orientation, scale, speed: speed }
+  }
+
+  /// Swaps in a different model, e.g. once a background load replaces the placeholder
+  pub fn setModel(&mut self, model: ModelHandle) {
+    self.boundingBoxes.clear();
+    self.boundingTransforms.clear();
+    for m in &model.meshes {
+      let (min, max) = m.bounds;
+      self.boundingBoxes.push(BoundingBox::new(min, max));
+      self.boundingTransforms.push(computeBoundingBoxTransform(min, max));
+    }
+    self.model = model;
+  }
+
+  pub fn transform(&self) -> Transform {
+    Transform { position: self.worldPos, orientation: self.orientation, scale: self.scale }
+  }
+
+  pub fn setTransform(&mut self, transform: Transform) {
+    self.worldPos = transform.position;
+    self.orientation = transform.orientation;
+    self.scale = transform.scale;
   }
 
   fn getModelMatrix(&self) -> Matrix4 {
@@ -49,21 +83,72 @@ impl Entity {
     Matrix4::from_scale(self.scale)
   }
 
-  pub fn draw(&self, shader: &Shader, view: &Matrix4, projection: &Matrix4) {
-    shader.initShader(&self.getModelMatrix(), view, projection);
-    for mesh in &self.meshes { unsafe { mesh.draw(shader) } }
+  /// Picks the level of detail from how much of the screen height the model's bounding sphere covers
+  fn lod(&self, camera: &Camera) -> usize {
+    if self.boundingBoxes.is_empty() {
+      return 0;
+    }
+    let min = self.boundingBoxes.iter().fold(vec3(f32::MAX, f32::MAX, f32::MAX), |m, b| vec3(m.x.min(b.min.x), m.y.min(b.min.y), m.z.min(b.min.z)));
+    let max = self.boundingBoxes.iter().fold(vec3(f32::MIN, f32::MIN, f32::MIN), |m, b| vec3(m.x.max(b.max.x), m.y.max(b.max.y), m.z.max(b.max.z)));
+    let centre = self.getModelMatrix() * ((min + max) / 2.0).extend(1.0);
+    let radius = (max - min).magnitude() / 2.0 * self.scale;
+    let distance = camera.position.distance(Point3::new(centre.x, centre.y, centre.z));
+
+    let screenSize = radius / (distance * (Deg(camera.zoom) / 2.0).tan());
+    LOD_SCREEN_SIZES.iter().take_while(|&&size| screenSize < size).count()
   }
 
-  pub fn drawBoundingBox(&self, shader: &Shader, mesh: &Mesh, view: &Matrix4, projection: &Matrix4) {
+  /// Draws the opaque meshes, transparent ones wait for `drawMesh` from the sorted pass
+  pub fn draw(&self, shader: &Shader, camera: &Camera) {
+    let lod = self.lod(camera);
+    shader.updateModel(&self.getModelMatrix());
+    for mesh in self.model.meshes.iter().filter(|m| !m.isTransparent()) { unsafe { mesh.drawLod(shader, lod) } }
+  }
+
+  pub fn drawMesh(&self, shader: &Shader, index: usize, camera: &Camera) {
+    shader.updateModel(&self.getModelMatrix());
+    unsafe { self.model.meshes[index].drawLod(shader, self.lod(camera)) }
+  }
+
+  /// Indices of the transparent meshes with their distance from `eye` to the centre of their bounding box
+  pub fn transparentMeshes(&self, eye: &Point3) -> Vec<(f32, usize)> {
+    let model = self.getModelMatrix();
+    self.model.meshes.iter().enumerate()
+      .filter(|(_, mesh)| mesh.isTransparent())
+      .map(|(i, mesh)| {
+        let (min, max) = mesh.bounds;
+        let centre = model * ((min + max) / 2.0);
+        (eye.distance(Point3::new(centre.x, centre.y, centre.z)), i)
+      })
+      .collect()
+  }
+
+  /// Queues the oriented bounds of each mesh
+  pub fn drawBoundingBox(&self, debug: &mut DebugDraw, colour: [f32; 4]) {
     let model = self.getModelMatrix();
-    shader.initShader(&model, view, projection);
+    for boundingTransform in self.boundingTransforms.iter() {
+      debug.aabb(vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5), &(model * boundingTransform), colour);
+    }
+  }
 
-    for (_, boundingTransform) in self.meshes.iter().zip(self.boundingTransforms.iter()) {
-      unsafe {
-        shader.updateModel(&(model * boundingTransform));
-        mesh.drawBoundingBox();
-      }
+  /// World space box around every mesh, or a point at the entity's position if it has none
+  pub fn worldBounds(&self) -> (Vector3, Vector3) {
+    let model = self.getModelMatrix();
+    let origin = vec3(self.worldPos.x, self.worldPos.y, self.worldPos.z);
+    let corners: Vec<Vector3> = self.boundingBoxes.iter()
+      .flat_map(|b| (0..8).map(move |i| vec4(
+        if i & 1 == 0 { b.min.x } else { b.max.x },
+        if i & 2 == 0 { b.min.y } else { b.max.y },
+        if i & 4 == 0 { b.min.z } else { b.max.z },
+        1.0)))
+      .map(|corner| (model * corner).truncate())
+      .collect();
+    if corners.is_empty() {
+      return (origin, origin);
     }
+    corners.iter().fold((corners[0], corners[0]), |(min, max), c| {
+      (vec3(min.x.min(c.x), min.y.min(c.y), min.z.min(c.z)), vec3(max.x.max(c.x), max.y.max(c.y), max.z.max(c.z)))
+    })
   }
 
   pub fn intersect(&self, ray: &Line) -> Vec<(f32, f32)> {
@@ -74,20 +159,20 @@ impl Entity {
       let vMin = model * boundingBox.min;
       let vMax = model * boundingBox.max;
 
-      let mut tMin = (vMin.x - ray.coords[0].x) / ray.dir.x;
-      let mut tMax = (vMax.x - ray.coords[0].x) / ray.dir.x;
+      let mut tMin = (vMin.x - ray.start.x) / ray.dir.x;
+      let mut tMax = (vMax.x - ray.start.x) / ray.dir.x;
       if tMin > tMax { std::mem::swap(&mut tMin, &mut tMax) }
 
-      let mut tYMin = (vMin.y - ray.coords[0].y) / ray.dir.y;
-      let mut tYMax = (vMax.y - ray.coords[0].y) / ray.dir.y;
+      let mut tYMin = (vMin.y - ray.start.y) / ray.dir.y;
+      let mut tYMax = (vMax.y - ray.start.y) / ray.dir.y;
       if tYMin > tYMax { std::mem::swap(&mut tYMin, &mut tYMax) } 
 
       if tMin > tYMax || tYMin > tMax { continue }
       tMin = tMin.max(tYMin);
       tMax = tMax.min(tYMax);
 
-      let mut tZMin = (vMin.z - ray.coords[0].z) / ray.dir.z;
-      let mut tZMax = (vMax.z - ray.coords[0].z) / ray.dir.z;
+      let mut tZMin = (vMin.z - ray.start.z) / ray.dir.z;
+      let mut tZMax = (vMax.z - ray.start.z) / ray.dir.z;
       if tZMin > tZMax { std::mem::swap(&mut tZMin, &mut tZMax) }
 
       if tMin > tZMax || tZMin > tMax { continue }
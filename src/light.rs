@@ -1,5 +1,14 @@
+use cgmath::vec3;
+
 use crate::types::Vector3;
 
+/// Upper bound on lights uploaded to the forward shaders, must match MAX_LIGHTS in frame.glsl
+pub const MAX_LIGHTS: usize = 16;
+
+// Contribution below which a light is considered to have no visible effect
+const CUTOFF: f32 = 5.0 / 256.0;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Light {
   pub position: Vector3,
   pub colour: Vector3,
@@ -10,4 +19,62 @@ impl Light {
   pub fn new(position: Vector3, colour: Vector3, attenuation: Vector3, brightness: f32) -> Light {
     Light { position, colour: (colour * brightness), attenuation }
   }
-}
\ No newline at end of file
+
+  /// Distance at which the attenuated light falls below the cutoff, or `None` if it never does
+  pub fn radius(&self) -> Option<f32> {
+    let (c, l, q) = (self.attenuation.x, self.attenuation.y, self.attenuation.z);
+    let maxColour = self.colour.x.max(self.colour.y).max(self.colour.z);
+    let target = maxColour / CUTOFF - c;
+    if q > 0.0 {
+      Some((-l + (l * l + 4.0 * q * target).sqrt()) / (2.0 * q))
+    } else if l > 0.0 {
+      Some(target / l)
+    } else {
+      None
+    }
+  }
+}
+
+/// How a scene is lit besides its lights: ambient light from the sky and ground, and screen-space
+/// ambient occlusion darkening it in creases and corners
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightingSettings {
+  /// Brightness of the ambient light before the time of day tints it
+  pub ambient: f32,
+  /// Blends from `groundColour` on downward facing surfaces to `skyColour` on upward ones rather
+  /// than lighting every side the same
+  pub hemisphere: bool,
+  pub skyColour: Vector3,
+  pub groundColour: Vector3,
+  pub ssao: bool,
+  /// World units around each pixel searched for occluders
+  pub ssaoRadius: f32,
+  pub ssaoIntensity: f32
+}
+
+impl Default for LightingSettings {
+  fn default() -> LightingSettings {
+    LightingSettings {
+      ambient: 0.25,
+      hemisphere: true,
+      skyColour: vec3(0.6, 0.75, 1.0),
+      groundColour: vec3(0.4, 0.33, 0.25),
+      ssao: true,
+      ssaoRadius: 2.0,
+      ssaoIntensity: 1.0
+    }
+  }
+}
+
+impl LightingSettings {
+  /// Ambient colours facing straight up and straight down, scaled by `tint` for the time of day
+  pub fn hemisphereColours(&self, tint: Vector3) -> (Vector3, Vector3) {
+    let light = |colour: Vector3| vec3(colour.x * tint.x, colour.y * tint.y, colour.z * tint.z) * self.ambient;
+    if self.hemisphere {
+      (light(self.skyColour), light(self.groundColour))
+    } else {
+      let flat = light(vec3(1.0, 1.0, 1.0));
+      (flat, flat)
+    }
+  }
+}
@@ -0,0 +1,104 @@
+#![allow(non_snake_case)]
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::utils::common::{decodeTexture, TextureData};
+use crate::utils::error::AssetError;
+use crate::utils::model::{MeshData, Model};
+
+const WORKER_THREADS: usize = 4;
+
+pub enum Job {
+  Model(String),
+  Texture(String)
+}
+
+/// The CPU half of an asset, ready for its GL objects to be created on the main thread
+pub enum Loaded {
+  Model(String, Result<Vec<MeshData>, AssetError>),
+  Texture(String, Result<TextureData, AssetError>)
+}
+
+/// Pool of threads doing the file I/O, OBJ parsing and image decoding that doesn't need a GL context
+pub struct Loader {
+  jobs: Sender<Job>,
+  results: Receiver<Loaded>,
+  /// Reports jobs that couldn't be handed to a worker through the usual results
+  failures: Sender<Loaded>
+}
+
+impl Loader {
+  pub fn new() -> Loader {
+    let (jobs, jobReceiver) = channel::<Job>();
+    let (resultSender, results) = channel();
+    let jobReceiver = Arc::new(Mutex::new(jobReceiver));
+
+    for _ in 0..WORKER_THREADS {
+      let jobReceiver = Arc::clone(&jobReceiver);
+      let resultSender = resultSender.clone();
+      // Workers exit once the loader, and with it the job sender, is dropped
+      thread::spawn(move || loop {
+        let job = match jobReceiver.lock().unwrap().recv() {
+          Ok(job) => job,
+          Err(_) => break
+        };
+        // A file that makes the parser or decoder panic fails like any other bad file, and the
+        // worker carries on with the next job
+        let loaded = match job {
+          Job::Model(path) => {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| Model::load(&path))).unwrap_or_else(|p| Err(panicked(&path, p)));
+            Loaded::Model(path, result)
+          }
+          Job::Texture(path) => {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| decodeTexture(&path))).unwrap_or_else(|p| Err(panicked(&path, p)));
+            Loaded::Texture(path, result)
+          }
+        };
+        if resultSender.send(loaded).is_err() {
+          break;
+        }
+      });
+    }
+    Loader { jobs, results, failures: resultSender }
+  }
+
+  pub fn request(&self, job: Job) {
+    if let Err(error) = self.jobs.send(job) {
+      let stopped = |path: &str| AssetError::Loader { path: path.into(), reason: "the loader threads have stopped".into() };
+      let loaded = match error.0 {
+        Job::Model(path) => Loaded::Model(path.clone(), Err(stopped(&path))),
+        Job::Texture(path) => Loaded::Texture(path.clone(), Err(stopped(&path)))
+      };
+      // Can't fail, the receiving end lives in this loader
+      let _ = self.failures.send(loaded);
+    }
+  }
+
+  /// Next finished job, if any, without blocking
+  pub fn tryRecv(&self) -> Option<Loaded> {
+    self.results.try_recv().ok()
+  }
+}
+
+fn panicked(path: &str, payload: Box<dyn Any + Send>) -> AssetError {
+  let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+    .or_else(|| payload.downcast_ref::<String>().cloned())
+    .unwrap_or_else(|| "unknown panic".into());
+  AssetError::Loader { path: path.into(), reason: format!("the loader panicked: {}", message) }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn panicsBecomeErrors() {
+    let payload = panic::catch_unwind(|| panic!("bad {} at {}", "header", 12)).unwrap_err();
+    assert_eq!(panicked("a.png", payload).to_string(), "Failed to load a.png: the loader panicked: bad header at 12");
+    let payload = panic::catch_unwind(|| panic!("static")).unwrap_err();
+    assert!(panicked("b.obj", payload).to_string().ends_with("panicked: static"));
+  }
+}
@@ -1,21 +1,29 @@
 #![allow(non_snake_case)]
 extern crate gl;
 extern crate glfw;
-use self::glfw::Context;
+use self::glfw::{Action, Context, Key, MouseButtonLeft};
+
+use std::ffi::CStr;
 
 use cgmath::{perspective, Deg, Rad, vec3};
 
 mod utils;
 use utils::common::*;
-use utils::model::Model;
 use utils::shader::Shader;
-use utils::maths::{BOUNDING_BOX, BOUNDING_BOX_INDICES};
+use utils::meshcache;
+use utils::model::Model;
+use utils::target::{RenderTarget, DepthAttachment, RGBA16F};
+use utils::uniforms::FrameUniforms;
+use utils::watcher::FileWatcher;
+
+mod assets;
+mod loader;
+use assets::AssetManager;
 
 mod entity;
 use entity::Entity;
 
 mod mesh;
-use mesh::Mesh;
 
 mod camera;
 use camera::Camera;
@@ -23,79 +31,659 @@ use camera::Camera;
 mod terrain;
 use terrain::{Terrain, SIZE};
 
+mod scene;
+use scene::{Scene, SceneFile, NANOSUIT, SCENE_FILE};
+
 mod light;
-use light::Light;
+
+mod daynight;
+
+mod skybox;
+use skybox::{Skybox, Fog};
+
+mod water;
+use water::Water;
+
+mod postprocess;
+use postprocess::{PostProcessor, PostEffect};
+
+mod deferred;
+use deferred::{DeferredRenderer, RenderPath};
+
+mod ssao;
+use ssao::Ssao;
+
+mod timing;
+use timing::{FrameClock, Snapshot, FIXED_STEP};
+
+mod profiler;
+use profiler::{Profiler, PROFILE_FILE};
+
+mod settings;
+use settings::Settings;
+
+mod debugui;
+use debugui::{DebugUi, UiInput};
+
+mod debugdraw;
+use debugdraw::DebugDraw;
+
+mod text;
+use text::TextRenderer;
+
+mod editor;
+use editor::{Editor, GizmoMode};
 
 mod types;
 use types::*;
 
-const SCR_WIDTH: u32 = 3840;
-const SCR_HEIGHT: u32 = 2160;
+const NEAR_PLANE: f32 = 0.1;
 const DRAW_DISTANCE: f32 = 1500.0;
+const FOG_DENSITY: f32 = 0.0012;
+const FOG_GRADIENT: f32 = 4.0;
+const WATER_HEIGHT: f32 = 105.0;
+// Time each frame may spend creating GL objects for assets loaded in the background
+const UPLOAD_BUDGET_MS: u64 = 4;
+const FONT: &str = "resources/fonts/DejaVuSans.ttf";
+// Baked large enough to stay crisp when scaled up for name tags
+const FONT_PIXELS: f32 = 32.0;
 
 pub fn main() {
+  let args: Vec<String> = std::env::args().collect();
+  if args.get(1).map(String::as_str) == Some("bake") {
+    bakeMeshCaches(&args[2..]);
+    return;
+  }
+  let renderPath = if args.iter().any(|a| a == "--deferred") { RenderPath::Deferred } else { RenderPath::Forward };
+  let settings = Settings::load(&args);
 
   // Gl init
   let mut glfw = initGlfw();
-  let (mut window, events) = createAndInitWindow(&mut glfw, SCR_WIDTH, SCR_HEIGHT);
+  let (mut window, events) = createAndInitWindow(&mut glfw, &settings);
   initGl(&mut window);
+  let (mut width, mut height) = window.get_framebuffer_size();
 
   // Camera/Mouse data
   let mut firstMouse = true;
-  let mut lastX = SCR_WIDTH as f32 / 2.0;
-  let mut lastY = SCR_HEIGHT as f32 / 2.0;
-  let mut deltaTime = 0.0;
-  let mut lastFrame = 0.0;
+  let (windowWidth, windowHeight) = window.get_size();
+  let mut lastX = windowWidth as f32 / 2.0;
+  let mut lastY = windowHeight as f32 / 2.0;
   let mut camera = Camera {
     position: Point3::new(0.0, 160.0, 0.0),
+    zoom: settings.fov,
+    maxZoom: settings.fov,
     ..Camera::default()
   };
 
   // Shaders
-  let mainShader = Shader::new("src/shaders/mainVertex.vs", "src/shaders/mainFragment.fs");
-  let lineShader = Shader::new("src/shaders/lineVertex.vs", "src/shaders/lineFragment.fs");
-  let terrainShader = Shader::new("src/shaders/terrVertex.vs", "src/shaders/terrFragment.fs");
-
-  // Terrain/Entities
-  let boundingMesh = Mesh::new(BOUNDING_BOX.to_vec(), BOUNDING_BOX_INDICES.to_vec(), vec![]);
-  let terrain = Terrain::new(Point3::new(0.0, 0.0, 0.0), vec3(Rad(0.0), Rad(0.0), Rad(0.0)), 1.0);
-  let mut nanoEntity = Entity::new(
-    Model::new("resources/objects/nanosuit/nanosuit.obj").meshes, 
-    Point3::new(20.0, terrain.getHeight(20.0, 20.0), 20.0), 
-    vec3(Rad(0.0), Rad(0.785), Rad(0.0)), 
-    1.0, 
-    80.0);
-
-  // Lights
-  let light = Light::new(
-    vec3(SIZE / 2.0, terrain.getHeight(35.0, 35.0) + 1000.0, SIZE / 2.0), 
-    vec3(255.0, 241.0, 224.0), 
-    vec3(1.0, 0.0, 0.0), 
-    0.005);
-  mainShader.loadLight(&light.position, &light.colour, &light.attenuation);
-  mainShader.loadShine(500.0, 1.0);
-  terrainShader.loadLight(&light.position, &light.colour, &light.attenuation);
+  let mut assets = AssetManager::new();
+  let mainShader = assets.shader("src/shaders/mainVertex.vs", "src/shaders/mainFragment.fs");
+  let terrainShader = assets.shader("src/shaders/terrVertex.vs", "src/shaders/terrFragment.fs");
+  let skyShader = assets.shader("src/shaders/skyVertex.vs", "src/shaders/skyFragment.fs");
+  let waterShader = assets.shader("src/shaders/waterVertex.vs", "src/shaders/waterFragment.fs");
+
+  // Terrain, entities and lights, from `--scene path` if given. F5 saves back to that file (or
+  // saved.scene) and F9 reloads it
+  let scenePath = args.iter().position(|a| a == "--scene").and_then(|i| args.get(i + 1)).cloned();
+  let mut scene = match scenePath.as_ref().map(|path| SceneFile::load(path)) {
+    Some(Ok(file)) => {
+      file.camera.apply(&mut camera);
+      Scene::build(&file, &mut assets)
+    }
+    Some(Err(e)) => {
+      println!("{}", e);
+      Scene::demo(&mut assets)
+    }
+    None => Scene::demo(&mut assets)
+  };
+  let scenePath = scenePath.unwrap_or_else(|| SCENE_FILE.into());
+  let frame = FrameUniforms::new();
+  frame.setLights(&scene.lights);
+
+  // Sky/Fog, coloured by the time of day
+  let mut skybox = Skybox::new("resources/textures/skybox");
+  let mut fog = Fog { colour: scene.time.sky().fogColour, density: FOG_DENSITY, gradient: FOG_GRADIENT };
+  for shader in &[&mainShader, &terrainShader, &skyShader, &waterShader] {
+    shader.loadFog(&fog.colour, fog.density, fog.gradient);
+  }
+
+  // Water
+  let mut water = Water::new(WATER_HEIGHT, SIZE, vec3(0.0, 0.3, 0.5), &mut assets);
+  mainShader.loadWater(water.height, &water.tint);
+  waterShader.loadWater(water.height, &water.tint);
+  unsafe {
+    waterShader.setFloat(c_str!("nearPlane"), NEAR_PLANE);
+    waterShader.setFloat(c_str!("farPlane"), DRAW_DISTANCE);
+  }
+  // Post-processing, the scene is rendered in HDR and resolved by the chain
+  let mut sceneTarget = RenderTarget::new(width, height, &[RGBA16F], DepthAttachment::Texture);
+  let mut postProcessor = PostProcessor::new(width, height, vec![
+    PostEffect::Bloom { threshold: 1.0, intensity: 0.6, blurPasses: 5 },
+    PostEffect::GammaCorrection { gamma: 2.2, exposure: 1.0 },
+    PostEffect::ColourGrading { lut: "resources/textures/lut/neutral.png".into() },
+    PostEffect::Vignette { radius: 0.85, softness: 0.45 },
+    PostEffect::Fxaa
+  ]);
+
+  let mut deferred = match renderPath {
+    RenderPath::Deferred => Some(DeferredRenderer::new(width, height, &fog, &water)),
+    RenderPath::Forward => None
+  };
+  // The forward path draws into a multisampled copy of the scene target and resolves it. The
+  // G-buffer can't be multisampled without shading every sample, so deferred goes without
+  let mut msaaTarget = match (renderPath, settings.msaaSamples) {
+    (RenderPath::Forward, samples) if samples > 0 => Some(RenderTarget::multisampled(width, height, &[RGBA16F], DepthAttachment::RenderBuffer, samples as i32)),
+    _ => None
+  };
+
+  let mut ssao = Ssao::new(width, height);
+
+  // Rebuild shaders and re-upload textures when their files change on disk
+  // (renderer-owned shaders aren't in the asset manager so are tracked separately)
+  let mut watcher = FileWatcher::new(std::time::Duration::from_millis(250));
+  watchAssets(&mut watcher, &assets, &rendererShaders(&postProcessor, &deferred, &ssao));
+  let mut loading = true;
+
+  let mut ui = DebugUi::new(&mut assets);
+  let mut debugDraw = DebugDraw::new(&mut assets);
+  let mut text = TextRenderer::new(&mut assets, FONT, FONT_PIXELS);
+  let mut editor = Editor::new();
+  let mut debug = DebugOptions { showBounds: true, showLights: false, wireframe: false, selectedLight: 0, toggleHeld: false };
+  let (mut saveHeld, mut loadHeld, mut pauseHeld, mut exportHeld) = (false, false, false, false);
+
+  // Main pass clips nothing, only the water passes cut the scene at the surface
+  let noClip = Vector4 { x: 0.0, y: -1.0, z: 0.0, w: 100000.0 };
+
+  let mut clock = FrameClock::new(settings.fpsLimit);
+  // Positions before the latest simulation step, to draw in between
+  let mut previous = Snapshot::capture(camera.position, &scene.entities);
+  let mut profiler = Profiler::new();
 
   while !window.should_close() {
-    updateTimings(glfw, &mut deltaTime, &mut lastFrame);
+    let deltaTime = clock.beginFrame();
+    profiler.beginFrame();
+    water.update(deltaTime);
 
-    let projection = perspective(Deg(camera.zoom), SCR_WIDTH as f32 / SCR_HEIGHT as f32, 0.1, DRAW_DISTANCE);
-    process_events(&mut window, &events, &mut firstMouse, &mut lastX, &mut lastY, &mut camera);
-    processInput(&mut window, deltaTime, &mut camera, &mut nanoEntity, lastX, lastY, &terrain, &projection);
-    
-    unsafe {
-      gl::ClearColor(0.1, 0.1, 0.1, 1.0);
-      gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-      
+    profiler.begin("assets");
+    assets.update(std::time::Duration::from_millis(UPLOAD_BUDGET_MS));
+    scene.update();
+    profiler.end();
+    let progress = assets.progress();
+    if progress.completed < progress.requested {
+      window.set_title(&format!("Rust Sandbox - loading {}/{}", progress.completed, progress.requested));
+      loading = true;
+    } else if loading {
+      window.set_title("Rust Sandbox");
+      watchAssets(&mut watcher, &assets, &rendererShaders(&postProcessor, &deferred, &ssao));
+      println!("Assets: {}", assets.report());
+      loading = false;
+    }
+    for path in watcher.poll() {
+      println!("Reloading {}", path.display());
+      assets.reload(&path);
+      let shaders = rendererShaders(&postProcessor, &deferred, &ssao);
+      for shader in shaders.iter().filter(|s| s.watches(&path)) {
+        shader.reload();
+      }
+      // The edit may have added an #include
+      watchAssets(&mut watcher, &assets, &shaders);
+    }
+
+    if let Some((newWidth, newHeight)) = process_events(&mut window, &events, &mut firstMouse, &mut lastX, &mut lastY, &mut camera, ui.wantsMouse() || editor.wantsMouse()) {
+      width = newWidth;
+      height = newHeight;
+      sceneTarget.resize(width, height);
+      postProcessor.resize(width, height);
+      ssao.resize(width, height);
+      if let Some(ref mut deferred) = deferred {
+        deferred.resize(width, height);
+      }
+      if let Some(ref mut msaaTarget) = msaaTarget {
+        msaaTarget.resize(width, height);
+      }
+    }
+    let projection = perspective(Deg(camera.zoom), width as f32 / height as f32, NEAR_PLANE, DRAW_DISTANCE);
+    text.begin((width, height));
+    // Keys and the debug panel act on the entity selected in the editor, or the first one
+    let active = editor.selected.unwrap_or(0);
+    // Movement runs in fixed steps so its speed doesn't depend on the frame rate
+    let ray = cursorRay(&window, (lastX, lastY), &projection, &camera);
+    let push = processFrameInput(&mut window, &ray, scene.entities.get(active), &mut debugDraw, editor.enabled);
+    editor.trackPush(push.is_some(), active, &|i| scene.entities.get(i).map(Entity::transform));
+    profiler.begin("simulation");
+    while clock.step() {
+      previous = Snapshot::capture(camera.position, &scene.entities);
+      processInput(&window, FIXED_STEP, &mut camera, scene.entities.get_mut(active), &scene.terrain);
+      if let (Some(direction), Some(entity)) = (push, scene.entities.get_mut(active)) {
+        entity.processMouse(direction, &scene.terrain, FIXED_STEP);
+      }
+    }
+    profiler.end();
+    let stepped = Snapshot::capture(camera.position, &scene.entities);
+    editor.update(&window, &ray, &mut scene.entities.iter_mut().collect::<Vec<_>>(), &scene.terrain, &camera, !ui.wantsMouse());
+    previous.resync(&stepped, &Snapshot::capture(camera.position, &scene.entities));
+
+    if keyPressed(&window, Key::F5, &mut saveHeld) {
+      match scene.toFile(&camera).save(&scenePath) {
+        Ok(()) => println!("Saved scene to {}", scenePath),
+        Err(e) => println!("{}", e)
+      }
+    }
+    if keyPressed(&window, Key::F9, &mut loadHeld) {
+      match SceneFile::load(&scenePath) {
+        Ok(file) => {
+          file.camera.apply(&mut camera);
+          scene = Scene::build(&file, &mut assets);
+          frame.setLights(&scene.lights);
+          editor.reset();
+          debug.selectedLight = 0;
+          previous = Snapshot::capture(camera.position, &scene.entities);
+          println!("Loaded scene from {}", scenePath);
+        }
+        Err(e) => println!("{}", e)
+      }
+    }
+    // F10 writes the last couple of seconds of profiling out as a Chrome trace
+    if keyPressed(&window, Key::F10, &mut exportHeld) {
+      match profiler.exportTrace(PROFILE_FILE) {
+        Ok(()) => println!("Wrote profile to {}", PROFILE_FILE),
+        Err(e) => println!("Failed to write {}: {}", PROFILE_FILE, e)
+      }
+    }
+    // P pauses the day and [ / ] wind it back and forward
+    if keyPressed(&window, Key::P, &mut pauseHeld) {
+      scene.time.paused = !scene.time.paused;
+    }
+    let scrub = match (window.get_key(Key::LeftBracket), window.get_key(Key::RightBracket)) {
+      (Action::Press, Action::Release) => -1.0,
+      (Action::Release, Action::Press) => 1.0,
+      _ => 0.0
+    };
+    scene.time.update(deltaTime, scrub);
+    let sky = scene.time.sky();
+    frame.setSky(&sky, &scene.lighting);
+    fog.colour = sky.fogColour;
+    skybox.tint = sky.skyTint;
+    for shader in &[&mainShader, &terrainShader, &skyShader, &waterShader] {
+      shader.loadFog(&fog.colour, fog.density, fog.gradient);
+    }
+    if let Some(ref deferred) = deferred {
+      deferred.setFog(&fog);
+    }
+    // Drawn part way to the latest step, and put back once everything using positions is drawn
+    let current = Snapshot::capture(camera.position, &scene.entities);
+    previous.blend(&current, clock.alpha()).apply(&mut camera.position, &mut scene.entities);
+    let terrain = &scene.terrain;
+
+    let labels = unsafe {
+      let entities: Vec<&Entity> = scene.entities.iter().collect();
+      if debug.wireframe {
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+      }
+      gl::ClearColor(fog.colour.x, fog.colour.y, fog.colour.z, 1.0);
+      let forward = ForwardScene { terrain, entities: &entities, terrainShader: &terrainShader, mainShader: &mainShader, skyShader: &skyShader, skybox: &skybox };
+
+      // Reflection: mirror the camera below the water surface
+      profiler.begin("reflection");
+      water.fbos.reflection.bind();
+      let distance = 2.0 * (camera.position.y - water.height);
+      camera.position.y -= distance;
+      camera.invertPitch();
+      frame.setCamera(&camera.getViewMatrix(), &projection, &camera.position);
+      drawScene(&forward, &water.reflectionPlane(), &camera, &mut profiler);
+      camera.position.y += distance;
+      camera.invertPitch();
+      profiler.end();
+
+      // Refraction
+      profiler.begin("refraction");
       let view = camera.getViewMatrix();
-      terrain.entity.draw(&terrainShader, &view, &projection);
-      terrain.entity.drawBoundingBox(&lineShader, &boundingMesh, &view, &projection);
+      frame.setCamera(&view, &projection, &camera.position);
+      water.fbos.refraction.bind();
+      drawScene(&forward, &water.refractionPlane(), &camera, &mut profiler);
+      profiler.end();
+
+      // Main pass
+      profiler.begin("main");
+      sceneTarget.bind();
+      match deferred {
+        Some(ref deferred) => {
+          deferred.geometryPass(terrain, &entities, &camera, &mut profiler);
+          let occlusion = if scene.lighting.ssao {
+            profiler.begin("ssao");
+            ssao.compute(deferred.depthTexture(), &projection, &scene.lighting);
+            profiler.end();
+            Some(ssao.texture())
+          } else {
+            None
+          };
+          profiler.begin("lighting");
+          deferred.lightingPass(&sceneTarget, &scene.lights, &view, &projection, occlusion);
+          profiler.end();
+          profiler.begin("sky");
+          skybox.draw(&skyShader);
+          profiler.end();
+          // The G-buffer holds one surface per pixel, so transparent meshes are shaded forward on top
+          profiler.begin("transparent");
+          drawTransparent(&entities, &mainShader, &noClip, &camera);
+          profiler.end();
+        }
+        None => {
+          let forwardShaders = [&*mainShader, &*terrainShader];
+          if scene.lighting.ssao {
+            // Depth-only pre-pass, so the occlusion is ready to darken the ambient term while lighting
+            profiler.begin("ssao");
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+            gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            terrain.entity.draw(&terrainShader, &camera);
+            for entity in &entities {
+              entity.draw(&mainShader, &camera);
+            }
+            gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+            ssao.compute(sceneTarget.depthTexture, &projection, &scene.lighting);
+            ssao.bindForward(&forwardShaders, Some((width, height)));
+            profiler.end();
+          }
+          let target = msaaTarget.as_ref().unwrap_or(&sceneTarget);
+          target.bind();
+          drawScene(&forward, &noClip, &camera, &mut profiler);
+          // The water passes and the deferred path's transparent meshes use these shaders too
+          ssao.bindForward(&forwardShaders, None);
+        }
+      }
+      profiler.begin("water");
+      water.draw(&waterShader);
+      profiler.end();
+      profiler.end();
+
+      // Bounding boxes and the rest of the debug lines are queued up and drawn together
+      profiler.begin("bounding boxes");
+      if debug.showBounds {
+        terrain.entity.drawBoundingBox(&mut debugDraw, debugdraw::GREEN);
+        for entity in &entities {
+          entity.drawBoundingBox(&mut debugDraw, debugdraw::GREEN);
+          let position = vec3(entity.worldPos.x, entity.worldPos.y, entity.worldPos.z);
+          debugDraw.axes(&Matrix4::from_translation(position), 5.0);
+        }
+      }
+      if debug.showLights {
+        for (i, light) in scene.lights.iter().enumerate() {
+          if let Some(radius) = light.radius() {
+            debugDraw.sphere(light.position, radius, debugdraw::YELLOW);
+          }
+          debugDraw.text(light.position, &format!("lamp {}", i + 1), debugdraw::YELLOW);
+        }
+      }
+      editor.draw(&mut debugDraw, &entities, &camera);
+      let labels = debugDraw.flush(deltaTime, &(projection * view), (width, height));
+      profiler.end();
+
+      profiler.begin("post");
+      if let Some(ref msaaTarget) = msaaTarget {
+        msaaTarget.resolveInto(&sceneTarget);
+      }
+      gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+      postProcessor.process(&sceneTarget);
+      profiler.end();
+      labels
+    };
+
+    // HUD text scales with the framebuffer so it stays the same size on screen
+    let hudScale = height as f32 / 1080.0 * 0.75;
+    for (i, entity) in scene.entities.iter().enumerate() {
+      let (min, max) = entity.worldBounds();
+      text.drawWorld(vec3((min.x + max.x) / 2.0, max.y + 1.5, (min.z + max.z) / 2.0), &scene.name(i), debugdraw::WHITE, 1.5, &projection, &camera.getViewMatrix());
+    }
+    current.apply(&mut camera.position, &mut scene.entities);
+
+    // F1 shows and hides the debug panel
+    if keyPressed(&window, Key::F1, &mut debug.toggleHeld) {
+      ui.visible = !ui.visible;
+    }
+    // The cursor is in window coordinates, which may not match framebuffer pixels
+    let (cursorX, cursorY) = window.get_cursor_pos();
+    let input = UiInput {
+      mouse: (cursorX as f32 * width as f32 / windowWidth as f32, cursorY as f32 * height as f32 / windowHeight as f32),
+      down: window.get_mouse_button(MouseButtonLeft) == Action::Press
+    };
+    ui.begin("Debug (F1)", input, (width, height));
+    if debugPanel(&mut ui, &mut debug, &clock, &camera, &mut scene, active) {
+      frame.setLights(&scene.lights);
+    }
+    profilerPanel(&mut ui, &mut profiler);
+    ui.end();
+    ui.errorPanel(&assets.errors());
 
-      nanoEntity.draw(&mainShader, &view, &projection);
-      nanoEntity.drawBoundingBox(&lineShader, &boundingMesh, &view, &projection);
+    let fps = format!("{:.0} FPS", 1.0 / clock.average().max(1e-6));
+    let (fpsWidth, _) = text.measure(&fps, hudScale);
+    text.draw(width as f32 - fpsWidth - 16.0 * hudScale, 16.0 * hudScale, &fps, debugdraw::WHITE, hudScale);
+    if editor.enabled {
+      let mode = match editor.mode {
+        GizmoMode::Translate => "Move",
+        GizmoMode::Rotate => "Rotate",
+        GizmoMode::Scale => "Scale"
+      };
+      let help = format!("Editor (Tab): {} (1/2/3), terrain snap {} (T), undo Ctrl+Z, redo Ctrl+Y", mode, if editor.snapToTerrain { "on" } else { "off" });
+      let (_, helpHeight) = text.measure(&help, hudScale);
+      text.draw(16.0 * hudScale, height as f32 - helpHeight - 16.0 * hudScale, &help, debugdraw::WHITE, hudScale);
+    }
+    // The debug UI's bitmap font stands in when the TrueType font couldn't be loaded
+    for label in labels {
+      if text.font.is_some() {
+        text.drawCentred(label.x, label.y, &label.text, label.colour, hudScale);
+      } else {
+        ui.screenText(label.x, label.y, &label.text, label.colour);
+      }
+    }
+    profiler.begin("ui");
+    unsafe {
+      text.render();
+      ui.render();
     }
+    profiler.end();
+    profiler.endFrame();
 
+    clock.limit();
     window.swap_buffers();
     glfw.poll_events();
   }
 }
+
+struct DebugOptions {
+  showBounds: bool,
+  showLights: bool,
+  wireframe: bool,
+  selectedLight: usize,
+  toggleHeld: bool
+}
+
+/// Fills in the debug panel for `scene` and its entity `active`, returning true if a light was
+/// edited and needs uploading again
+fn debugPanel(ui: &mut DebugUi, debug: &mut DebugOptions, clock: &FrameClock, camera: &Camera, scene: &mut Scene, active: usize) -> bool {
+  let Scene { entities, lights, time, lighting, .. } = scene;
+  let average = clock.average();
+  ui.label(&format!("{:.0} fps, {:.2} ms, worst {:.2} ms", 1.0 / average.max(1e-6), average * 1000.0, clock.worst() * 1000.0));
+  // Scaled to twice the average so spikes stand out
+  ui.graph(clock.history().iter().cloned(), average * 2.0);
+  ui.label(&format!("Camera {:.1}, {:.1}, {:.1}", camera.position.x, camera.position.y, camera.position.z));
+  ui.label(&format!("Yaw {:.1}, pitch {:.1}", camera.yaw, camera.pitch));
+
+  ui.spacing();
+  ui.label(&format!("Time {}", time.clock()));
+  if ui.dragFloat("Hours", &mut time.hours, 0.02) {
+    time.hours = time.hours.rem_euclid(24.0);
+  }
+  ui.checkbox("Paused (P)", &mut time.paused);
+
+  ui.spacing();
+  ui.label("Lighting");
+  if ui.dragFloat("Ambient", &mut lighting.ambient, 0.005) {
+    lighting.ambient = lighting.ambient.max(0.0);
+  }
+  ui.checkbox("Hemisphere", &mut lighting.hemisphere);
+  if lighting.hemisphere {
+    ui.dragVector3("Sky", &mut lighting.skyColour, 0.01);
+    ui.dragVector3("Ground", &mut lighting.groundColour, 0.01);
+  }
+  ui.checkbox("SSAO", &mut lighting.ssao);
+  if lighting.ssao {
+    if ui.dragFloat("AO radius", &mut lighting.ssaoRadius, 0.02) {
+      lighting.ssaoRadius = lighting.ssaoRadius.max(0.05);
+    }
+    if ui.dragFloat("AO strength", &mut lighting.ssaoIntensity, 0.01) {
+      lighting.ssaoIntensity = lighting.ssaoIntensity.max(0.0);
+    }
+  }
+
+  if let Some(entity) = entities.get_mut(active) {
+    ui.spacing();
+    ui.label("Entity");
+    let mut position = vec3(entity.worldPos.x, entity.worldPos.y, entity.worldPos.z);
+    if ui.dragVector3("Position", &mut position, 0.1) {
+      entity.worldPos = Point3::new(position.x, position.y, position.z);
+    }
+    let mut rotation = vec3(Deg::from(entity.orientation.x).0, Deg::from(entity.orientation.y).0, Deg::from(entity.orientation.z).0);
+    if ui.dragVector3("Rotation", &mut rotation, 0.5) {
+      entity.orientation = vec3(Rad::from(Deg(rotation.x)), Rad::from(Deg(rotation.y)), Rad::from(Deg(rotation.z)));
+    }
+    if ui.dragFloat("Scale", &mut entity.scale, 0.01) {
+      entity.scale = entity.scale.max(0.01);
+    }
+  }
+
+  let mut changed = false;
+  if !lights.is_empty() {
+    ui.spacing();
+    ui.label(&format!("Lamp {}/{}", debug.selectedLight + 1, lights.len()));
+    if ui.button("Next light") {
+      debug.selectedLight = (debug.selectedLight + 1) % lights.len();
+    }
+    let light = &mut lights[debug.selectedLight];
+    changed |= ui.dragVector3("Colour", &mut light.colour, 0.01);
+    changed |= ui.dragVector3("Light pos", &mut light.position, 1.0);
+    changed |= ui.dragVector3("Attenuation", &mut light.attenuation, 0.0005);
+    if changed {
+      light.colour = vec3(light.colour.x.max(0.0), light.colour.y.max(0.0), light.colour.z.max(0.0));
+      light.attenuation = vec3(light.attenuation.x.max(0.0), light.attenuation.y.max(0.0), light.attenuation.z.max(0.0));
+    }
+  }
+
+  ui.spacing();
+  ui.checkbox("Bounds", &mut debug.showBounds);
+  ui.checkbox("Light radii", &mut debug.showLights);
+  ui.checkbox("Wireframe", &mut debug.wireframe);
+  changed
+}
+
+/// True on the frame `key` goes down, with `held` tracking it between frames
+fn keyPressed(window: &glfw::Window, key: Key, held: &mut bool) -> bool {
+  let down = window.get_key(key) == Action::Press;
+  let pressed = down && !*held;
+  *held = down;
+  pressed
+}
+
+/// `cargo run -- bake [model.obj...]` writes a mesh cache next to each OBJ, defaulting to the nanosuit
+fn bakeMeshCaches(paths: &[String]) {
+  let paths = if paths.is_empty() { vec![NANOSUIT.to_string()] } else { paths.to_vec() };
+  for path in paths {
+    let result = Model::parse(&path).and_then(|meshes| meshcache::write(&path, &meshes).map(|_| meshes.len()));
+    match result {
+      Ok(count) => println!("Baked {} meshes from {} into {}", count, path, meshcache::cachePath(&path).display()),
+      Err(e) => println!("{}", e)
+    }
+  }
+}
+
+/// Shaders owned by the renderers rather than the asset manager
+fn rendererShaders<'a>(postProcessor: &'a PostProcessor, deferred: &'a Option<DeferredRenderer>, ssao: &'a Ssao) -> Vec<&'a Shader> {
+  let mut shaders = postProcessor.shaders();
+  shaders.extend(ssao.shaders());
+  if let Some(ref deferred) = deferred {
+    shaders.extend(deferred.shaders());
+  }
+  shaders
+}
+
+fn watchAssets(watcher: &mut FileWatcher, assets: &AssetManager, shaders: &[&Shader]) {
+  for file in assets.files().into_iter().chain(shaders.iter().flat_map(|s| s.sourcePaths())) {
+    watcher.watch(file);
+  }
+}
+
+/// Average CPU and GPU milliseconds for each profiled pass, nested passes indented
+fn profilerPanel(ui: &mut DebugUi, profiler: &mut Profiler) {
+  ui.spacing();
+  ui.checkbox("Profiler", &mut profiler.enabled);
+  if !profiler.enabled {
+    return;
+  }
+  ui.label(&format!("{:<24}{:>8}{:>8}", "Pass (F10 export)", "CPU ms", "GPU ms"));
+  for pass in profiler.summary() {
+    let name = format!("{}{}", "  ".repeat(pass.depth), pass.name);
+    ui.label(&format!("{:<24}{:>8.2}{:>8.2}", name, pass.cpu * 1000.0, pass.gpu * 1000.0));
+  }
+}
+
+/// Everything `drawScene` draws, the same for the reflection, refraction and main passes
+struct ForwardScene<'a> {
+  terrain: &'a Terrain,
+  entities: &'a [&'a Entity],
+  terrainShader: &'a Shader,
+  mainShader: &'a Shader,
+  skyShader: &'a Shader,
+  skybox: &'a Skybox
+}
+
+unsafe fn drawScene(scene: &ForwardScene, plane: &Vector4, camera: &Camera, profiler: &mut Profiler) {
+  let ForwardScene { terrain, entities, terrainShader, mainShader, skyShader, skybox } = *scene;
+  gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+  gl::Enable(gl::CLIP_DISTANCE0);
+
+  profiler.begin("terrain");
+  terrainShader.loadClipPlane(plane);
+  terrain.entity.draw(terrainShader, camera);
+  profiler.end();
+
+  profiler.begin("entities");
+  mainShader.loadClipPlane(plane);
+  for entity in entities {
+    entity.draw(mainShader, camera);
+  }
+  profiler.end();
+
+  // The sky and water shaders don't write gl_ClipDistance
+  gl::Disable(gl::CLIP_DISTANCE0);
+  profiler.begin("sky");
+  skybox.draw(skyShader);
+  profiler.end();
+
+  profiler.begin("transparent");
+  drawTransparent(entities, mainShader, plane, camera);
+  profiler.end();
+}
+
+/// Blends the transparent meshes of every entity over the scene, furthest first
+unsafe fn drawTransparent(entities: &[&Entity], shader: &Shader, plane: &Vector4, camera: &Camera) {
+  let mut meshes: Vec<(f32, &Entity, usize)> = entities.iter()
+    .flat_map(|entity| entity.transparentMeshes(&camera.position).into_iter().map(move |(distance, i)| (distance, *entity, i)))
+    .collect();
+  if meshes.is_empty() {
+    return;
+  }
+  meshes.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+  gl::Enable(gl::CLIP_DISTANCE0);
+  gl::Enable(gl::BLEND);
+  gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+  gl::DepthMask(gl::FALSE);
+  shader.loadClipPlane(plane);
+  // The occlusion was worked out from the opaque surfaces behind these
+  shader.setBool(c_str!("useAmbientOcclusion"), false);
+  for (_, entity, i) in meshes {
+    entity.drawMesh(shader, i, camera);
+  }
+  gl::DepthMask(gl::TRUE);
+  gl::Disable(gl::BLEND);
+  gl::Disable(gl::CLIP_DISTANCE0);
+}
@@ -2,8 +2,7 @@
 #![allow(dead_code)]
 use gl;
 
-use std::ffi::CString;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::mem::size_of;
 use std::os::raw::c_void;
 use std::ptr;
@@ -11,8 +10,11 @@ use std::ptr;
 use cgmath::prelude::*;
 use cgmath::vec3;
 
+use crate::assets::TextureHandle;
+use crate::utils::maths::computeBoundingBox;
 use crate::utils::shader::Shader;
-use crate::{c_str, offset_of};
+use crate::c_str;
+use crate::offset_of;
 use crate::types::*;
 
 #[repr(C)]
@@ -35,73 +37,86 @@ impl Default for Vertex {
 
 #[derive(Clone)]
 pub struct Texture {
-  pub id: u32,
-  pub type_: String,
-  pub path: String,
+  pub resource: TextureHandle,
+  pub type_: String
+}
+
+/// Surface parameters from an MTL file. Texture maps take the place of the matching colour
+#[derive(Clone, Copy)]
+pub struct Material {
+  pub diffuse: Vector3,
+  pub specular: Vector3,
+  pub shininess: f32,
+  /// MTL `d`, meshes below 1 are drawn in the sorted transparent pass
+  pub opacity: f32
+}
+
+impl Default for Material {
+  fn default() -> Self {
+    Material {
+      diffuse: vec3(0.8, 0.8, 0.8),
+      specular: vec3(0.5, 0.5, 0.5),
+      shininess: 32.0,
+      opacity: 1.0
+    }
+  }
+}
+
+/// Range of the element buffer holding one level of detail
+#[derive(Clone, Copy)]
+pub struct Lod {
+  pub offset: usize,
+  pub count: usize
 }
 
 pub struct Mesh {
   pub vertices: Vec<Vertex>,
   pub indices: Vec<u32>,
+  /// Full detail first, then each simplified index list stored after it in the same buffer
+  pub lods: Vec<Lod>,
   pub textures: Vec<Texture>,
+  /// Only set for loaded models, meshes built in code are drawn with shaders that have no material
+  pub material: Option<Material>,
+  /// Object space min and max corners
+  pub bounds: (Vector4, Vector4),
   VAO: u32,
   VBO: u32,
   EBO: u32
 }
 
-#[repr(C)]
+/// A ray through the scene for picking, from `start` towards `end`
 pub struct Line {
-  pub coords: Vec<Vector3>,
-  pub dir: Vector3,
-  VAO: u32,
-  VBO: u32
+  pub start: Vector3,
+  pub end: Vector3,
+  pub dir: Vector3
 }
 
 impl Line {
   pub fn new(start: Vector3, end: Vector3) -> Line {
-    let dir = end - start;
-    let mut line = Line { coords: vec![start, end], dir: dir.normalize(), VAO: 0, VBO: 0 };
-    unsafe { line.setupLine() };
-    line
-  }
-
-  pub unsafe fn draw(&self, shader: &Shader, view: &Matrix4, projection: &Matrix4) {
-    let model = Matrix4::from_translation(vec3(0.0,0.0,0.0));
-    shader.useProgram();
-    shader.setMat4(c_str!("model"), &model);
-    shader.setMat4(c_str!("view"), view);
-    shader.setMat4(c_str!("projection"), projection);
-    gl::BindVertexArray(self.VAO);
-    gl::DrawArrays(gl::LINES, 0, self.coords.len() as i32);
-    gl::BindVertexArray(0);
-  }
-
-  unsafe fn setupLine(&mut self) {
-    gl::GenVertexArrays(1, &mut self.VAO);
-    gl::GenBuffers(1, &mut self.VBO);
-
-    gl::BindVertexArray(self.VAO);
-    gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
-
-    let size = (self.coords.len() * size_of::<Vector3>()) as isize;
-    let data = &self.coords[0] as *const Vector3 as *const c_void;
-    gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-
-    let size = size_of::<Vector3>() as i32;
-    gl::EnableVertexAttribArray(0);
-    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, size, 0 as *const c_void);
-    gl::BindVertexArray(0);
+    Line { start, end, dir: (end - start).normalize() }
   }
 }
 
 impl Mesh {
   pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>) -> Mesh {
-    let mut mesh = Mesh { vertices, indices, textures, VAO: 0, VBO: 0, EBO: 0 };
-    unsafe { mesh.setupMesh() }
+    let bounds = computeBoundingBox(&vertices);
+    Mesh::withLods(vertices, indices, vec![], textures, bounds)
+  }
+
+  /// For loaded meshes, whose bounds and simplified index lists were worked out off the main thread
+  pub fn withLods(vertices: Vec<Vertex>, indices: Vec<u32>, lods: Vec<Vec<u32>>, textures: Vec<Texture>, bounds: (Vector4, Vector4)) -> Mesh {
+    let mut offset = 0;
+    let ranges = Some(&indices).into_iter().chain(lods.iter()).map(|l| {
+      let lod = Lod { offset, count: l.len() };
+      offset += l.len();
+      lod
+    }).collect();
+    let mut mesh = Mesh { vertices, indices, lods: ranges, textures, material: None, bounds, VAO: 0, VBO: 0, EBO: 0 };
+    unsafe { mesh.setupMesh(&lods) }
     mesh
   }
 
-  unsafe fn setupMesh(&mut self) {
+  unsafe fn setupMesh(&mut self, lods: &[Vec<u32>]) {
     gl::GenVertexArrays(1, &mut self.VAO);
     gl::GenBuffers(1, &mut self.VBO);
     gl::GenBuffers(1, &mut self.EBO);
@@ -113,8 +128,12 @@ impl Mesh {
     gl::BufferData(gl::ARRAY_BUFFER, vSize, vData, gl::STATIC_DRAW);
 
     gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.EBO);
-    let eSize = (self.indices.len() * size_of::<u32>()) as isize;
-    let eData = &self.indices[0] as *const u32 as *const c_void;
+    let mut elements = self.indices.clone();
+    for lod in lods {
+      elements.extend_from_slice(lod);
+    }
+    let eSize = (elements.len() * size_of::<u32>()) as isize;
+    let eData = &elements[0] as *const u32 as *const c_void;
     gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, eSize, eData, gl::STATIC_DRAW);
 
     let attribSize = size_of::<Vertex>() as i32;
@@ -127,19 +146,21 @@ impl Mesh {
     gl::BindVertexArray(0);
   }
 
-  pub unsafe fn drawBoundingBox(&self) {
-    gl::BindVertexArray(self.VAO);
-    gl::DrawElements(gl::LINE_LOOP, 4, gl::UNSIGNED_INT, ptr::null());
-    gl::DrawElements(gl::LINE_LOOP, 4, gl::UNSIGNED_INT, (4 * size_of::<u32>() as isize) as *const c_void);
-    gl::DrawElements(gl::LINES, 8, gl::UNSIGNED_INT, (8 * size_of::<u32>() as isize) as *const c_void);
-    gl::BindVertexArray(0);
+  pub fn isTransparent(&self) -> bool {
+    self.material.is_some_and(|m| m.opacity < 1.0)
   }
 
   pub unsafe fn draw(&self, shader: &Shader) {
+    self.drawLod(shader, 0);
+  }
+
+  /// Draws level `lod`, or the simplest there is if the mesh has fewer levels
+  pub unsafe fn drawLod(&self, shader: &Shader, lod: usize) {
     let mut diffuseNr = 0;
     let mut specularNr = 0;
     let mut normalNr = 0;
     let mut heightNr = 0;
+    let mut alphaNr = 0;
     let mut textureNr = 0;
     for (i, texture) in self.textures.iter().enumerate() {
       gl::ActiveTexture(gl::TEXTURE0 + i as u32); // active proper texture unit before binding
@@ -162,6 +183,10 @@ impl Mesh {
           heightNr += 1;
           heightNr
         }
+        "texture_alpha" => {
+          alphaNr += 1;
+          alphaNr
+        }
         "textureSampler" => {
           textureNr += 1;
           textureNr
@@ -170,14 +195,31 @@ impl Mesh {
       };
 
       let sampler = CString::new(format!("{}{}", name, number)).unwrap();
-      gl::Uniform1i(gl::GetUniformLocation(shader.ID, sampler.as_ptr()), i as i32);
-      gl::BindTexture(gl::TEXTURE_2D, texture.id);
+      shader.setInt(&sampler, i as i32);
+      gl::BindTexture(gl::TEXTURE_2D, texture.resource.id);
+    }
+    if let Some(ref material) = self.material {
+      shader.loadMaterial(material);
+      shader.setBool(c_str!("hasDiffuseMap"), diffuseNr > 0);
+      shader.setBool(c_str!("hasSpecularMap"), specularNr > 0);
+      shader.setBool(c_str!("hasAlphaMap"), alphaNr > 0);
     }
 
+    let lod = self.lods[lod.min(self.lods.len() - 1)];
     gl::BindVertexArray(self.VAO);
-    gl::DrawElements(gl::TRIANGLES, self.indices.len() as i32, gl::UNSIGNED_INT, ptr::null());
+    gl::DrawElements(gl::TRIANGLES, lod.count as i32, gl::UNSIGNED_INT, (lod.offset * size_of::<u32>()) as *const c_void);
     gl::BindVertexArray(0);
     gl::BindTexture(gl::TEXTURE_2D, 0);
     gl::ActiveTexture(gl::TEXTURE0);
   }
 }
+
+impl Drop for Mesh {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteVertexArrays(1, &self.VAO);
+      gl::DeleteBuffers(1, &self.VBO);
+      gl::DeleteBuffers(1, &self.EBO);
+    }
+  }
+}
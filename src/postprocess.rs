@@ -0,0 +1,232 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ffi::CStr;
+
+use cgmath::vec2;
+
+use crate::utils::common::{lutFromFile, neutralLut};
+use crate::utils::shader::Shader;
+use crate::utils::target::{RenderTarget, DepthAttachment, RGBA16F};
+use crate::c_str;
+
+const FULLSCREEN_VERTEX: &str = "src/shaders/fullscreenVertex.vs";
+
+#[derive(Clone)]
+pub enum PostEffect {
+  /// Extracts pixels brighter than `threshold`, blurs them at half resolution and adds them back
+  Bloom { threshold: f32, intensity: f32, blurPasses: u32 },
+  /// Remaps colours through a 3D LUT loaded from a strip image
+  ColourGrading { lut: String },
+  Vignette { radius: f32, softness: f32 },
+  /// Exposure tone mapping followed by gamma correction
+  GammaCorrection { gamma: f32, exposure: f32 },
+  Fxaa
+}
+
+struct BloomStage {
+  brightShader: Shader,
+  blurShader: Shader,
+  targets: [RenderTarget; 2]
+}
+
+struct PostPass {
+  effect: PostEffect,
+  shader: Shader,
+  lut: (u32, i32),
+  bloom: Option<BloomStage>
+}
+
+/// A chain of fullscreen passes run over the scene texture, the last one drawing to the window
+pub struct PostProcessor {
+  passes: Vec<PostPass>,
+  targets: [RenderTarget; 2],
+  width: i32,
+  height: i32,
+  VAO: u32
+}
+
+impl PostPass {
+  fn new(effect: PostEffect, width: i32, height: i32) -> PostPass {
+    let fragmentPath = match effect {
+      PostEffect::Bloom { .. } => "src/shaders/bloomFragment.fs",
+      PostEffect::ColourGrading { .. } => "src/shaders/gradingFragment.fs",
+      PostEffect::Vignette { .. } => "src/shaders/vignetteFragment.fs",
+      PostEffect::GammaCorrection { .. } => "src/shaders/gammaFragment.fs",
+      PostEffect::Fxaa => "src/shaders/fxaaFragment.fs"
+    };
+
+    let lut = match effect {
+      PostEffect::ColourGrading { ref lut } => unsafe {
+        lutFromFile(lut).unwrap_or_else(|e| {
+          println!("{}", e);
+          neutralLut()
+        })
+      },
+      _ => (0, 0)
+    };
+
+    let bloom = match effect {
+      PostEffect::Bloom { .. } => Some(BloomStage {
+        brightShader: Shader::new(FULLSCREEN_VERTEX, "src/shaders/brightFragment.fs"),
+        blurShader: Shader::new(FULLSCREEN_VERTEX, "src/shaders/blurFragment.fs"),
+        targets: [
+          RenderTarget::new(width / 2, height / 2, &[RGBA16F], DepthAttachment::None),
+          RenderTarget::new(width / 2, height / 2, &[RGBA16F], DepthAttachment::None)
+        ]
+      }),
+      _ => None
+    };
+
+    PostPass { effect, shader: Shader::new(FULLSCREEN_VERTEX, fragmentPath), lut, bloom }
+  }
+
+  unsafe fn run(&self, input: u32, width: i32, height: i32) {
+    let texelSize = vec2(1.0 / width as f32, 1.0 / height as f32);
+    self.shader.useProgram();
+    self.shader.setInt(c_str!("screenTexture"), 0);
+    match self.effect {
+      PostEffect::Bloom { intensity, .. } => {
+        self.shader.setInt(c_str!("bloomTexture"), 1);
+        self.shader.setFloat(c_str!("intensity"), intensity);
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.bloom.as_ref().unwrap().targets[0].colourTexture());
+      }
+      PostEffect::ColourGrading { .. } => {
+        self.shader.setInt(c_str!("lut"), 1);
+        self.shader.setFloat(c_str!("lutSize"), self.lut.1 as f32);
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_3D, self.lut.0);
+      }
+      PostEffect::Vignette { radius, softness } => {
+        self.shader.setFloat(c_str!("radius"), radius);
+        self.shader.setFloat(c_str!("softness"), softness);
+      }
+      PostEffect::GammaCorrection { gamma, exposure } => {
+        self.shader.setFloat(c_str!("gamma"), gamma);
+        self.shader.setFloat(c_str!("exposure"), exposure);
+      }
+      PostEffect::Fxaa => self.shader.setVector2(c_str!("texelSize"), &texelSize)
+    }
+
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::BindTexture(gl::TEXTURE_2D, input);
+    gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+    gl::ActiveTexture(gl::TEXTURE1);
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+    gl::BindTexture(gl::TEXTURE_3D, 0);
+    gl::ActiveTexture(gl::TEXTURE0);
+  }
+}
+
+impl BloomStage {
+  /// Leaves the blurred highlights in `targets[0]`
+  unsafe fn run(&self, input: u32, threshold: f32, blurPasses: u32) {
+    self.targets[0].bind();
+    self.brightShader.useProgram();
+    self.brightShader.setInt(c_str!("screenTexture"), 0);
+    self.brightShader.setFloat(c_str!("threshold"), threshold);
+    gl::BindTexture(gl::TEXTURE_2D, input);
+    gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+    let texelSize = vec2(1.0 / self.targets[0].width as f32, 1.0 / self.targets[0].height as f32);
+    self.blurShader.useProgram();
+    self.blurShader.setInt(c_str!("screenTexture"), 0);
+    self.blurShader.setVector2(c_str!("texelSize"), &texelSize);
+    for _ in 0..blurPasses {
+      self.targets[1].bind();
+      self.blurShader.setBool(c_str!("horizontal"), true);
+      gl::BindTexture(gl::TEXTURE_2D, self.targets[0].colourTexture());
+      gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+      self.targets[0].bind();
+      self.blurShader.setBool(c_str!("horizontal"), false);
+      gl::BindTexture(gl::TEXTURE_2D, self.targets[1].colourTexture());
+      gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    }
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+  }
+}
+
+impl PostProcessor {
+  pub fn shaders(&self) -> Vec<&Shader> {
+    let mut shaders = vec![];
+    for pass in &self.passes {
+      shaders.push(&pass.shader);
+      if let Some(ref bloom) = pass.bloom {
+        shaders.push(&bloom.brightShader);
+        shaders.push(&bloom.blurShader);
+      }
+    }
+    shaders
+  }
+
+  pub fn new(width: i32, height: i32, effects: Vec<PostEffect>) -> PostProcessor {
+    let passes = effects.into_iter().map(|e| PostPass::new(e, width, height)).collect();
+    let targets = [
+      RenderTarget::new(width, height, &[RGBA16F], DepthAttachment::None),
+      RenderTarget::new(width, height, &[RGBA16F], DepthAttachment::None)
+    ];
+
+    // Core profile needs a bound VAO even though the fullscreen triangle has no attributes
+    let mut VAO = 0;
+    unsafe { gl::GenVertexArrays(1, &mut VAO) };
+    PostProcessor { passes, targets, width, height, VAO }
+  }
+
+  /// Matches the intermediate targets to a new window size
+  pub fn resize(&mut self, width: i32, height: i32) {
+    self.width = width;
+    self.height = height;
+    for target in self.targets.iter_mut() {
+      target.resize(width, height);
+    }
+    for bloom in self.passes.iter_mut().filter_map(|p| p.bloom.as_mut()) {
+      for target in bloom.targets.iter_mut() {
+        target.resize(width / 2, height / 2);
+      }
+    }
+  }
+
+  /// Runs every pass in order starting from `scene`'s colour texture, ping-ponging between
+  /// intermediate targets and drawing the final pass to the default framebuffer
+  pub unsafe fn process(&self, scene: &RenderTarget) {
+    gl::Disable(gl::DEPTH_TEST);
+    gl::BindVertexArray(self.VAO);
+
+    let mut input = scene.colourTexture();
+    for (i, pass) in self.passes.iter().enumerate() {
+      if let (Some(bloom), PostEffect::Bloom { threshold, blurPasses, .. }) = (&pass.bloom, &pass.effect) {
+        bloom.run(input, *threshold, *blurPasses);
+      }
+
+      let output = &self.targets[i % 2];
+      if i == self.passes.len() - 1 {
+        RenderTarget::bindDefault(self.width, self.height);
+      } else {
+        output.bind();
+      }
+
+      pass.run(input, self.width, self.height);
+      input = output.colourTexture();
+    }
+
+    // No passes configured: copy the scene straight to the window
+    if self.passes.is_empty() {
+      gl::BindFramebuffer(gl::READ_FRAMEBUFFER, scene.FBO);
+      gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+      gl::BlitFramebuffer(0, 0, scene.width, scene.height, 0, 0, self.width, self.height, gl::COLOR_BUFFER_BIT, gl::LINEAR);
+      RenderTarget::bindDefault(self.width, self.height);
+    }
+
+    gl::BindVertexArray(0);
+    gl::Enable(gl::DEPTH_TEST);
+  }
+}
+
+impl Drop for PostProcessor {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteVertexArrays(1, &self.VAO) }
+  }
+}
@@ -0,0 +1,317 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::time::Instant;
+
+pub const PROFILE_FILE: &str = "profile.json";
+/// Frames the averages cover and a trace export holds
+const FRAMES: usize = 120;
+// Frames of queries left in flight before waiting on the oldest, so reading them rarely stalls
+const MAX_PENDING: usize = 4;
+const CPU_THREAD: u32 = 1;
+const GPU_THREAD: u32 = 2;
+
+/// One timed scope within a frame. Times are in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+  pub name: &'static str,
+  /// 0 for scopes opened outside any other
+  pub depth: usize,
+  /// From the profiler's creation
+  pub start: f64,
+  pub cpu: f64,
+  /// Includes nested scopes, like `cpu`
+  pub gpu: f64
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Frame {
+  start: f64,
+  end: f64,
+  samples: Vec<Sample>
+}
+
+/// A frame waiting on its timer queries, each paired with the sample it times
+struct Pending {
+  frame: Frame,
+  queries: Vec<(usize, u32)>
+}
+
+/// Average time per frame spent in one scope
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassStats {
+  pub name: &'static str,
+  pub depth: usize,
+  pub cpu: f64,
+  pub gpu: f64
+}
+
+/// Nested CPU timers with a `GL_TIME_ELAPSED` query alongside each, opened and closed with
+/// `begin` and `end` between `beginFrame` and `endFrame`. Only one time elapsed query can run at
+/// once, so a scope's query is paused while a nested one runs and its pieces are added up later.
+/// Results arrive a few frames late rather than stalling on the GPU
+pub struct Profiler {
+  /// Takes effect from the next frame
+  pub enabled: bool,
+  recording: bool,
+  epoch: Instant,
+  frame: Frame,
+  queries: Vec<(usize, u32)>,
+  /// Open scopes, innermost last, as sample index and start time
+  open: Vec<(usize, Instant)>,
+  /// The query timing the innermost open scope
+  running: Option<u32>,
+  pending: VecDeque<Pending>,
+  spare: Vec<u32>,
+  frames: VecDeque<Frame>
+}
+
+impl Profiler {
+  pub fn new() -> Profiler {
+    Profiler {
+      enabled: true,
+      recording: false,
+      epoch: Instant::now(),
+      frame: Frame { start: 0.0, end: 0.0, samples: vec![] },
+      queries: vec![],
+      open: vec![],
+      running: None,
+      pending: VecDeque::new(),
+      spare: vec![],
+      frames: VecDeque::with_capacity(FRAMES)
+    }
+  }
+
+  pub fn beginFrame(&mut self) {
+    self.recording = self.enabled;
+    if self.recording {
+      self.frame = Frame { start: self.now(), end: 0.0, samples: vec![] };
+    }
+  }
+
+  pub fn begin(&mut self, name: &'static str) {
+    if !self.recording {
+      return;
+    }
+    self.stopQuery();
+    let sample = self.frame.samples.len();
+    self.frame.samples.push(Sample { name, depth: self.open.len(), start: self.now(), cpu: 0.0, gpu: 0.0 });
+    self.open.push((sample, Instant::now()));
+    self.startQuery(sample);
+  }
+
+  /// Closes the innermost open scope
+  pub fn end(&mut self) {
+    if !self.recording {
+      return;
+    }
+    let (sample, started) = match self.open.pop() {
+      Some(scope) => scope,
+      None => {
+        println!("Profiler: end without begin");
+        return;
+      }
+    };
+    self.frame.samples[sample].cpu = started.elapsed().as_secs_f64();
+    self.stopQuery();
+    if let Some(&(parent, _)) = self.open.last() {
+      self.startQuery(parent);
+    }
+  }
+
+  /// Hands the frame over to wait for its queries, and collects any earlier frames now ready
+  pub fn endFrame(&mut self) {
+    if self.recording {
+      if !self.open.is_empty() {
+        println!("Profiler: {} scopes left open", self.open.len());
+        while !self.open.is_empty() {
+          self.end();
+        }
+      }
+      self.frame.end = self.now();
+      let frame = std::mem::replace(&mut self.frame, Frame { start: 0.0, end: 0.0, samples: vec![] });
+      self.pending.push_back(Pending { frame, queries: std::mem::take(&mut self.queries) });
+    }
+    self.collect();
+  }
+
+  /// Per scope averages over the last frames, in the order the scopes first ran. Scopes are told
+  /// apart by name and by the scopes they're nested in
+  pub fn summary(&self) -> Vec<PassStats> {
+    let mut paths: Vec<Vec<&'static str>> = vec![];
+    let mut stats: Vec<PassStats> = vec![];
+    for frame in &self.frames {
+      let mut path: Vec<&'static str> = vec![];
+      for sample in &frame.samples {
+        path.truncate(sample.depth);
+        path.push(sample.name);
+        let i = match paths.iter().position(|p| *p == path) {
+          Some(i) => i,
+          None => {
+            paths.push(path.clone());
+            stats.push(PassStats { name: sample.name, depth: sample.depth, cpu: 0.0, gpu: 0.0 });
+            stats.len() - 1
+          }
+        };
+        stats[i].cpu += sample.cpu;
+        stats[i].gpu += sample.gpu;
+      }
+    }
+    let count = self.frames.len().max(1) as f64;
+    for stat in stats.iter_mut() {
+      stat.cpu /= count;
+      stat.gpu /= count;
+    }
+    stats
+  }
+
+  /// Writes the kept frames as a Chrome trace, for chrome://tracing or Perfetto
+  pub fn exportTrace(&self, path: &str) -> io::Result<()> {
+    fs::write(path, self.traceJson())
+  }
+
+  /// CPU scopes and GPU times on separate tracks. Time elapsed queries only give durations, so
+  /// each GPU event is drawn from the moment its scope began on the CPU
+  fn traceJson(&self) -> String {
+    let mut events = vec![
+      format!("{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"CPU\"}}}}", CPU_THREAD),
+      format!("{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"GPU\"}}}}", GPU_THREAD)
+    ];
+    let event = |name: &str, start: f64, duration: f64, thread: u32| {
+      let mut json = String::new();
+      write!(json, "{{\"name\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":{}}}", name, start * 1e6, duration * 1e6, thread).unwrap();
+      json
+    };
+    for frame in &self.frames {
+      events.push(event("frame", frame.start, frame.end - frame.start, CPU_THREAD));
+      for sample in &frame.samples {
+        events.push(event(sample.name, sample.start, sample.cpu, CPU_THREAD));
+        events.push(event(sample.name, sample.start, sample.gpu, GPU_THREAD));
+      }
+    }
+    format!("{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+  }
+
+  fn now(&self) -> f64 {
+    self.epoch.elapsed().as_secs_f64()
+  }
+
+  fn startQuery(&mut self, sample: usize) {
+    let query = self.spare.pop().unwrap_or_else(|| {
+      let mut query = 0;
+      unsafe { gl::GenQueries(1, &mut query) };
+      query
+    });
+    unsafe { gl::BeginQuery(gl::TIME_ELAPSED, query) };
+    self.queries.push((sample, query));
+    self.running = Some(query);
+  }
+
+  fn stopQuery(&mut self) {
+    if self.running.take().is_some() {
+      unsafe { gl::EndQuery(gl::TIME_ELAPSED) };
+    }
+  }
+
+  /// Reads back finished frames, oldest first, only waiting once too many are queued
+  fn collect(&mut self) {
+    while let Some(pending) = self.pending.front() {
+      let ready = match pending.queries.last() {
+        Some(&(_, query)) => {
+          let mut available = 0;
+          unsafe { gl::GetQueryObjectiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available) };
+          available != 0
+        }
+        None => true
+      };
+      if !ready && self.pending.len() <= MAX_PENDING {
+        return;
+      }
+
+      let Pending { mut frame, queries } = self.pending.pop_front().unwrap();
+      for (sample, query) in queries {
+        let mut nanoseconds = 0;
+        unsafe { gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut nanoseconds) };
+        frame.samples[sample].gpu += nanoseconds as f64 * 1e-9;
+        self.spare.push(query);
+      }
+      includeNested(&mut frame.samples);
+      self.record(frame);
+    }
+  }
+
+  fn record(&mut self, frame: Frame) {
+    if self.frames.len() == FRAMES {
+      self.frames.pop_front();
+    }
+    self.frames.push_back(frame);
+  }
+}
+
+impl Drop for Profiler {
+  fn drop(&mut self) {
+    self.stopQuery();
+    let queries: Vec<u32> = self.spare.iter().cloned()
+      .chain(self.queries.iter().map(|q| q.1))
+      .chain(self.pending.iter().flat_map(|p| p.queries.iter().map(|q| q.1)))
+      .collect();
+    if !queries.is_empty() {
+      unsafe { gl::DeleteQueries(queries.len() as i32, queries.as_ptr()) }
+    }
+  }
+}
+
+/// Turns each sample's own GPU time into its total, adding in the scopes nested inside it.
+/// Samples are in the order they began, so a scope's nested scopes follow it directly
+fn includeNested(samples: &mut [Sample]) {
+  let own: Vec<f64> = samples.iter().map(|s| s.gpu).collect();
+  for i in 0..samples.len() {
+    let depth = samples[i].depth;
+    let nested = samples[i + 1..].iter().take_while(|s| s.depth > depth).count();
+    samples[i].gpu = own[i..=i + nested].iter().sum();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample(name: &'static str, depth: usize, start: f64, cpu: f64, gpu: f64) -> Sample {
+    Sample { name, depth, start, cpu, gpu }
+  }
+
+  #[test]
+  fn nestedGpuTimeAddsUp() {
+    let mut samples = vec![sample("scene", 0, 0.0, 4.0, 1.0), sample("terrain", 1, 0.0, 1.0, 2.0), sample("entities", 1, 1.0, 2.0, 3.0), sample("post", 0, 4.0, 1.0, 0.5)];
+    includeNested(&mut samples);
+    let gpu: Vec<f64> = samples.iter().map(|s| s.gpu).collect();
+    assert_eq!(gpu, vec![6.0, 2.0, 3.0, 0.5]);
+  }
+
+  #[test]
+  fn summaryAndTrace() {
+    let mut profiler = Profiler::new();
+    for i in 0..FRAMES + 1 {
+      let cpu = if i % 2 == 0 { 1.0 } else { 3.0 };
+      // The same name nested somewhere else is a separate pass
+      profiler.record(Frame { start: 0.0, end: 5.0, samples: vec![sample("reflection", 0, 0.0, 4.0, 4.0), sample("terrain", 1, 0.0, 1.0, 1.0), sample("terrain", 0, 4.0, cpu, 2.0)] });
+    }
+    assert_eq!(profiler.frames.len(), FRAMES);
+    let summary = profiler.summary();
+    assert_eq!(summary, vec![
+      PassStats { name: "reflection", depth: 0, cpu: 4.0, gpu: 4.0 },
+      PassStats { name: "terrain", depth: 1, cpu: 1.0, gpu: 1.0 },
+      PassStats { name: "terrain", depth: 0, cpu: 2.0, gpu: 2.0 }
+    ]);
+
+    let trace = profiler.traceJson();
+    assert!(trace.starts_with("{\"displayTimeUnit\":\"ms\",\"traceEvents\":["));
+    assert!(trace.contains("{\"name\":\"terrain\",\"ph\":\"X\",\"ts\":4000000.000,\"dur\":2000000.000,\"pid\":1,\"tid\":2}"));
+    // Two thread names, then a frame event and two per sample for each frame
+    assert_eq!(trace.matches("\"ph\"").count(), 2 + FRAMES * 7);
+  }
+}
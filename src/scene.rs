@@ -0,0 +1,447 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use cgmath::{vec3, Rad};
+
+use crate::assets::{AssetManager, PendingModel};
+use crate::camera::Camera;
+use crate::daynight::{TimeOfDay, NOON};
+use crate::entity::Entity;
+use crate::light::{Light, LightingSettings};
+use crate::terrain::{Terrain, SIZE};
+use crate::utils::error::AssetError;
+use crate::types::*;
+
+/// Format written by `SceneFile::write`. Versions before 3 had a fixed sun as the first light
+/// rather than a time of day, and lighting settings were added in version 4
+pub const SCENE_VERSION: u32 = 4;
+/// The first scene format, there has never been a version 1. Orientations are in radians in every version
+const OLDEST_VERSION: u32 = 2;
+pub const SCENE_FILE: &str = "saved.scene";
+
+const DEFAULT_HEIGHTMAP: &str = "resources/textures/heightmap.png";
+pub const NANOSUIT: &str = "resources/objects/nanosuit/nanosuit.obj";
+const LAMP_COUNT: usize = 12;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainSource {
+  pub heightmap: String,
+  pub position: Vector3
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraPose {
+  pub position: Vector3,
+  /// Degrees, as the camera keeps them
+  pub yaw: f32,
+  pub pitch: f32,
+  pub zoom: f32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityRecord {
+  pub model: String,
+  pub position: Vector3,
+  /// Radians about x, y and z
+  pub orientation: Vector3,
+  pub scale: f32,
+  pub speed: f32
+}
+
+/// Everything needed to rebuild the world as it was saved, as plain data. On disk it's a
+/// `version` line followed by one line per object, each a kind and `key=value` fields:
+///
+/// ```text
+/// version 4
+/// terrain heightmap=resources/textures/heightmap.png position=0,0,0
+/// camera position=0,160,0 yaw=-90 pitch=0 zoom=45
+/// time hours=12
+/// lighting ambient=0.25 hemisphere=true sky=0.6,0.75,1 ground=0.4,0.33,0.25 ssao=true radius=2 intensity=1
+/// entity model=resources/objects/nanosuit/nanosuit.obj position=20,1.5,20 orientation=0,0.785,0 scale=1 speed=80
+/// light position=550,121,400 colour=2.55,0.8,0.4 attenuation=1,0.02,0.01
+/// ```
+///
+/// Paths can't contain whitespace or `#`, which `write` refuses rather than save a file that
+/// can't be read back
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneFile {
+  pub terrain: TerrainSource,
+  pub camera: CameraPose,
+  /// Time of day in hours
+  pub hours: f32,
+  pub lighting: LightingSettings,
+  pub entities: Vec<EntityRecord>,
+  pub lights: Vec<Light>
+}
+
+impl SceneFile {
+  pub fn load(path: &str) -> Result<SceneFile, AssetError> {
+    let source = fs::read_to_string(path).map_err(|error| AssetError::Io { path: path.into(), error })?;
+    SceneFile::parse(&source).map_err(|reason| AssetError::BadScene { path: path.into(), reason })
+  }
+
+  pub fn save(&self, path: &str) -> Result<(), AssetError> {
+    if let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+      fs::create_dir_all(parent).map_err(|error| AssetError::Io { path: path.into(), error })?;
+    }
+    let source = self.write().map_err(|reason| AssetError::BadScene { path: path.into(), reason })?;
+    fs::write(path, source).map_err(|error| AssetError::Io { path: path.into(), error })
+  }
+
+  /// Reads any version up to `SCENE_VERSION`, migrating older ones as it goes
+  pub fn parse(source: &str) -> Result<SceneFile, String> {
+    let mut version = None;
+    let (mut terrain, mut camera, mut hours, mut lighting) = (None, None, None, None);
+    // Dropped from older versions, the time of day places the sun now
+    let mut sunDropped = false;
+    let (mut entities, mut lights) = (vec![], vec![]);
+
+    for (number, line) in source.lines().enumerate() {
+      let line = line.split('#').next().unwrap().trim();
+      if line.is_empty() {
+        continue;
+      }
+      let error = |reason: String| format!("line {}: {}", number + 1, reason);
+      let mut tokens = line.split_whitespace();
+      let kind = tokens.next().unwrap();
+      if kind == "version" {
+        let value = tokens.next().and_then(|v| v.parse::<u32>().ok()).ok_or_else(|| error("expected a version number".into()))?;
+        if !(OLDEST_VERSION..=SCENE_VERSION).contains(&value) {
+          return Err(error(format!("version {} isn't supported, this build reads {} to {}", value, OLDEST_VERSION, SCENE_VERSION)));
+        }
+        version = Some(value);
+        continue;
+      }
+      let version = version.ok_or_else(|| error("the first line has to give the version".into()))?;
+      let mut fields = Fields::parse(tokens).map_err(error)?;
+
+      match kind {
+        "terrain" => terrain = Some(TerrainSource {
+          heightmap: fields.string("heightmap").map_err(error)?,
+          position: fields.vector("position").map_err(error)?
+        }),
+        "camera" => camera = Some(CameraPose {
+          position: fields.vector("position").map_err(error)?,
+          yaw: fields.float("yaw").map_err(error)?,
+          pitch: fields.float("pitch").map_err(error)?,
+          zoom: fields.float("zoom").map_err(error)?
+        }),
+        "time" => hours = Some(fields.float("hours").map_err(error)?),
+        "lighting" => lighting = Some(LightingSettings {
+          ambient: fields.float("ambient").map_err(error)?,
+          hemisphere: fields.flag("hemisphere").map_err(error)?,
+          skyColour: fields.vector("sky").map_err(error)?,
+          groundColour: fields.vector("ground").map_err(error)?,
+          ssao: fields.flag("ssao").map_err(error)?,
+          ssaoRadius: fields.float("radius").map_err(error)?,
+          ssaoIntensity: fields.float("intensity").map_err(error)?
+        }),
+        "entity" => entities.push(EntityRecord {
+          model: fields.string("model").map_err(error)?,
+          position: fields.vector("position").map_err(error)?,
+          orientation: fields.vector("orientation").map_err(error)?,
+          scale: fields.float("scale").map_err(error)?,
+          speed: fields.float("speed").map_err(error)?
+        }),
+        "light" => {
+          let light = Light {
+            position: fields.vector("position").map_err(error)?,
+            colour: fields.vector("colour").map_err(error)?,
+            attenuation: fields.vector("attenuation").map_err(error)?
+          };
+          if version < 3 && !sunDropped {
+            sunDropped = true;
+          } else {
+            lights.push(light);
+          }
+        }
+        _ => return Err(error(format!("unknown kind {}", kind)))
+      }
+      fields.finish().map_err(error)?;
+    }
+
+    let version = version.ok_or("empty scene")?;
+    if version < 3 {
+      hours = hours.or(Some(NOON));
+    }
+    if version < 4 {
+      lighting = lighting.or_else(|| Some(LightingSettings::default()));
+    }
+    Ok(SceneFile {
+      terrain: terrain.ok_or("no terrain line")?,
+      camera: camera.ok_or("no camera line")?,
+      hours: hours.ok_or("no time line")?,
+      lighting: lighting.ok_or("no lighting line")?,
+      entities,
+      lights
+    })
+  }
+
+  /// Always writes the current version. Floats are printed in their shortest exact form, so
+  /// reading the output back gives the same values
+  pub fn write(&self) -> Result<String, String> {
+    let mut out = String::new();
+    // Writing into a String can't fail
+    let _ = writeln!(out, "version {}", SCENE_VERSION);
+    let _ = writeln!(out, "terrain heightmap={} position={}", filePath(&self.terrain.heightmap)?, vector(&self.terrain.position));
+    let c = &self.camera;
+    let _ = writeln!(out, "camera position={} yaw={} pitch={} zoom={}", vector(&c.position), c.yaw, c.pitch, c.zoom);
+    let _ = writeln!(out, "time hours={}", self.hours);
+    let l = &self.lighting;
+    let _ = writeln!(out, "lighting ambient={} hemisphere={} sky={} ground={} ssao={} radius={} intensity={}",
+      l.ambient, l.hemisphere, vector(&l.skyColour), vector(&l.groundColour), l.ssao, l.ssaoRadius, l.ssaoIntensity);
+    for e in &self.entities {
+      let _ = writeln!(out, "entity model={} position={} orientation={} scale={} speed={}", filePath(&e.model)?, vector(&e.position), vector(&e.orientation), e.scale, e.speed);
+    }
+    for l in &self.lights {
+      let _ = writeln!(out, "light position={} colour={} attenuation={}", vector(&l.position), vector(&l.colour), vector(&l.attenuation));
+    }
+    Ok(out)
+  }
+}
+
+impl CameraPose {
+  pub fn of(camera: &Camera) -> CameraPose {
+    CameraPose { position: vec3(camera.position.x, camera.position.y, camera.position.z), yaw: camera.yaw, pitch: camera.pitch, zoom: camera.zoom }
+  }
+
+  pub fn apply(&self, camera: &mut Camera) {
+    camera.position = Point3::new(self.position.x, self.position.y, self.position.z);
+    camera.yaw = self.yaw;
+    camera.pitch = self.pitch;
+    camera.zoom = self.zoom;
+    camera.updateCameraVectors();
+  }
+}
+
+/// The live world built from a `SceneFile`. Entities show a placeholder until their model loads
+pub struct Scene {
+  pub terrain: Terrain,
+  pub entities: Vec<Entity>,
+  /// Where each entity's model came from, saved in its place
+  pub modelPaths: Vec<String>,
+  pub lights: Vec<Light>,
+  pub time: TimeOfDay,
+  pub lighting: LightingSettings,
+  pending: Vec<(usize, PendingModel)>
+}
+
+impl Scene {
+  pub fn build(file: &SceneFile, assets: &mut AssetManager) -> Scene {
+    let noRotation = vec3(Rad(0.0), Rad(0.0), Rad(0.0));
+    let t = &file.terrain;
+    let terrain = Terrain::new(&t.heightmap, Point3::new(t.position.x, t.position.y, t.position.z), noRotation, 1.0, assets);
+    let mut scene = Scene { terrain, entities: vec![], modelPaths: vec![], lights: file.lights.clone(), time: TimeOfDay::new(file.hours), lighting: file.lighting.clone(), pending: vec![] };
+    for record in &file.entities {
+      scene.add(record, assets);
+    }
+    scene
+  }
+
+  /// The nanosuit on the terrain at noon, with a ring of coloured lamps
+  pub fn demo(assets: &mut AssetManager) -> Scene {
+    let noRotation = vec3(Rad(0.0), Rad(0.0), Rad(0.0));
+    let terrain = Terrain::new(DEFAULT_HEIGHTMAP, Point3::new(0.0, 0.0, 0.0), noRotation, 1.0, assets);
+    let mut lights = vec![];
+    let lampColours = [vec3(255.0, 80.0, 40.0), vec3(60.0, 200.0, 255.0), vec3(120.0, 255.0, 90.0), vec3(255.0, 220.0, 60.0)];
+    for i in 0..LAMP_COUNT {
+      let angle = 2.0 * std::f32::consts::PI * i as f32 / LAMP_COUNT as f32;
+      let (x, z) = (SIZE / 2.0 + 150.0 * angle.cos(), SIZE / 2.0 + 150.0 * angle.sin());
+      lights.push(Light::new(vec3(x, terrain.getHeight(x, z) + 15.0, z), lampColours[i % lampColours.len()], vec3(1.0, 0.02, 0.01), 0.01));
+    }
+    let nanosuit = EntityRecord {
+      model: NANOSUIT.into(),
+      position: vec3(20.0, terrain.getHeight(20.0, 20.0), 20.0),
+      orientation: vec3(0.0, 0.785, 0.0),
+      scale: 1.0,
+      speed: 80.0
+    };
+
+    let mut scene = Scene { terrain, entities: vec![], modelPaths: vec![], lights, time: TimeOfDay::new(NOON), lighting: LightingSettings::default(), pending: vec![] };
+    scene.add(&nanosuit, assets);
+    scene
+  }
+
+  fn add(&mut self, record: &EntityRecord, assets: &mut AssetManager) {
+    let orientation = vec3(Rad(record.orientation.x), Rad(record.orientation.y), Rad(record.orientation.z));
+    let position = Point3::new(record.position.x, record.position.y, record.position.z);
+    self.pending.push((self.entities.len(), assets.model(&record.model)));
+    self.entities.push(Entity::new(assets.placeholderModel(), position, orientation, record.scale, record.speed));
+    self.modelPaths.push(record.model.clone());
+  }
+
+  /// Label for an entity, the file name of its model
+  pub fn name(&self, index: usize) -> String {
+    let stem = Path::new(&self.modelPaths[index]).file_stem().and_then(|s| s.to_str()).unwrap_or("entity");
+    let mut chars = stem.chars();
+    chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+  }
+
+  /// Swaps in models that finished loading since the last call
+  pub fn update(&mut self) {
+    let entities = &mut self.entities;
+    self.pending.retain(|(i, model)| match model.take() {
+      Some(Ok(model)) => { entities[*i].setModel(model); false }
+      Some(Err(e)) => { println!("{}", e); false }
+      None => true
+    });
+  }
+
+  pub fn toFile(&self, camera: &Camera) -> SceneFile {
+    let origin = self.terrain.entity.worldPos;
+    SceneFile {
+      terrain: TerrainSource { heightmap: self.terrain.heightmap.clone(), position: vec3(origin.x, origin.y, origin.z) },
+      camera: CameraPose::of(camera),
+      hours: self.time.hours,
+      lighting: self.lighting.clone(),
+      entities: self.entities.iter().zip(&self.modelPaths).map(|(e, model)| EntityRecord {
+        model: model.clone(),
+        position: vec3(e.worldPos.x, e.worldPos.y, e.worldPos.z),
+        orientation: vec3(e.orientation.x.0, e.orientation.y.0, e.orientation.z.0),
+        scale: e.scale,
+        speed: e.speed
+      }).collect(),
+      lights: self.lights.clone()
+    }
+  }
+}
+
+/// The `key=value` fields of one line, each of which has to be used exactly once
+struct Fields<'a>(HashMap<&'a str, &'a str>);
+
+impl<'a> Fields<'a> {
+  fn parse<I: Iterator<Item = &'a str>>(tokens: I) -> Result<Fields<'a>, String> {
+    let mut fields = HashMap::new();
+    for token in tokens {
+      let mut parts = token.splitn(2, '=');
+      match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) => if fields.insert(key, value).is_some() { return Err(format!("{} given twice", key)) },
+        _ => return Err(format!("expected key=value, found {}", token))
+      }
+    }
+    Ok(Fields(fields))
+  }
+
+  fn string(&mut self, key: &str) -> Result<String, String> {
+    self.0.remove(key).map(String::from).ok_or_else(|| format!("missing {}", key))
+  }
+
+  fn float(&mut self, key: &str) -> Result<f32, String> {
+    let value = self.string(key)?;
+    value.parse().map_err(|_| format!("invalid {} {:?}", key, value))
+  }
+
+  fn flag(&mut self, key: &str) -> Result<bool, String> {
+    let value = self.string(key)?;
+    value.parse().map_err(|_| format!("{} must be true or false, not {:?}", key, value))
+  }
+
+  fn vector(&mut self, key: &str) -> Result<Vector3, String> {
+    let value = self.string(key)?;
+    let parts: Vec<f32> = value.split(',').map(str::parse).collect::<Result<_, _>>().map_err(|_| format!("invalid {} {:?}", key, value))?;
+    match parts[..] {
+      [x, y, z] => Ok(vec3(x, y, z)),
+      _ => Err(format!("{} needs three components, found {:?}", key, value))
+    }
+  }
+
+  fn finish(self) -> Result<(), String> {
+    match self.0.keys().next() {
+      Some(key) => Err(format!("unknown field {}", key)),
+      None => Ok(())
+    }
+  }
+}
+
+/// Fields are split on whitespace and lines cut at `#`, so paths containing either can't be saved
+fn filePath(path: &str) -> Result<&str, String> {
+  if path.is_empty() || path.contains(|c: char| c.is_whitespace() || c == '#') {
+    return Err(format!("can't save the path {:?}, paths must be non-empty without whitespace or #", path));
+  }
+  Ok(path)
+}
+
+fn vector(v: &Vector3) -> String {
+  format!("{},{},{}", v.x, v.y, v.z)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn scene() -> SceneFile {
+    SceneFile {
+      terrain: TerrainSource { heightmap: DEFAULT_HEIGHTMAP.into(), position: vec3(0.0, -2.5, 0.0) },
+      camera: CameraPose { position: vec3(0.1, 160.0, -3.3), yaw: -90.0, pitch: 12.345678, zoom: 45.0 },
+      hours: 19.75,
+      lighting: LightingSettings { ambient: 0.3, hemisphere: false, ssao: true, ssaoRadius: 0.75, ..LightingSettings::default() },
+      entities: vec![
+        EntityRecord { model: NANOSUIT.into(), position: vec3(20.0, 1.0 / 3.0, 20.0), orientation: vec3(0.0, 0.785, -1e-7), scale: 1.0, speed: 80.0 },
+        EntityRecord { model: "resources/objects/cube/cube.obj".into(), position: vec3(1e6, 0.3, 2.0), orientation: vec3(2.5, 0.0, 0.0), scale: 0.25, speed: 0.0 }
+      ],
+      lights: vec![Light { position: vec3(550.0, 121.5, 400.0), colour: vec3(2.55, 0.8, 0.4), attenuation: vec3(1.0, 0.02, 0.01) }]
+    }
+  }
+
+  #[test]
+  fn roundTrip() {
+    let saved = scene().write().unwrap();
+    let loaded = SceneFile::parse(&saved).unwrap();
+    assert_eq!(loaded, scene());
+    assert_eq!(loaded.write().unwrap(), saved);
+  }
+
+  #[test]
+  fn unreadablePathsAreRefused() {
+    for path in &["my models/cube.obj", "cube#2.obj", "tab\there.obj", ""] {
+      let mut file = scene();
+      file.entities[1].model = path.to_string();
+      assert!(file.write().unwrap_err().contains("can't save the path"), "{:?}", path);
+      let mut file = scene();
+      file.terrain.heightmap = path.to_string();
+      assert!(file.write().is_err(), "{:?}", path);
+    }
+    // Anything else survives the trip
+    let mut file = scene();
+    file.entities[1].model = "C:\\models\\a=b,c.obj".into();
+    assert_eq!(SceneFile::parse(&file.write().unwrap()).unwrap(), file);
+  }
+
+  #[test]
+  fn migratesOlderVersions() {
+    let source = "version 2\n\
+      terrain heightmap=a.png position=0,0,0\n\
+      camera position=0,160,0 yaw=-90 pitch=0 zoom=45\n\
+      entity model=b.obj position=1,2,3 orientation=0,1,2 scale=1 speed=80\n\
+      light position=400,1000,400 colour=1,1,1 attenuation=1,0,0\n\
+      light position=0,100,0 colour=1,0,0 attenuation=1,0.02,0.01\n";
+    let scene = SceneFile::parse(source).unwrap();
+    assert_eq!(scene.entities[0].orientation, vec3(0.0, 1.0, 2.0));
+    // The old fixed sun gives way to the time of day
+    assert_eq!(scene.hours, NOON);
+    assert_eq!(scene.lighting, LightingSettings::default());
+    assert_eq!(scene.lights.len(), 1);
+    assert_eq!(scene.lights[0].colour, vec3(1.0, 0.0, 0.0));
+    // Saving upgrades the file
+    assert!(scene.write().unwrap().starts_with(&format!("version {}\n", SCENE_VERSION)));
+  }
+
+  #[test]
+  fn rejectsBadScenes() {
+    let saved = scene().write().unwrap();
+    let error = |source: &str| SceneFile::parse(source).unwrap_err();
+    let version = format!("version {}\n", SCENE_VERSION);
+    assert!(error(&saved.replace(&version, &format!("version {}\n", SCENE_VERSION + 1))).contains("isn't supported"));
+    assert!(error(&saved.replace(&version, "version 1\n")).contains("isn't supported"));
+    assert!(error(&saved.replacen(&version, "", 1)).starts_with("line 1:"));
+    assert!(error(&saved.replace("scale=0.25", "scale=big")).starts_with("line 7: invalid scale"));
+    assert_eq!(error(&saved.replace("time hours=19.75\n", "")), "no time line");
+    assert!(error(&saved.replace("ssao=true", "ssao=yes")).contains("ssao must be true or false"));
+    assert!(error(&saved.replace("speed=0", "speed=0 colour=red")).contains("unknown field colour"));
+    assert!(error(&saved.replace("position=550,121.5,400", "position=550,121.5")).contains("three components"));
+    assert_eq!(error(""), "empty scene");
+  }
+}
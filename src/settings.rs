@@ -0,0 +1,154 @@
+#![allow(non_snake_case)]
+use std::fs;
+use std::str::FromStr;
+
+pub const SETTINGS_FILE: &str = "settings.cfg";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowMode {
+  Windowed,
+  /// Covers the primary monitor at its current video mode, so the display never switches mode
+  Borderless,
+  /// Exclusive full screen, switching the primary monitor to the requested resolution
+  Fullscreen
+}
+
+/// Window and display options, read from `settings.cfg` and then overridden by command line flags
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+  pub width: u32,
+  pub height: u32,
+  pub windowMode: WindowMode,
+  pub vsync: bool,
+  /// Samples per pixel for the forward scene target, 0 to disable MSAA
+  pub msaaSamples: u32,
+  /// Vertical field of view in degrees
+  pub fov: f32,
+  /// Frames per second to cap at, mostly for when vsync is off. 0 for no limit
+  pub fpsLimit: u32
+}
+
+impl Default for Settings {
+  fn default() -> Settings {
+    Settings { width: 1920, height: 1080, windowMode: WindowMode::Windowed, vsync: true, msaaSamples: 4, fov: 45.0, fpsLimit: 0 }
+  }
+}
+
+impl Settings {
+  /// Reads the settings file named by `--settings`, or `settings.cfg` if there is one, and applies
+  /// the remaining flags on top. Anything that doesn't parse is reported and left at its default
+  pub fn load(args: &[String]) -> Settings {
+    let mut settings = Settings::default();
+    let chosen = args.iter().position(|a| a == "--settings").and_then(|i| args.get(i + 1));
+    let path = chosen.map(String::as_str).unwrap_or(SETTINGS_FILE);
+    match fs::read_to_string(path) {
+      Ok(source) => settings.parseFile(path, &source),
+      // Only a file asked for by name has to exist
+      Err(e) => if chosen.is_some() { println!("Failed to read {}: {}", path, e) }
+    }
+    settings.parseArgs(args);
+    settings
+  }
+
+  /// `key = value` lines, with `#` starting a comment
+  fn parseFile(&mut self, path: &str, source: &str) {
+    for (number, line) in source.lines().enumerate() {
+      let line = line.split('#').next().unwrap().trim();
+      if line.is_empty() {
+        continue;
+      }
+      let mut parts = line.splitn(2, '=').map(str::trim);
+      match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) => if let Err(e) = self.set(key, value) { println!("{}:{}: {}", path, number + 1, e) },
+        _ => println!("{}:{}: expected `key = value`", path, number + 1)
+      }
+    }
+  }
+
+  /// Flags this doesn't know about are left for the rest of `main` to handle
+  fn parseArgs(&mut self, args: &[String]) {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      let result = match arg.as_str() {
+        "--windowed" => self.set("window", "windowed"),
+        "--borderless" => self.set("window", "borderless"),
+        "--fullscreen" => self.set("window", "fullscreen"),
+        "--vsync" => self.set("vsync", "true"),
+        "--no-vsync" => self.set("vsync", "false"),
+        "--resolution" | "--msaa" | "--fov" => match args.next() {
+          Some(value) => self.set(&arg[2..], value),
+          None => Err(format!("{} needs a value", arg))
+        },
+        "--fps-limit" => match args.next() {
+          Some(value) => self.set("fps_limit", value),
+          None => Err(format!("{} needs a value", arg))
+        },
+        _ => Ok(())
+      };
+      if let Err(e) = result {
+        println!("{}", e);
+      }
+    }
+  }
+
+  fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+    match key {
+      "resolution" => {
+        let mut parts = value.splitn(2, 'x');
+        let (width, height) = (parse(parts.next().unwrap_or(""))?, parse(parts.next().unwrap_or(""))?);
+        if width == 0 || height == 0 {
+          return Err(format!("resolution {} must be at least 1x1", value));
+        }
+        self.width = width;
+        self.height = height;
+      }
+      "window" => self.windowMode = match value {
+        "windowed" => WindowMode::Windowed,
+        "borderless" => WindowMode::Borderless,
+        "fullscreen" => WindowMode::Fullscreen,
+        _ => return Err(format!("window must be windowed, borderless or fullscreen, not {}", value))
+      },
+      "vsync" => self.vsync = parse(value)?,
+      "msaa" => self.msaaSamples = parse(value)?,
+      "fov" => {
+        let fov: f32 = parse(value)?;
+        if fov <= 0.0 || fov >= 180.0 {
+          return Err(format!("fov {} must be between 0 and 180 degrees", value));
+        }
+        self.fov = fov;
+      }
+      "fps_limit" => self.fpsLimit = parse(value)?,
+      _ => return Err(format!("unknown setting {}", key))
+    }
+    Ok(())
+  }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+  value.parse().map_err(|_| format!("invalid value {:?}", value))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+  }
+
+  #[test]
+  fn fileThenFlags() {
+    let mut settings = Settings::default();
+    settings.parseFile(SETTINGS_FILE, "# display\nresolution = 1280x720\nwindow = fullscreen\nmsaa = 8 # samples\nfps_limit = 60\n");
+    settings.parseArgs(&args("sandbox --deferred --borderless --fov 70 --no-vsync --fps-limit 144"));
+    assert_eq!(settings, Settings { width: 1280, height: 720, windowMode: WindowMode::Borderless, vsync: false, msaaSamples: 8, fov: 70.0, fpsLimit: 144 });
+  }
+
+  #[test]
+  fn badValuesKeepDefaults() {
+    let mut settings = Settings::default();
+    settings.parseFile(SETTINGS_FILE, "resolution = 0x720\nwindow = maximised\nfov = 200\nmsaa = lots\nfps_limit = -30\nnonsense\n");
+    settings.parseArgs(&args("sandbox --resolution"));
+    assert_eq!(settings, Settings::default());
+  }
+}
@@ -0,0 +1,14 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler2D bloomTexture;
+uniform float intensity;
+
+void main() {
+    vec3 colour = texture(screenTexture, texCoords).rgb;
+    colour += texture(bloomTexture, texCoords).rgb * intensity;
+    outColour = vec4(colour, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform vec2 texelSize;
+uniform bool horizontal;
+
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    // Separable 9-tap gaussian, run once per axis
+    vec2 offset = horizontal ? vec2(texelSize.x, 0.0) : vec2(0.0, texelSize.y);
+    vec3 result = texture(screenTexture, texCoords).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        result += texture(screenTexture, texCoords + offset * i).rgb * weights[i];
+        result += texture(screenTexture, texCoords - offset * i).rgb * weights[i];
+    }
+    outColour = vec4(result, 1.0);
+}
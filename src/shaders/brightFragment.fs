@@ -0,0 +1,13 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform float threshold;
+
+void main() {
+    vec3 colour = texture(screenTexture, texCoords).rgb;
+    float brightness = dot(colour, vec3(0.2126, 0.7152, 0.0722));
+    outColour = brightness > threshold ? vec4(colour, 1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,63 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+#include "frame.glsl"
+#include "lighting.glsl"
+#include "occlusion.glsl"
+
+uniform sampler2D gAlbedo;
+uniform sampler2D gNormal;
+uniform sampler2D gSpecular;
+uniform sampler2D gDepth;
+uniform mat4 invViewProjection;
+uniform vec3 skyColour;
+uniform float fogDensity;
+uniform float fogGradient;
+uniform float waterHeight;
+uniform vec3 waterTint;
+
+vec3 worldFromDepth(vec2 uv, float depth) {
+    vec4 world = invViewProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return world.xyz / world.w;
+}
+
+// Shades the G-buffer with the sun or moon and the unbounded lights, skipping the bounded ones drawn
+// as light volumes, then adds the terms that only apply once per pixel (ambient, water tint
+// and fog). Light volumes are scaled to match in lightVolumeFragment.fs
+void main() {
+    float depth = texture(gDepth, texCoords).r;
+    if (depth == 1.0) {
+        discard;
+    }
+
+    vec4 albedo = texture(gAlbedo, texCoords);
+    vec3 unitNormal = normalize(texture(gNormal, texCoords).xyz);
+    vec2 specular = texture(gSpecular, texCoords).rg;
+    float reflectivity = specular.x;
+    float shineDamper = specular.y * 1000.0;
+    vec3 worldPos = worldFromDepth(texCoords, depth);
+    vec3 unitToCamera = normalize(frame.cameraPosition.xyz - worldPos);
+
+    vec3 colour = albedo.rgb * ambientLight(unitNormal) * ambientOcclusionAt(texCoords);
+    Lighting sky = directionalLight(frame.skyLightDirection.xyz, frame.skyLightColour.rgb, unitNormal, unitToCamera, shineDamper, reflectivity);
+    colour += sky.diffuse * albedo.rgb + sky.specular;
+    for (int i = 0; i < frame.lightCount; i++) {
+        vec3 attenuation = frame.attenuation[i].xyz;
+        if (attenuation.y > 0.0 || attenuation.z > 0.0) {
+            continue;
+        }
+        Lighting lighting = pointLight(frame.lightPosition[i].xyz, frame.lightColour[i].xyz, attenuation, worldPos, unitNormal, unitToCamera, shineDamper, reflectivity);
+        colour += lighting.diffuse * albedo.rgb + lighting.specular;
+    }
+
+    // Submerged parts of an entity pick up the colour of the water
+    if (albedo.a > 0.5 && worldPos.y < waterHeight) {
+        colour = mix(colour, waterTint, 0.4);
+    }
+
+    float visibility = exp(-pow(length(frame.cameraPosition.xyz - worldPos) * fogDensity, fogGradient));
+    visibility = clamp(visibility, 0.0, 1.0);
+    outColour = vec4(mix(skyColour, colour, visibility), 1.0);
+}
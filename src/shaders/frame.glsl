@@ -0,0 +1,22 @@
+// Per-pass values shared by every program, filled by FrameUniforms. Layout must match uniforms.rs
+const int MAX_LIGHTS = 16;
+
+layout (std140) uniform Frame {
+    mat4 view;
+    mat4 projection;
+    vec4 cameraPosition;
+    vec4 lightPosition[MAX_LIGHTS];
+    vec4 lightColour[MAX_LIGHTS];
+    vec4 attenuation[MAX_LIGHTS];
+    int lightCount;
+    // Towards the sun by day and the moon by night
+    vec4 skyLightDirection;
+    vec4 skyLightColour;
+    vec4 ambientSky;
+    vec4 ambientGround;
+} frame;
+
+// Ambient light reaching a surface, from the ground below blending to the sky above
+vec3 ambientLight(vec3 unitNormal) {
+    return mix(frame.ambientGround.rgb, frame.ambientSky.rgb, unitNormal.y * 0.5 + 0.5);
+}
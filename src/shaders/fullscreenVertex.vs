@@ -0,0 +1,9 @@
+#version 330 core
+out vec2 texCoords;
+
+void main() {
+    // Oversized triangle covering the whole screen, generated from the vertex index alone
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    texCoords = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
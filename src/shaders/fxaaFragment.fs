@@ -0,0 +1,40 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform vec2 texelSize;
+
+const float FXAA_SPAN_MAX = 8.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+
+void main() {
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+    float lumaNW = dot(texture(screenTexture, texCoords + vec2(-1.0, -1.0) * texelSize).rgb, luma);
+    float lumaNE = dot(texture(screenTexture, texCoords + vec2(1.0, -1.0) * texelSize).rgb, luma);
+    float lumaSW = dot(texture(screenTexture, texCoords + vec2(-1.0, 1.0) * texelSize).rgb, luma);
+    float lumaSE = dot(texture(screenTexture, texCoords + vec2(1.0, 1.0) * texelSize).rgb, luma);
+    vec3 rgbM = texture(screenTexture, texCoords).rgb;
+    float lumaM = dot(rgbM, luma);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Blur along the edge, perpendicular to the luma gradient
+    vec2 dir = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texelSize;
+
+    vec3 rgbA = 0.5 * (
+        texture(screenTexture, texCoords + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(screenTexture, texCoords + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        texture(screenTexture, texCoords + dir * -0.5).rgb +
+        texture(screenTexture, texCoords + dir * 0.5).rgb);
+
+    float lumaB = dot(rgbB, luma);
+    outColour = (lumaB < lumaMin || lumaB > lumaMax) ? vec4(rgbA, 1.0) : vec4(rgbB, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform float gamma;
+uniform float exposure;
+
+void main() {
+    // Exposure tone mapping brings the HDR scene into [0, 1] before correcting
+    vec3 colour = texture(screenTexture, texCoords).rgb;
+    colour = vec3(1.0) - exp(-colour * exposure);
+    colour = pow(colour, vec3(1.0 / gamma));
+    outColour = vec4(colour, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+layout (location = 0) out vec4 gAlbedo;
+layout (location = 1) out vec4 gNormal;
+layout (location = 2) out vec2 gSpecular;
+
+in vec2 texCoords;
+in vec3 surfaceNormal;
+
+#include "material.glsl"
+
+void main() {
+    // Same material combination as mainFragment.fs with the specular colour reduced to its strongest
+    // channel, alpha flags the pixel as an entity
+    vec3 specular = materialSpecular(texCoords);
+    gAlbedo = vec4(materialAlbedo(texCoords), 1.0);
+    gNormal = vec4(normalize(surfaceNormal), 0.0);
+    gSpecular = vec2(max(specular.r, max(specular.g, specular.b)), material.shininess / 1000.0);
+}
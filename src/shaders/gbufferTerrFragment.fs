@@ -0,0 +1,20 @@
+#version 330 core
+layout (location = 0) out vec4 gAlbedo;
+layout (location = 1) out vec4 gNormal;
+layout (location = 2) out vec2 gSpecular;
+
+in vec2 texCoords;
+in vec3 surfaceNormal;
+
+uniform sampler2D textureSampler1;
+uniform sampler2D textureSampler2;
+
+void main() {
+    // Mutli texture sampling
+    vec4 grassTexColour = texture(textureSampler1, texCoords) * 0.8;
+    vec4 rockTexColour = texture(textureSampler2, texCoords) * 0.2;
+
+    gAlbedo = vec4((grassTexColour + rockTexColour).rgb, 0.0);
+    gNormal = vec4(normalize(surfaceNormal), 0.0);
+    gSpecular = vec2(0.0);
+}
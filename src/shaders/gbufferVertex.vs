@@ -0,0 +1,19 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+
+out vec2 texCoords;
+out vec3 surfaceNormal;
+
+#include "frame.glsl"
+
+uniform mat4 model;
+uniform float tiling;
+
+void main() {
+    vec4 worldPosition = model * vec4(aPos, 1.0);
+    surfaceNormal = (model * vec4(aNormal, 0.0)).xyz;
+    texCoords = aTexCoords * tiling;
+    gl_Position = frame.projection * frame.view * worldPosition;
+}
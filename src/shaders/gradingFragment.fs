@@ -0,0 +1,15 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler3D lut;
+uniform float lutSize;
+
+void main() {
+    vec3 colour = clamp(texture(screenTexture, texCoords).rgb, 0.0, 1.0);
+    // Remap so 0 and 1 land on texel centres of the outer slices
+    vec3 lutCoords = colour * ((lutSize - 1.0) / lutSize) + 0.5 / lutSize;
+    outColour = vec4(texture(lut, lutCoords).rgb, 1.0);
+}
@@ -0,0 +1,51 @@
+#version 330 core
+out vec4 outColour;
+
+#include "frame.glsl"
+#include "lighting.glsl"
+
+uniform sampler2D gAlbedo;
+uniform sampler2D gNormal;
+uniform sampler2D gSpecular;
+uniform sampler2D gDepth;
+uniform vec2 screenSize;
+uniform mat4 invViewProjection;
+uniform vec3 lightPosition;
+uniform vec3 lightColour;
+uniform vec3 attenuation;
+uniform float fogDensity;
+uniform float fogGradient;
+uniform float waterHeight;
+
+vec3 worldFromDepth(vec2 uv, float depth) {
+    vec4 world = invViewProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return world.xyz / world.w;
+}
+
+// Additively blended contribution of one bounded light, drawn as a sphere covering its radius
+void main() {
+    vec2 texCoords = gl_FragCoord.xy / screenSize;
+    float depth = texture(gDepth, texCoords).r;
+    if (depth == 1.0) {
+        discard;
+    }
+
+    vec4 albedo = texture(gAlbedo, texCoords);
+    vec3 unitNormal = normalize(texture(gNormal, texCoords).xyz);
+    vec2 specular = texture(gSpecular, texCoords).rg;
+    float reflectivity = specular.x;
+    float shineDamper = specular.y * 1000.0;
+    vec3 worldPos = worldFromDepth(texCoords, depth);
+    vec3 unitToCamera = normalize(frame.cameraPosition.xyz - worldPos);
+
+    Lighting lighting = pointLight(lightPosition, lightColour, attenuation, worldPos, unitNormal, unitToCamera, shineDamper, reflectivity);
+
+    vec3 colour = lighting.diffuse * albedo.rgb + lighting.specular;
+
+    // Tint and fog are linear blends so each light's share can be scaled independently
+    if (albedo.a > 0.5 && worldPos.y < waterHeight) {
+        colour *= 0.6;
+    }
+    float visibility = exp(-pow(length(frame.cameraPosition.xyz - worldPos) * fogDensity, fogGradient));
+    outColour = vec4(colour * clamp(visibility, 0.0, 1.0), 1.0);
+}
@@ -0,0 +1,10 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+#include "frame.glsl"
+
+uniform mat4 model;
+
+void main() {
+    gl_Position = frame.projection * frame.view * model * vec4(aPos, 1.0);
+}
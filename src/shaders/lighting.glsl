@@ -0,0 +1,29 @@
+// Diffuse and specular terms of the light models shared by the forward and deferred shaders
+struct Lighting {
+    vec3 diffuse;
+    vec3 specular;
+};
+
+Lighting directionalLight(vec3 unitToLight, vec3 lightColour, vec3 unitNormal, vec3 unitToCamera, float shineDamper, float reflectivity) {
+    float brightness = max(dot(unitNormal, unitToLight), 0.0);
+    vec3 reflectedLight = reflect(-unitToLight, unitNormal);
+    float dampedFactor = pow(max(dot(reflectedLight, unitToCamera), 0.0), shineDamper);
+    return Lighting(brightness * lightColour, dampedFactor * reflectivity * lightColour);
+}
+
+Lighting pointLight(vec3 lightPosition, vec3 lightColour, vec3 attenuation, vec3 worldPos, vec3 unitNormal, vec3 unitToCamera, float shineDamper, float reflectivity) {
+    vec3 toLight = lightPosition - worldPos;
+    float distance = length(toLight);
+    float attFactor = attenuation.x + attenuation.y * distance + (attenuation.z * distance * distance);
+
+    // Diffuse
+    vec3 unitToLight = normalize(toLight);
+    float brightness = max(dot(unitNormal, unitToLight), 0.0);
+
+    // Specular
+    vec3 reflectedLight = reflect(-unitToLight, unitNormal);
+    float specularFactor = max(dot(reflectedLight, unitToCamera), 0.0);
+    float dampedFactor = pow(specularFactor, shineDamper);
+
+    return Lighting((brightness * lightColour)/attFactor, (dampedFactor * reflectivity * lightColour)/attFactor);
+}
@@ -0,0 +1,12 @@
+#include "frame.glsl"
+#include "lighting.glsl"
+
+Lighting sceneLighting(vec3 worldPos, vec3 unitNormal, vec3 unitToCamera, float shineDamper, float reflectivity) {
+    Lighting total = directionalLight(frame.skyLightDirection.xyz, frame.skyLightColour.rgb, unitNormal, unitToCamera, shineDamper, reflectivity);
+    for (int i = 0; i < frame.lightCount; i++) {
+        Lighting light = pointLight(frame.lightPosition[i].xyz, frame.lightColour[i].xyz, frame.attenuation[i].xyz, worldPos, unitNormal, unitToCamera, shineDamper, reflectivity);
+        total.diffuse += light.diffuse;
+        total.specular += light.specular;
+    }
+    return total;
+}
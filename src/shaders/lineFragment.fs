@@ -1,7 +1,8 @@
 #version 330 core
+in vec4 colour;
 out vec4 FragColor;
 
 void main()
 {
-    FragColor = vec4(1.0,0.0,0.0,1.0);
+    FragColor = colour;
 }
@@ -1,11 +1,13 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec4 aColour;
 
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+#include "frame.glsl"
+
+out vec4 colour;
 
 void main()
 {
-    gl_Position = projection * view * model * vec4(aPos, 1.0);
+    colour = aColour;
+    gl_Position = frame.projection * frame.view * vec4(aPos, 1.0);
 }
@@ -3,36 +3,31 @@ out vec4 outColour;
 
 in vec2 texCoords;
 in vec3 surfaceNormal;
-in vec3 toLight;
+in vec3 worldPos;
 in vec3 toCamera;
+in float visibility;
 
-uniform sampler2D texture_diffuse1;
-uniform sampler2D texture_specular1;
-uniform vec3 lightColour;
-uniform float shineDamper;
-uniform float reflectivity;
-uniform vec3 attenuation;
+#include "lights.glsl"
+#include "material.glsl"
+#include "occlusion.glsl"
 
-void main() {
+uniform vec3 skyColour;
+uniform float waterHeight;
+uniform vec3 waterTint;
 
-    float distance = length(toLight);
-    float attFactor = attenuation.x + attenuation.y * distance + (attenuation.z * distance * distance);
+void main() {
 
-    // Diffuse
     vec3 unitNormal = normalize(surfaceNormal);
-    vec3 unitToLight = normalize(toLight);
-    float brightness = dot(unitNormal, unitToLight);
-    brightness = max(brightness, 0.0);
-    vec3 diffuse = (brightness * lightColour)/attFactor;
-
-    // Specular
     vec3 unitToCamera = normalize(toCamera);
-    vec3 unitFromLight = -unitToLight;
-    vec3 reflectedLight = reflect(unitFromLight, unitNormal);
-    float specularFactor = dot(reflectedLight, unitToCamera);
-    specularFactor = max(specularFactor, 0.0);
-    float dampedFactor = pow(specularFactor, shineDamper);
-    vec3 specularColour = (dampedFactor * reflectivity * lightColour)/attFactor;
-
-    outColour = vec4(diffuse, 1.0) * texture(texture_diffuse1, texCoords) * texture(texture_specular1, texCoords) + vec4(specularColour, 1.0);
+    Lighting lighting = sceneLighting(worldPos, unitNormal, unitToCamera, material.shininess, 1.0);
+
+    vec3 albedo = materialAlbedo(texCoords);
+    vec3 colour = (lighting.diffuse + ambientLight(unitNormal) * fragmentOcclusion()) * albedo + lighting.specular * materialSpecular(texCoords);
+
+    // Submerged parts of an entity pick up the colour of the water
+    if (worldPos.y < waterHeight) {
+        colour = mix(colour, waterTint, 0.4);
+    }
+
+    outColour = vec4(mix(skyColour, colour, visibility), material.opacity);
 }
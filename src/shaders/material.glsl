@@ -0,0 +1,27 @@
+// Per-mesh surface parameters from the model's MTL file, see Shader::loadMaterial
+struct Material {
+    vec3 diffuse;
+    vec3 specular;
+    float shininess;
+    float opacity;
+};
+
+uniform Material material;
+uniform sampler2D texture_diffuse1;
+uniform sampler2D texture_specular1;
+uniform sampler2D texture_alpha1;
+uniform bool hasDiffuseMap;
+uniform bool hasSpecularMap;
+uniform bool hasAlphaMap;
+
+// Diffuse colour at texCoords, discarding the fragment where the alpha map cuts it out
+vec3 materialAlbedo(vec2 texCoords) {
+    if (hasAlphaMap && texture(texture_alpha1, texCoords).r < 0.5) {
+        discard;
+    }
+    return hasDiffuseMap ? texture(texture_diffuse1, texCoords).rgb : material.diffuse;
+}
+
+vec3 materialSpecular(vec2 texCoords) {
+    return hasSpecularMap ? material.specular * texture(texture_specular1, texCoords).rgb : material.specular;
+}
@@ -0,0 +1,13 @@
+// Screen-space ambient occlusion worked out by Ssao, 1 where nothing is occluded
+uniform sampler2D ambientOcclusion;
+uniform bool useAmbientOcclusion;
+// Framebuffer size, to find this fragment in the occlusion texture when shading forward
+uniform vec2 screenSize;
+
+float ambientOcclusionAt(vec2 uv) {
+    return useAmbientOcclusion ? texture(ambientOcclusion, uv).r : 1.0;
+}
+
+float fragmentOcclusion() {
+    return ambientOcclusionAt(gl_FragCoord.xy / screenSize);
+}
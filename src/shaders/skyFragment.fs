@@ -0,0 +1,21 @@
+#version 330 core
+out vec4 outColour;
+
+in vec3 texCoords;
+
+uniform samplerCube skybox;
+uniform vec3 skyColour;
+// Darkens and warms the texture with the time of day
+uniform vec3 skyTint;
+
+const float lowerLimit = 0.0;
+const float upperLimit = 0.1;
+
+void main() {
+    vec4 finalColour = vec4(texture(skybox, texCoords).rgb * skyTint, 1.0);
+
+    // Fade the horizon into the fog colour so distant terrain melts into the sky
+    float factor = (normalize(texCoords).y - lowerLimit) / (upperLimit - lowerLimit);
+    factor = clamp(factor, 0.0, 1.0);
+    outColour = mix(vec4(skyColour, 1.0), finalColour, factor);
+}
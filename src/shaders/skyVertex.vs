@@ -0,0 +1,13 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+out vec3 texCoords;
+
+#include "frame.glsl"
+
+void main() {
+    texCoords = aPos;
+    // Strip the translation so the sky stays centred on the camera, then force depth to 1.0
+    vec4 position = frame.projection * mat4(mat3(frame.view)) * vec4(aPos, 1.0);
+    gl_Position = position.xyww;
+}
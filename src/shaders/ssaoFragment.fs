@@ -0,0 +1,49 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+const int KERNEL_SIZE = 16;
+const float bias = 0.05;
+
+uniform sampler2D depthTexture;
+uniform mat4 projection;
+uniform mat4 invProjection;
+uniform vec3 kernel[KERNEL_SIZE];
+uniform float radius;
+uniform float intensity;
+
+vec3 viewFromDepth(vec2 uv) {
+    float depth = texture(depthTexture, uv).r;
+    vec4 view = invProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return view.xyz / view.w;
+}
+
+void main() {
+    if (texture(depthTexture, texCoords).r == 1.0) {
+        outColour = vec4(1.0);
+        return;
+    }
+    vec3 position = viewFromDepth(texCoords);
+    // Faceted normal from the depth buffer, good enough to orient the hemisphere
+    vec3 normal = normalize(cross(dFdx(position), dFdy(position)));
+
+    // Interleaved gradient noise turns the kernel per pixel, the blur then hides the pattern
+    float angle = 6.2831853 * fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    vec3 random = vec3(cos(angle), sin(angle), 0.0);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    mat3 TBN = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; i++) {
+        vec3 samplePosition = position + TBN * kernel[i] * radius;
+        vec4 offset = projection * vec4(samplePosition, 1.0);
+        vec2 sampleUv = offset.xy / offset.w * 0.5 + 0.5;
+        float sceneDepth = viewFromDepth(sampleUv).z;
+        // Geometry far in front of the sample is a different object and shouldn't darken this one
+        float range = smoothstep(0.0, 1.0, radius / abs(position.z - sceneDepth));
+        occlusion += (sceneDepth >= samplePosition.z + bias ? 1.0 : 0.0) * range;
+    }
+    float ambient = clamp(1.0 - occlusion / float(KERNEL_SIZE) * intensity, 0.0, 1.0);
+    outColour = vec4(vec3(ambient), 1.0);
+}
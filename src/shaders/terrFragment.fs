@@ -3,12 +3,15 @@ out vec4 outColour;
 
 in vec2 texCoords;
 in vec3 surfaceNormal;
-in vec3 toLight;
+in vec3 worldPos;
+in float visibility;
+
+#include "lights.glsl"
+#include "occlusion.glsl"
 
 uniform sampler2D textureSampler1;
 uniform sampler2D textureSampler2;
-uniform vec3 lightColour;
-uniform vec3 attenuation;
+uniform vec3 skyColour;
 
 void main() {
 
@@ -16,16 +19,10 @@ void main() {
     vec4 grassTexColour = texture(textureSampler1, texCoords) * 0.8;
     vec4 rockTexColour = texture(textureSampler2, texCoords) * 0.2;
 
-    // Attenuation
-    float distance = length(toLight);
-    float attFactor = attenuation.x + attenuation.y * distance + (attenuation.z * distance * distance);
-
-    // Diffuse
+    // Terrain is diffuse only
     vec3 unitNormal = normalize(surfaceNormal);
-    vec3 unitToLight = normalize(toLight);
-    float brightness = dot(unitNormal, unitToLight);
-    brightness = max(brightness, 0.0);
-    vec3 diffuse = (brightness * lightColour)/attFactor;
+    Lighting lighting = sceneLighting(worldPos, unitNormal, vec3(0.0, 1.0, 0.0), 1.0, 0.0);
 
-    outColour = vec4(diffuse, 1.0) * (grassTexColour + rockTexColour);
+    outColour = vec4(lighting.diffuse + ambientLight(unitNormal) * fragmentOcclusion(), 1.0) * (grassTexColour + rockTexColour);
+    outColour = mix(vec4(skyColour, 1.0), outColour, visibility);
 }
@@ -5,18 +5,28 @@ layout (location = 2) in vec2 aTexCoords;
 
 out vec2 texCoords;
 out vec3 surfaceNormal;
-out vec3 toLight;
+out vec3 worldPos;
+out float visibility;
+
+#include "frame.glsl"
 
 uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
-uniform vec3 lightPosition;
+uniform float fogDensity;
+uniform float fogGradient;
+uniform vec4 plane;
 
 void main() {
     vec4 worldPosition = model * vec4(aPos, 1.0);
+    gl_ClipDistance[0] = dot(worldPosition, plane);
+
     surfaceNormal = (model * vec4(aNormal, 0.0)).xyz;
-    toLight = lightPosition - worldPosition.xyz;
+    worldPos = worldPosition.xyz;
+
+    vec4 positionRelativeToCam = frame.view * worldPosition;
+    float distance = length(positionRelativeToCam.xyz);
+    visibility = exp(-pow(distance * fogDensity, fogGradient));
+    visibility = clamp(visibility, 0.0, 1.0);
 
     texCoords = aTexCoords * 100;
-    gl_Position = projection * view * worldPosition;
+    gl_Position = frame.projection * frame.view * worldPosition;
 }
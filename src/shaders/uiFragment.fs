@@ -0,0 +1,12 @@
+#version 330 core
+in vec2 texCoords;
+in vec4 colour;
+out vec4 FragColor;
+
+// Coverage in the red channel, solid quads sample a fully set cell
+uniform sampler2D font;
+
+void main()
+{
+    FragColor = vec4(colour.rgb, colour.a * texture(font, texCoords).r);
+}
@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec2 aPos;
+layout (location = 1) in vec2 aTexCoords;
+layout (location = 2) in vec4 aColour;
+
+out vec2 texCoords;
+out vec4 colour;
+
+// Positions are in framebuffer pixels from the top left
+uniform vec2 screenSize;
+
+void main()
+{
+    texCoords = aTexCoords;
+    colour = aColour;
+    gl_Position = vec4(aPos.x / screenSize.x * 2.0 - 1.0, 1.0 - aPos.y / screenSize.y * 2.0, 0.0, 1.0);
+}
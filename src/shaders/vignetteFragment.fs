@@ -0,0 +1,15 @@
+#version 330 core
+out vec4 outColour;
+
+in vec2 texCoords;
+
+uniform sampler2D screenTexture;
+uniform float radius;
+uniform float softness;
+
+void main() {
+    vec3 colour = texture(screenTexture, texCoords).rgb;
+    float distance = length(texCoords - vec2(0.5));
+    float vignette = smoothstep(radius, radius - softness, distance);
+    outColour = vec4(colour * vignette, 1.0);
+}
@@ -0,0 +1,70 @@
+#version 330 core
+out vec4 outColour;
+
+in vec4 clipSpace;
+in vec2 texCoords;
+in vec3 toCamera;
+in float visibility;
+
+#include "frame.glsl"
+
+uniform sampler2D reflectionTexture;
+uniform sampler2D refractionTexture;
+uniform sampler2D dudvMap;
+uniform sampler2D normalMap;
+uniform sampler2D depthMap;
+uniform vec3 skyColour;
+uniform vec3 waterTint;
+uniform float moveFactor;
+uniform float nearPlane;
+uniform float farPlane;
+
+const float waveStrength = 0.02;
+const float fresnelPower = 0.5;
+const float shineDamper = 20.0;
+const float reflectivity = 0.5;
+
+float linearDepth(float depth) {
+    return 2.0 * nearPlane * farPlane / (farPlane + nearPlane - (2.0 * depth - 1.0) * (farPlane - nearPlane));
+}
+
+void main() {
+    // Projective texture coordinates, the reflection was rendered upside down
+    vec2 ndc = (clipSpace.xy / clipSpace.w) / 2.0 + 0.5;
+    vec2 refractTexCoords = ndc;
+    vec2 reflectTexCoords = vec2(ndc.x, 1.0 - ndc.y);
+
+    // Depth of water between the surface and the refracted floor, used to soften the shoreline
+    float floorDistance = linearDepth(texture(depthMap, refractTexCoords).r);
+    float waterDistance = linearDepth(gl_FragCoord.z);
+    float waterDepth = floorDistance - waterDistance;
+
+    // DuDv distortion
+    vec2 distortedTexCoords = texture(dudvMap, vec2(texCoords.x + moveFactor, texCoords.y)).rg * 0.1;
+    distortedTexCoords = texCoords + vec2(distortedTexCoords.x, distortedTexCoords.y + moveFactor);
+    vec2 totalDistortion = (texture(dudvMap, distortedTexCoords).rg * 2.0 - 1.0) * waveStrength * clamp(waterDepth / 20.0, 0.0, 1.0);
+
+    refractTexCoords = clamp(refractTexCoords + totalDistortion, 0.001, 0.999);
+    reflectTexCoords = clamp(reflectTexCoords + totalDistortion, 0.001, 0.999);
+
+    vec4 reflectColour = texture(reflectionTexture, reflectTexCoords);
+    vec4 refractColour = texture(refractionTexture, refractTexCoords);
+
+    // Normal map stores the up axis in blue
+    vec4 normalMapColour = texture(normalMap, distortedTexCoords);
+    vec3 normal = normalize(vec3(normalMapColour.r * 2.0 - 1.0, normalMapColour.b * 3.0, normalMapColour.g * 2.0 - 1.0));
+
+    // Fresnel
+    vec3 viewVector = normalize(toCamera);
+    float refractiveFactor = pow(max(dot(viewVector, normal), 0.0), fresnelPower);
+
+    // Specular
+    vec3 reflectedLight = reflect(-frame.skyLightDirection.xyz, normal);
+    float specular = pow(max(dot(reflectedLight, viewVector), 0.0), shineDamper);
+    vec3 specularHighlights = frame.skyLightColour.rgb * specular * reflectivity * clamp(waterDepth / 5.0, 0.0, 1.0);
+
+    outColour = mix(reflectColour, refractColour, refractiveFactor);
+    outColour = mix(outColour, vec4(waterTint, 1.0), 0.2) + vec4(specularHighlights, 0.0);
+    outColour.a = clamp(waterDepth / 5.0, 0.0, 1.0);
+    outColour = vec4(mix(skyColour, outColour.rgb, visibility), outColour.a);
+}
@@ -0,0 +1,29 @@
+#version 330 core
+layout (location = 0) in vec2 aPos;
+
+out vec4 clipSpace;
+out vec2 texCoords;
+out vec3 toCamera;
+out float visibility;
+
+#include "frame.glsl"
+
+uniform mat4 model;
+uniform float fogDensity;
+uniform float fogGradient;
+
+const float tiling = 40.0;
+
+void main() {
+    vec4 worldPosition = model * vec4(aPos.x, 0.0, aPos.y, 1.0);
+    toCamera = frame.cameraPosition.xyz - worldPosition.xyz;
+
+    vec4 positionRelativeToCam = frame.view * worldPosition;
+    float distance = length(positionRelativeToCam.xyz);
+    visibility = exp(-pow(distance * fogDensity, fogGradient));
+    visibility = clamp(visibility, 0.0, 1.0);
+
+    texCoords = aPos * tiling;
+    clipSpace = frame.projection * positionRelativeToCam;
+    gl_Position = clipSpace;
+}
@@ -0,0 +1,102 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+
+use cgmath::vec3;
+
+use crate::utils::common::{cubemapFromFiles, missingCubemap};
+use crate::utils::shader::Shader;
+use crate::types::*;
+use crate::c_str;
+
+const SKYBOX_SIZE: f32 = 1.0;
+
+// Order matches GL_TEXTURE_CUBE_MAP_POSITIVE_X + i
+pub const SKYBOX_FACES: [&str; 6] = ["right.png", "left.png", "top.png", "bottom.png", "front.png", "back.png"];
+
+static SKYBOX_VERTICES: [f32; 108] = [
+  -SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,   -SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,    SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,
+   SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,    SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,   -SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,
+  -SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,   -SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,   -SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,
+  -SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,   -SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,   -SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,
+   SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,    SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,    SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,
+   SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,    SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,    SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,
+  -SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,   -SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,    SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,
+   SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,    SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,   -SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,
+  -SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,    SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,    SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,
+   SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,   -SKYBOX_SIZE,  SKYBOX_SIZE,  SKYBOX_SIZE,   -SKYBOX_SIZE,  SKYBOX_SIZE, -SKYBOX_SIZE,
+  -SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,   -SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,    SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,
+   SKYBOX_SIZE, -SKYBOX_SIZE, -SKYBOX_SIZE,   -SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE,    SKYBOX_SIZE, -SKYBOX_SIZE,  SKYBOX_SIZE
+];
+
+pub struct Fog {
+  pub colour: Vector3,
+  pub density: f32,
+  pub gradient: f32
+}
+
+pub struct Skybox {
+  pub textureID: u32,
+  /// Multiplies the texture, see `Sky::skyTint`
+  pub tint: Vector3,
+  VAO: u32,
+  VBO: u32
+}
+
+impl Skybox {
+  pub fn new(directory: &str) -> Skybox {
+    let textureID = unsafe {
+      cubemapFromFiles(&SKYBOX_FACES, directory).unwrap_or_else(|e| {
+        println!("{}", e);
+        missingCubemap()
+      })
+    };
+    let mut skybox = Skybox { textureID, tint: vec3(1.0, 1.0, 1.0), VAO: 0, VBO: 0 };
+    unsafe { skybox.setupSkybox() };
+    skybox
+  }
+
+  unsafe fn setupSkybox(&mut self) {
+    gl::GenVertexArrays(1, &mut self.VAO);
+    gl::GenBuffers(1, &mut self.VBO);
+
+    gl::BindVertexArray(self.VAO);
+    gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
+    let size = (SKYBOX_VERTICES.len() * size_of::<f32>()) as isize;
+    let data = &SKYBOX_VERTICES[0] as *const f32 as *const c_void;
+    gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+
+    gl::EnableVertexAttribArray(0);
+    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * size_of::<f32>() as i32, ptr::null());
+    gl::BindVertexArray(0);
+  }
+
+  /// Drawn after the opaque geometry: the vertex shader pins the cube to the far plane so
+  /// only pixels nothing else has written to pass the depth test
+  pub unsafe fn draw(&self, shader: &Shader) {
+    gl::DepthFunc(gl::LEQUAL);
+    shader.useProgram();
+    shader.setVector3(c_str!("skyTint"), &self.tint);
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.textureID);
+    gl::BindVertexArray(self.VAO);
+    gl::DrawArrays(gl::TRIANGLES, 0, 36);
+    gl::BindVertexArray(0);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+    gl::DepthFunc(gl::LESS);
+  }
+}
+
+impl Drop for Skybox {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteTextures(1, &self.textureID);
+      gl::DeleteBuffers(1, &self.VBO);
+      gl::DeleteVertexArrays(1, &self.VAO);
+    }
+  }
+}
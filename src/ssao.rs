@@ -0,0 +1,164 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ffi::{CStr, CString};
+
+use cgmath::{vec2, vec3, InnerSpace, SquareMatrix};
+
+use crate::light::LightingSettings;
+use crate::utils::shader::Shader;
+use crate::utils::target::{RenderTarget, DepthAttachment, R8};
+use crate::types::*;
+use crate::c_str;
+
+const FULLSCREEN_VERTEX: &str = "src/shaders/fullscreenVertex.vs";
+/// Must match KERNEL_SIZE in ssaoFragment.fs
+const KERNEL_SIZE: usize = 16;
+const BLUR_PASSES: u32 = 1;
+// Texture unit the forward shaders read the occlusion from, above any a mesh binds
+const FORWARD_UNIT: u32 = 8;
+
+/// Screen-space ambient occlusion from a depth buffer alone, computed at half resolution and
+/// blurred, darkening only the ambient term. The deferred path reads it while shading the G-buffer
+/// and the forward path through `bindForward`, after a depth-only pre-pass to compute it from
+pub struct Ssao {
+  shader: Shader,
+  blurShader: Shader,
+  /// The blurred result ends up in `targets[0]`
+  targets: [RenderTarget; 2],
+  VAO: u32
+}
+
+impl Ssao {
+  pub fn new(width: i32, height: i32) -> Ssao {
+    let mut VAO = 0;
+    unsafe { gl::GenVertexArrays(1, &mut VAO) };
+    let shader = Shader::new(FULLSCREEN_VERTEX, "src/shaders/ssaoFragment.fs");
+    unsafe {
+      shader.useProgram();
+      for (i, sample) in hemisphereKernel(KERNEL_SIZE).iter().enumerate() {
+        let name = CString::new(format!("kernel[{}]", i)).unwrap();
+        shader.setVector3(&name, sample);
+      }
+    }
+    Ssao {
+      shader,
+      blurShader: Shader::new(FULLSCREEN_VERTEX, "src/shaders/blurFragment.fs"),
+      targets: [
+        RenderTarget::new(width / 2, height / 2, &[R8], DepthAttachment::None),
+        RenderTarget::new(width / 2, height / 2, &[R8], DepthAttachment::None)
+      ],
+      VAO
+    }
+  }
+
+  pub fn resize(&mut self, width: i32, height: i32) {
+    for target in self.targets.iter_mut() {
+      target.resize(width / 2, height / 2);
+    }
+  }
+
+  pub fn shaders(&self) -> Vec<&Shader> {
+    vec![&self.shader, &self.blurShader]
+  }
+
+  /// Occlusion texture written by the last `compute`, 1 where nothing is occluded
+  pub fn texture(&self) -> u32 {
+    self.targets[0].colourTexture()
+  }
+
+  /// Samples a hemisphere around each pixel of `depthTexture`, seen through `projection`, counting
+  /// how much of it lies behind nearby geometry
+  pub unsafe fn compute(&self, depthTexture: u32, projection: &Matrix4, settings: &LightingSettings) {
+    gl::Disable(gl::DEPTH_TEST);
+    gl::BindVertexArray(self.VAO);
+
+    self.targets[0].bind();
+    self.shader.useProgram();
+    self.shader.setInt(c_str!("depthTexture"), 0);
+    self.shader.setMat4(c_str!("projection"), projection);
+    self.shader.setMat4(c_str!("invProjection"), &projection.invert().unwrap());
+    self.shader.setFloat(c_str!("radius"), settings.ssaoRadius);
+    self.shader.setFloat(c_str!("intensity"), settings.ssaoIntensity);
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::BindTexture(gl::TEXTURE_2D, depthTexture);
+    gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+    let texelSize = vec2(1.0 / self.targets[0].width as f32, 1.0 / self.targets[0].height as f32);
+    self.blurShader.useProgram();
+    self.blurShader.setInt(c_str!("screenTexture"), 0);
+    self.blurShader.setVector2(c_str!("texelSize"), &texelSize);
+    for _ in 0..BLUR_PASSES {
+      self.targets[1].bind();
+      self.blurShader.setBool(c_str!("horizontal"), true);
+      gl::BindTexture(gl::TEXTURE_2D, self.targets[0].colourTexture());
+      gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+      self.targets[0].bind();
+      self.blurShader.setBool(c_str!("horizontal"), false);
+      gl::BindTexture(gl::TEXTURE_2D, self.targets[1].colourTexture());
+      gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    }
+
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+    gl::BindVertexArray(0);
+    gl::Enable(gl::DEPTH_TEST);
+  }
+
+  /// Points forward shaders including occlusion.glsl at the last `compute`, for a framebuffer of
+  /// `screen` pixels, or turns the occlusion off in them with None, e.g. for the water passes
+  pub unsafe fn bindForward(&self, shaders: &[&Shader], screen: Option<(i32, i32)>) {
+    gl::ActiveTexture(gl::TEXTURE0 + FORWARD_UNIT);
+    gl::BindTexture(gl::TEXTURE_2D, if screen.is_some() { self.texture() } else { 0 });
+    gl::ActiveTexture(gl::TEXTURE0);
+    for shader in shaders {
+      shader.useProgram();
+      shader.setInt(c_str!("ambientOcclusion"), FORWARD_UNIT as i32);
+      shader.setBool(c_str!("useAmbientOcclusion"), screen.is_some());
+      if let Some((width, height)) = screen {
+        shader.setVector2(c_str!("screenSize"), &vec2(width as f32, height as f32));
+      }
+    }
+  }
+}
+
+impl Drop for Ssao {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteVertexArrays(1, &self.VAO) }
+  }
+}
+
+/// Points in the +z unit hemisphere spiralling out from the pole, packed closer to the centre so
+/// nearby geometry counts for more. Fixed rather than random so the result doesn't shimmer
+fn hemisphereKernel(count: usize) -> Vec<Vector3> {
+  let goldenAngle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+  (0..count).map(|i| {
+    let t = (i as f32 + 0.5) / count as f32;
+    // Evenly spread over the hemisphere's area, kept off the surface itself
+    let z = 1.0 - t * 0.9;
+    let r = (1.0 - z * z).sqrt();
+    let angle = goldenAngle * i as f32;
+    let direction = vec3(r * angle.cos(), r * angle.sin(), z).normalize();
+    let scale = 0.1 + 0.9 * t * t;
+    direction * scale
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn kernelFillsTheHemisphere() {
+    let kernel = hemisphereKernel(KERNEL_SIZE);
+    assert_eq!(kernel.len(), KERNEL_SIZE);
+    for sample in &kernel {
+      assert!(sample.z > 0.0);
+      assert!(sample.magnitude() <= 1.0 + 1e-6);
+    }
+    // Grows outwards and covers every side
+    assert!(kernel[0].magnitude() < kernel[KERNEL_SIZE - 1].magnitude());
+    assert!(kernel.iter().any(|s| s.x > 0.1) && kernel.iter().any(|s| s.x < -0.1));
+    assert!(kernel.iter().any(|s| s.y > 0.1) && kernel.iter().any(|s| s.y < -0.1));
+  }
+}
@@ -3,11 +3,14 @@ use std::collections::HashMap;
 use std::path::Path;
 
 use image;
-use image::GenericImage;
+use image::{GenericImage, DynamicImage, ImageBuffer};
 use cgmath::{vec2, vec3, Rad, InnerSpace};
 
 use super::mesh::{Mesh, Vertex, Texture};
-use crate::utils::common::*;
+use crate::assets::AssetManager;
+use crate::utils::common::ColourSpace;
+use crate::utils::model::Model;
+use crate::utils::error::AssetError;
 use crate::entity::Entity;
 use crate::types::*;
 
@@ -20,14 +23,20 @@ type Heights = HashMap<(u32, u32), f32>;
 
 pub struct Terrain {
   pub entity: Entity,
+  /// Image the heights were read from
+  pub heightmap: String,
   heights: Heights
 }
 
 impl Terrain {
-  pub fn new(worldPos: Point3, orientation: cgmath::Vector3<Rad<f32>>, scale: f32) -> Terrain {
-    let (mesh, heightArr) = genTerrain("resources/textures/heightmap.png");
-    let e = Entity::new(vec![mesh], Point3{ x: worldPos.x, y: worldPos.y, z: worldPos.z }, orientation, scale, 0.0);
-    Terrain { entity: e, heights: heightArr }
+  pub fn new(heightmap: &str, worldPos: Point3, orientation: cgmath::Vector3<Rad<f32>>, scale: f32, assets: &mut AssetManager) -> Terrain {
+    let (mesh, heightArr) = genTerrain(heightmap, assets).unwrap_or_else(|e| {
+      println!("{}", e);
+      meshFromHeightmap(DynamicImage::ImageLuma8(ImageBuffer::new(2, 2)), assets)
+    });
+    let model = assets.addModel("terrain", Model::fromMeshes(vec![mesh]));
+    let e = Entity::new(model, Point3{ x: worldPos.x, y: worldPos.y, z: worldPos.z }, orientation, scale, 0.0);
+    Terrain { entity: e, heightmap: heightmap.into(), heights: heightArr }
   }
   
   pub fn getHeight(&self, worldX: f32, worldZ: f32) -> f32 {
@@ -55,25 +64,38 @@ impl Terrain {
   }
 }
 
-fn genTerrain(heightMap: &str) -> (Mesh, Heights) {
-  let img = image::open(&Path::new(&heightMap)).expect("Heightmap failed to load");
+fn genTerrain(heightMap: &str, assets: &mut AssetManager) -> Result<(Mesh, Heights), AssetError> {
+  let img = image::open(&Path::new(&heightMap)).map_err(|e| AssetError::fromImage(heightMap, e))?;
+  checkSize(img.width(), img.height()).map_err(|reason| AssetError::BadHeightmap { path: heightMap.into(), reason })?;
+  Ok(meshFromHeightmap(img, assets))
+}
+
+/// The grid has a vertex per pixel and the same number along both sides
+fn checkSize(width: u32, height: u32) -> Result<(), String> {
+  if width != height {
+    return Err(format!("{}x{} isn't square", width, height));
+  }
+  if width < 2 {
+    return Err(format!("{}x{} is too small, it needs at least 2x2 pixels", width, height));
+  }
+  Ok(())
+}
+
+/// Falls back to a flat plane when called with a blank image
+fn meshFromHeightmap(img: DynamicImage, assets: &mut AssetManager) -> (Mesh, Heights) {
   let VERTEX_COUNT = img.height();
 
   let (vertices, heights) = genVertices(img, VERTEX_COUNT);
   let indices = genIndices(VERTEX_COUNT);
 
-  let (grass, rock) = ("grass.png", "rock.jpg");
-  let dir = "resources/textures";
   let grassTexture = Texture { 
-    id: unsafe { textureFromFile(grass, dir) }, 
-    type_: "textureSampler".into(), 
-    path: grass.into()
+    resource: assets.texture("resources/textures/grass.png", ColourSpace::Srgb), 
+    type_: "textureSampler".into()
   };
 
   let rockTexture = Texture {
-    id: unsafe{ textureFromFile(rock, dir) },
-    type_: "textureSampler".into(),
-    path: rock.into()
+    resource: assets.texture("resources/textures/rock.jpg", ColourSpace::Srgb),
+    type_: "textureSampler".into()
   };
 
   let mesh = Mesh::new(vertices, indices, vec![grassTexture, rockTexture]);
@@ -81,7 +103,7 @@ fn genTerrain(heightMap: &str) -> (Mesh, Heights) {
 }
 
 fn getHeightFromImage(x: u32, z: u32, img: &image::DynamicImage) -> f32 {
-  if x >= img.height() || z >= img.height() {
+  if x >= img.width() || z >= img.height() {
     0.0
   } else {
     let p = img.get_pixel(x, z).data;
@@ -152,4 +174,18 @@ fn barryCentric(p1: Vector3, p2: Vector3, p3: Vector3, pos: Vector2) -> f32 {
   let l2 = ((p3.z - p1.z) * (pos.x - p3.x) + (p1.x - p3.x) * (pos.y - p3.z)) / det;
   let l3 = 1.0 - l1 - l2;
   l1 * p1.y + l2 * p2.y + l3 * p3.y
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn heightmapsMustBeSquare() {
+    assert!(checkSize(256, 256).is_ok());
+    assert!(checkSize(2, 2).is_ok());
+    assert!(checkSize(128, 256).unwrap_err().contains("isn't square"));
+    assert!(checkSize(1, 1).unwrap_err().contains("too small"));
+    assert!(checkSize(0, 0).is_err());
+  }
+}
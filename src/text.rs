@@ -0,0 +1,161 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::raw::c_void;
+
+use crate::assets::AssetManager;
+use crate::utils::error::AssetError;
+use crate::utils::quadbatch::{pushQuad, QuadBatch, UiVertex};
+use crate::utils::truetype::{AtlasGlyph, FontFile};
+use crate::types::*;
+
+/// Characters baked into every atlas, printable ASCII plus a few symbols the HUD uses
+const CHARSET: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~°±×";
+const FALLBACK: char = '?';
+
+/// A TrueType font rasterised at one size into a coverage atlas on the GPU
+pub struct Font {
+  texture: u32,
+  atlasSize: (f32, f32),
+  glyphs: HashMap<char, AtlasGlyph>,
+  /// Pixels per em the atlas was baked at, drawing at scale 1 matches it exactly
+  pub pixelHeight: f32,
+  pub ascent: f32,
+  pub lineHeight: f32
+}
+
+impl Font {
+  pub fn load(path: &str, pixelHeight: f32) -> Result<Font, AssetError> {
+    let data = fs::read(path).map_err(|error| AssetError::Io { path: path.into(), error })?;
+    let bad = |reason: String| AssetError::BadFont { path: path.into(), reason };
+    let file = FontFile::parse(data).map_err(bad)?;
+    let atlas = file.bakeAtlas(pixelHeight, &CHARSET.chars().collect::<Vec<_>>()).map_err(bad)?;
+
+    let mut texture = 0;
+    unsafe {
+      gl::GenTextures(1, &mut texture);
+      gl::BindTexture(gl::TEXTURE_2D, texture);
+      gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+      gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, atlas.width as i32, atlas.height as i32, 0, gl::RED, gl::UNSIGNED_BYTE, atlas.pixels.as_ptr() as *const c_void);
+      gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+      gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+      gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+    Ok(Font {
+      texture,
+      atlasSize: (atlas.width as f32, atlas.height as f32),
+      glyphs: atlas.glyphs,
+      pixelHeight: atlas.pixelHeight,
+      ascent: atlas.ascent,
+      lineHeight: atlas.lineHeight
+    })
+  }
+
+  fn glyph(&self, c: char) -> Option<&AtlasGlyph> {
+    self.glyphs.get(&c).or_else(|| self.glyphs.get(&FALLBACK))
+  }
+
+  /// Width of the widest line and the height of all of them, in pixels at `scale`
+  pub fn measure(&self, text: &str, scale: f32) -> (f32, f32) {
+    let width = text.lines()
+      .map(|line| line.chars().filter_map(|c| self.glyph(c)).map(|g| g.advance).sum::<f32>())
+      .fold(0.0, f32::max);
+    let lines = text.lines().count().max(1);
+    (width * scale, lines as f32 * self.lineHeight * scale)
+  }
+}
+
+impl Drop for Font {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteTextures(1, &self.texture) }
+  }
+}
+
+/// Batches strings in one font for a frame, drawn over the finished image in a single call.
+/// Screen positions are framebuffer pixels from the top left
+pub struct TextRenderer {
+  pub font: Option<Font>,
+  batch: QuadBatch,
+  vertices: Vec<UiVertex>,
+  screen: (f32, f32)
+}
+
+impl TextRenderer {
+  /// A font that fails to load is reported and leaves a renderer that draws nothing
+  pub fn new(assets: &mut AssetManager, path: &str, pixelHeight: f32) -> TextRenderer {
+    let font = Font::load(path, pixelHeight).map_err(|e| println!("{}", e)).ok();
+    TextRenderer { font, batch: QuadBatch::new(assets), vertices: vec![], screen: (1.0, 1.0) }
+  }
+
+  pub fn begin(&mut self, screen: (i32, i32)) {
+    self.vertices.clear();
+    self.screen = (screen.0 as f32, screen.1 as f32);
+  }
+
+  /// Draws `text` with the top left of its first line at `(x, y)`, starting a new line at each `\n`
+  pub fn draw(&mut self, x: f32, y: f32, text: &str, colour: [f32; 4], scale: f32) {
+    let font = match self.font {
+      Some(ref font) => font,
+      None => return
+    };
+    let (du, dv) = (1.0 / font.atlasSize.0, 1.0 / font.atlasSize.1);
+    let mut baseline = y + font.ascent * scale;
+    for line in text.lines() {
+      let mut pen = x;
+      for c in line.chars() {
+        let glyph = match font.glyph(c) {
+          Some(glyph) => glyph,
+          None => continue
+        };
+        if glyph.width > 0 {
+          let (x0, y0) = (pen + glyph.left * scale, baseline - glyph.top * scale);
+          let (x1, y1) = (x0 + glyph.width as f32 * scale, y0 + glyph.height as f32 * scale);
+          let (u0, v0) = (glyph.x as f32 * du, glyph.y as f32 * dv);
+          let (u1, v1) = ((glyph.x + glyph.width) as f32 * du, (glyph.y + glyph.height) as f32 * dv);
+          pushQuad(&mut self.vertices, (x0, y0, x1, y1), [u0, v0, u1, v1], colour);
+        }
+        pen += glyph.advance * scale;
+      }
+      baseline += font.lineHeight * scale;
+    }
+  }
+
+  /// Draws `text` centred on `(x, y)`
+  pub fn drawCentred(&mut self, x: f32, y: f32, text: &str, colour: [f32; 4], scale: f32) {
+    let (width, height) = self.measure(text, scale);
+    self.draw(x - width / 2.0, y - height / 2.0, text, colour, scale);
+  }
+
+  /// Draws `text` centred over a point in the world, `height` world units tall so it shrinks with
+  /// distance. Nothing is drawn for points behind the camera
+  pub fn drawWorld(&mut self, position: Vector3, text: &str, colour: [f32; 4], height: f32, projection: &Matrix4, view: &Matrix4) {
+    let pixelHeight = match self.font {
+      Some(ref font) => font.pixelHeight,
+      None => return
+    };
+    let clip = projection * view * position.extend(1.0);
+    if clip.w <= 0.0 {
+      return;
+    }
+    let (x, y) = ((clip.x / clip.w + 1.0) / 2.0 * self.screen.0, (1.0 - clip.y / clip.w) / 2.0 * self.screen.1);
+    // projection[1][1] turns a world height at unit distance into normalised device units
+    let scale = height * projection[1][1] / clip.w * self.screen.1 / 2.0 / pixelHeight;
+    self.drawCentred(x, y, text, colour, scale);
+  }
+
+  pub fn measure(&self, text: &str, scale: f32) -> (f32, f32) {
+    self.font.as_ref().map_or((0.0, 0.0), |font| font.measure(text, scale))
+  }
+
+  /// Draws everything queued since `begin` over whatever framebuffer is bound
+  pub unsafe fn render(&self) {
+    if let Some(ref font) = self.font {
+      self.batch.render(&self.vertices, font.texture, self.screen);
+    }
+  }
+}
@@ -0,0 +1,201 @@
+#![allow(non_snake_case)]
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::entity::Entity;
+use crate::types::*;
+
+/// Seconds of simulation per update
+pub const FIXED_STEP: f32 = 1.0 / 60.0;
+/// Frames kept for the frame time readouts
+const HISTORY: usize = 240;
+// Longer frames (a breakpoint, dragging the window) are cut short rather than caught up on, so
+// the simulation never falls further behind trying
+const MAX_FRAME_TIME: f32 = 0.25;
+// The OS may oversleep by about this much, so the end of a limited frame is spun out instead
+const SPIN_TIME: Duration = Duration::from_millis(2);
+
+/// Splits real time into fixed simulation steps, keeping the remainder to interpolate rendering
+/// between the last two steps. Also caps the frame rate and remembers recent frame times
+pub struct FrameClock {
+  /// Frames per second to wait for, 0 to run as fast as possible
+  pub fpsLimit: u32,
+  accumulator: f32,
+  lastFrame: Instant,
+  history: VecDeque<f32>
+}
+
+impl FrameClock {
+  pub fn new(fpsLimit: u32) -> FrameClock {
+    FrameClock { fpsLimit, accumulator: 0.0, lastFrame: Instant::now(), history: VecDeque::with_capacity(HISTORY) }
+  }
+
+  /// Starts a frame, returning the real seconds since the last one
+  pub fn beginFrame(&mut self) -> f32 {
+    let now = Instant::now();
+    let frameTime = seconds(now - self.lastFrame);
+    self.lastFrame = now;
+    self.record(frameTime);
+    frameTime
+  }
+
+  fn record(&mut self, frameTime: f32) {
+    if self.history.len() == HISTORY {
+      self.history.pop_front();
+    }
+    self.history.push_back(frameTime);
+    self.accumulator += frameTime.min(MAX_FRAME_TIME);
+  }
+
+  /// True while a whole step of banked time is left, taking it from the bank. Call it in a loop
+  /// and run one simulation update each time
+  pub fn step(&mut self) -> bool {
+    if self.accumulator < FIXED_STEP {
+      return false;
+    }
+    self.accumulator -= FIXED_STEP;
+    true
+  }
+
+  /// How far rendering is between the previous step and the latest, from 0 to 1
+  pub fn alpha(&self) -> f32 {
+    self.accumulator / FIXED_STEP
+  }
+
+  /// Oldest first
+  pub fn history(&self) -> &VecDeque<f32> {
+    &self.history
+  }
+
+  pub fn average(&self) -> f32 {
+    if self.history.is_empty() {
+      return 0.0;
+    }
+    self.history.iter().sum::<f32>() / self.history.len() as f32
+  }
+
+  pub fn worst(&self) -> f32 {
+    self.history.iter().cloned().fold(0.0, f32::max)
+  }
+
+  /// Waits out the rest of the frame when there's a limit
+  pub fn limit(&self) {
+    if self.fpsLimit == 0 {
+      return;
+    }
+    let end = self.lastFrame + Duration::from_secs(1) / self.fpsLimit;
+    loop {
+      let now = Instant::now();
+      if now >= end {
+        break;
+      }
+      if end - now > SPIN_TIME {
+        thread::sleep(end - now - SPIN_TIME);
+      } else {
+        thread::yield_now();
+      }
+    }
+  }
+}
+
+fn seconds(duration: Duration) -> f32 {
+  duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
+
+/// Camera and entity positions after one simulation step, blended between to draw a frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+  pub camera: Point3,
+  pub entities: Vec<Point3>
+}
+
+impl Snapshot {
+  pub fn capture(camera: Point3, entities: &[Entity]) -> Snapshot {
+    Snapshot { camera, entities: entities.iter().map(|e| e.worldPos).collect() }
+  }
+
+  /// `alpha` of the way from `self` to `next`. Entities added or removed since `self` take their
+  /// positions from `next`
+  pub fn blend(&self, next: &Snapshot, alpha: f32) -> Snapshot {
+    let lerp = |a: Point3, b: Point3| a + (b - a) * alpha;
+    let entities = if self.entities.len() == next.entities.len() {
+      self.entities.iter().zip(&next.entities).map(|(&a, &b)| lerp(a, b)).collect()
+    } else {
+      next.entities.clone()
+    };
+    Snapshot { camera: lerp(self.camera, next.camera), entities }
+  }
+
+  /// Takes entities moved outside the steps from `now`, comparing it with `stepped` from just
+  /// after the last step, so edits jump into place rather than being blended into
+  pub fn resync(&mut self, stepped: &Snapshot, now: &Snapshot) {
+    if self.entities.len() != now.entities.len() || stepped.entities.len() != now.entities.len() {
+      self.entities = now.entities.clone();
+      return;
+    }
+    for ((previous, &before), &after) in self.entities.iter_mut().zip(&stepped.entities).zip(&now.entities) {
+      if before != after {
+        *previous = after;
+      }
+    }
+  }
+
+  pub fn apply(&self, camera: &mut Point3, entities: &mut [Entity]) {
+    *camera = self.camera;
+    for (entity, &position) in entities.iter_mut().zip(&self.entities) {
+      entity.worldPos = position;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stepsAndRemainder() {
+    let mut clock = FrameClock::new(0);
+    clock.record(FIXED_STEP * 2.5);
+    let mut steps = 0;
+    while clock.step() {
+      steps += 1;
+    }
+    assert_eq!(steps, 2);
+    assert!((clock.alpha() - 0.5).abs() < 1e-4);
+
+    // A long stall only runs a bounded number of steps
+    clock.record(10.0);
+    let mut steps = 0;
+    while clock.step() {
+      steps += 1;
+    }
+    assert_eq!(steps, (MAX_FRAME_TIME / FIXED_STEP + 0.5) as usize);
+  }
+
+  #[test]
+  fn editsSkipBlending() {
+    let point = |x: f32| Point3::new(x, 0.0, 0.0);
+    let snapshot = |entities: Vec<Point3>| Snapshot { camera: point(0.0), entities };
+    let mut previous = snapshot(vec![point(0.0), point(0.0)]);
+    let stepped = snapshot(vec![point(1.0), point(1.0)]);
+    // The second entity was dragged after the step
+    previous.resync(&stepped, &snapshot(vec![point(1.0), point(5.0)]));
+    assert_eq!(previous.entities, vec![point(0.0), point(5.0)]);
+    let blended = previous.blend(&snapshot(vec![point(1.0), point(5.0)]), 0.5);
+    assert_eq!(blended.entities, vec![point(0.5), point(5.0)]);
+  }
+
+  #[test]
+  fn historyRolls() {
+    let mut clock = FrameClock::new(0);
+    assert_eq!(clock.average(), 0.0);
+    for i in 0..HISTORY + 10 {
+      clock.record(if i == 5 { 0.1 } else { 0.01 });
+    }
+    assert_eq!(clock.history().len(), HISTORY);
+    // The slow frame has rolled out
+    assert_eq!(clock.worst(), 0.01);
+    assert!((clock.average() - 0.01).abs() < 1e-6);
+  }
+}
@@ -3,18 +3,27 @@ extern crate glfw;
 use self::glfw::{Context, Glfw, Window, WindowEvent, Action, Key, MouseButtonLeft, MouseButtonRight};
 use gl;
 
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::fs;
 use std::sync::mpsc::Receiver;
 use std::os::raw::c_void;
 use std::path::Path;
 
 use image::*;
 
+use super::compressed::{self, CompressedImage};
+use super::error::AssetError;
 use super::maths::translateCoords;
 use crate::camera::{Camera, CameraMovement::*};
+use crate::debugdraw::{self, DebugDraw};
 use crate::entity::Entity;
 use crate::mesh::Line;
+use crate::settings::{Settings, WindowMode};
 use crate::terrain::Terrain;
-use crate::types::Matrix4;
+use crate::types::{Matrix4, Vector3};
+
+const PICK_DISPLAY_SECONDS: f32 = 2.0;
 
 pub fn initGlfw() -> Glfw {
   let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
@@ -25,11 +34,25 @@ pub fn initGlfw() -> Glfw {
   glfw
 }
 
-pub fn createAndInitWindow(glfw: &mut Glfw, w: u32, h: u32) -> (Window, Receiver<(f64, WindowEvent)>) {
+/// Falls back to a window if there's no monitor to go full screen on
+pub fn createAndInitWindow(glfw: &mut Glfw, settings: &Settings) -> (Window, Receiver<(f64, WindowEvent)>) {
   let (mut window, events) = glfw.with_primary_monitor(|g, m| {
-    g.create_window(w, h, "Rust Sandbox", m.map_or(glfw::WindowMode::Windowed, |m| glfw::WindowMode::FullScreen(m)))
+    match (settings.windowMode, m) {
+      (WindowMode::Fullscreen, Some(m)) => g.create_window(settings.width, settings.height, "Rust Sandbox", glfw::WindowMode::FullScreen(m)),
+      (WindowMode::Borderless, Some(m)) => {
+        // A full screen window matching the monitor's current mode leaves the mode alone
+        let mode = m.get_video_mode()?;
+        g.window_hint(glfw::WindowHint::RedBits(Some(mode.red_bits)));
+        g.window_hint(glfw::WindowHint::GreenBits(Some(mode.green_bits)));
+        g.window_hint(glfw::WindowHint::BlueBits(Some(mode.blue_bits)));
+        g.window_hint(glfw::WindowHint::RefreshRate(Some(mode.refresh_rate)));
+        g.create_window(mode.width, mode.height, "Rust Sandbox", glfw::WindowMode::FullScreen(m))
+      }
+      _ => g.create_window(settings.width, settings.height, "Rust Sandbox", glfw::WindowMode::Windowed)
+    }
   }).expect("Failed to create GLFW window");
   window.make_current();
+  glfw.set_swap_interval(if settings.vsync { glfw::SwapInterval::Sync(1) } else { glfw::SwapInterval::None });
   window.set_framebuffer_size_polling(true);
   window.set_cursor_pos_polling(true);
   window.set_scroll_polling(true);
@@ -42,29 +65,24 @@ pub fn initGl(window: &mut Window) -> () {
     gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
     gl::Enable(gl::DEPTH_TEST);
     gl::LineWidth(20.0);
-    // gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
   }
 }
 
-pub fn updateTimings(glfw: Glfw, deltaTime: &mut f32, lastFrame: &mut f32) -> () {
-  let currentFrame = glfw.get_time() as f32;
-  *deltaTime = currentFrame - *lastFrame;
-  *lastFrame = currentFrame;
-}
-
+/// Returns the new framebuffer size if the window was resized. The camera ignores the mouse while
+/// `mouseCaptured`, e.g. when it's over the debug UI
 pub fn process_events(window: &mut glfw::Window, 
                       events: &Receiver<(f64, glfw::WindowEvent)>, 
                       firstMouse: &mut bool, 
                       lastX: &mut f32, 
                       lastY: &mut f32, 
-                      camera: &mut Camera) {
+                      camera: &mut Camera,
+                      mouseCaptured: bool) -> Option<(i32, i32)> {
+  let mut resized = None;
   for (_, event) in glfw::flush_messages(events) {
     match event {
-      glfw::WindowEvent::FramebufferSize(width, height) => {
-        // make sure the viewport matches the new window dimensions; note that width and
-        // height will be significantly larger than specified on retina displays.
-        unsafe { gl::Viewport(0, 0, width, height) }
-      }
+      // Note that width and height will be significantly larger than the window size on retina
+      // displays. A minimised window reports zero, which no render target can be made at
+      glfw::WindowEvent::FramebufferSize(width, height) if width > 0 && height > 0 => resized = Some((width, height)),
 
       glfw::WindowEvent::CursorPos(xpos, ypos) => {
         let (xpos, ypos) = (xpos as f32, ypos as f32);
@@ -80,22 +98,21 @@ pub fn process_events(window: &mut glfw::Window,
         *lastX = xpos;
         *lastY = ypos;
 
-        if window.get_mouse_button(MouseButtonLeft) == Action::Press {
+        if window.get_mouse_button(MouseButtonLeft) == Action::Press && !mouseCaptured {
           camera.processMouseMovement(xoffset, yoffset, true);
         }
       }
 
-      glfw::WindowEvent::Scroll(_xoffset, yoffset) => camera.processMouseScroll(yoffset as f32),
+      glfw::WindowEvent::Scroll(_xoffset, yoffset) if !mouseCaptured => camera.processMouseScroll(yoffset as f32),
 
       _ => {}
     }
   }
+  resized
 }
 
-pub fn processInput(window: &mut glfw::Window, deltaTime: f32, camera: &mut Camera, nanoEntity: &mut Entity, lastX: f32, lastY: f32, terrain: &Terrain, projectionMatrix: &Matrix4) {
-  if window.get_key(Key::Escape) == Action::Press {
-    window.set_should_close(true)
-  }
+/// Held keys that move the camera and entity, run once per simulation step
+pub fn processInput(window: &glfw::Window, deltaTime: f32, camera: &mut Camera, entity: Option<&mut Entity>, terrain: &Terrain) {
   if window.get_key(Key::W) == Action::Press {
     camera.processKeyboard(FORWARD, deltaTime);
   }
@@ -114,53 +131,292 @@ pub fn processInput(window: &mut glfw::Window, deltaTime: f32, camera: &mut Came
   if window.get_key(Key::LeftControl) == Action::Press {
     camera.processKeyboard(DOWN, deltaTime);
   }
-  
+
+  // Arrow keys push the entity around
+  let entity = match entity {
+    Some(entity) => entity,
+    None => return
+  };
   let (up, down, left, right) = (window.get_key(Key::Up), window.get_key(Key::Down), window.get_key(Key::Left), window.get_key(Key::Right));
   if up == Action::Press {
-    nanoEntity.processKeyboard(Key::Up, terrain, deltaTime);
+    entity.processKeyboard(Key::Up, terrain, deltaTime);
   }
   if down == Action::Press {
-    nanoEntity.processKeyboard(Key::Down, terrain, deltaTime);
+    entity.processKeyboard(Key::Down, terrain, deltaTime);
   }
   if left == Action::Press {
-    nanoEntity.processKeyboard(Key::Left, terrain, deltaTime);
+    entity.processKeyboard(Key::Left, terrain, deltaTime);
   }
   if right == Action::Press {
-    nanoEntity.processKeyboard(Key::Right, terrain, deltaTime);
+    entity.processKeyboard(Key::Right, terrain, deltaTime);
   }
+}
 
-  if window.get_mouse_button(MouseButtonRight) == Action::Press {
-    let (start, end) = translateCoords(lastX, lastY, projectionMatrix, camera);
-    let l = Line::new(start, end);
-    if nanoEntity.intersect(&l).len() > 0 { 
-      nanoEntity.processMouse(l.dir, terrain, deltaTime)
+/// Ray from the camera through `cursor`, given in window coordinates
+pub fn cursorRay(window: &Window, cursor: (f32, f32), projectionMatrix: &Matrix4, camera: &Camera) -> Line {
+  let (width, height) = window.get_size();
+  let (start, end) = translateCoords(cursor.0, cursor.1, (width as f32, height as f32), projectionMatrix, camera);
+  Line::new(start, end)
+}
+
+/// Escape and right click picking along `ray`, run once per frame so a click casts one ray.
+/// Returns the direction to push the entity in while the ray hits it, for the fixed steps to apply
+pub fn processFrameInput(window: &mut glfw::Window, ray: &Line, entity: Option<&Entity>, debug: &mut DebugDraw, editing: bool) -> Option<Vector3> {
+  if window.get_key(Key::Escape) == Action::Press {
+    window.set_should_close(true)
+  }
+  let entity = entity?;
+  // The editor selects and moves entities itself
+  if window.get_mouse_button(MouseButtonRight) == Action::Press && !editing {
+    let hits = entity.intersect(ray);
+    // Kept for a moment so the ray can be seen once the camera moves off it
+    debug.keep(PICK_DISPLAY_SECONDS, |debug| {
+      debug.line(ray.start, ray.end, if hits.is_empty() { debugdraw::WHITE } else { debugdraw::YELLOW });
+      for &(tMin, _) in hits.iter().filter(|h| h.0 > 0.0) {
+        debug.point(ray.start + ray.dir * tMin, 0.5, debugdraw::RED);
+      }
+    });
+    if !hits.is_empty() {
+      return Some(ray.dir);
     }
   }
+  None
 }
 
-pub unsafe fn textureFromFile(path: &str, directory: &str) -> u32 {
-  let filename = format!("{}/{}", directory, path);
-  println!("Filename: {}", filename);
+/// How a texture's texels are encoded. Colour maps are authored in sRGB and decoded to linear by
+/// the sampler, while data such as normals or specular intensity is used as stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColourSpace {
+  Srgb,
+  Linear
+}
+
+/// Decoded texels ready to upload, either raw pixels or blocks the GPU samples directly
+pub enum TextureData {
+  Image(DynamicImage),
+  Compressed(CompressedImage)
+}
+
+/// Re-uploads an image into an existing texture object so everything sampling it picks up the change
+pub unsafe fn reloadTexture(textureID: u32, filename: &str, space: ColourSpace) -> Result<(), AssetError> {
+  let data = decodeTexture(filename)?;
+  uploadTexture(textureID, &data, space);
+  Ok(())
+}
 
+/// Reads a texture, preferring a pre-compressed .ktx2 or .dds next to `filename`. Plain images are
+/// flipped into GL's bottom-up order. Doesn't touch GL so can run on any thread
+pub fn decodeTexture(filename: &str) -> Result<TextureData, AssetError> {
+  let path = Path::new(filename);
+  let source = ["ktx2", "dds"].iter().map(|ext| path.with_extension(ext)).find(|p| p.exists());
+  let source = source.as_deref().unwrap_or(path);
+  let name = source.to_string_lossy();
+  println!("Filename: {}", name);
+
+  let bytes = fs::read(source).map_err(|error| AssetError::Io { path: name.to_string(), error })?;
+  if compressed::isCompressed(&bytes) {
+    let image = compressed::parse(&bytes).map_err(|reason| AssetError::Decode { path: name.to_string(), error: ImageError::FormatError(reason) })?;
+    return Ok(TextureData::Compressed(image));
+  }
+  let img = image::load_from_memory(&bytes).map_err(|e| AssetError::fromImage(&name, e))?;
+  Ok(TextureData::Image(img.flipv()))
+}
+
+/// Magenta/black checkerboard that makes missing textures obvious without stopping the app
+pub fn missingImage() -> TextureData {
+  TextureData::Image(DynamicImage::ImageRgb8(checkerboard()))
+}
+
+/// Flat grey shown while the real texture is still loading
+pub unsafe fn placeholderTexture() -> u32 {
   let mut textureID = 0;
   gl::GenTextures(1, &mut textureID);
+  let grey = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(1, 1, Rgb([128, 128, 128])));
+  uploadTexture(textureID, &TextureData::Image(grey), ColourSpace::Linear);
+  textureID
+}
 
-  let img = image::open(&Path::new(&filename)).expect("Texture failed to load");
-  let img = img.flipv();
-  let format = match img {
-    ImageLuma8(_) => gl::RED,
-    ImageLumaA8(_) => gl::RG,
-    ImageRgb8(_) => gl::RGB,
-    ImageRgba8(_) => gl::RGBA,
-  };
+pub unsafe fn missingCubemap() -> u32 {
+  let img = checkerboard();
+  let mut textureID = 0;
+  gl::GenTextures(1, &mut textureID);
+  gl::BindTexture(gl::TEXTURE_CUBE_MAP, textureID);
+  for i in 0..6 {
+    gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i, 0, gl::SRGB8 as i32, img.width() as i32, img.height() as i32, 0, gl::RGB, gl::UNSIGNED_BYTE, img.as_ptr() as *const c_void);
+  }
+  setCubemapParameters();
+  textureID
+}
 
-  let data = img.raw_pixels();
+fn checkerboard() -> RgbImage {
+  ImageBuffer::from_fn(64, 64, |x, y| {
+    if (x / 8 + y / 8) % 2 == 0 { Rgb([255, 0, 255]) } else { Rgb([0, 0, 0]) }
+  })
+}
+
+pub unsafe fn uploadTexture(textureID: u32, data: &TextureData, space: ColourSpace) {
   gl::BindTexture(gl::TEXTURE_2D, textureID);
-  gl::TexImage2D(gl::TEXTURE_2D, 0, format as i32, img.width() as i32, img.height() as i32, 0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const c_void);
-  gl::GenerateMipmap(gl::TEXTURE_2D);
+  match data {
+    TextureData::Image(img) => uploadImage(img, space),
+    TextureData::Compressed(image) if hasExtension(image.format.extension()) => uploadCompressed(image, space),
+    TextureData::Compressed(image) => {
+      println!("{:?} textures need {}, showing the missing texture instead", image.format, image.format.extension());
+      if let TextureData::Image(img) = missingImage() {
+        uploadImage(&img, space);
+      }
+    }
+  }
   gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
   gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
   gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
   gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+  let anisotropy = maxAnisotropy();
+  if anisotropy > 1.0 {
+    gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, anisotropy);
+  }
+}
+
+unsafe fn uploadImage(img: &DynamicImage, space: ColourSpace) {
+  // GL has no single or two channel sRGB formats, those stay linear
+  let (format, internalFormat) = match (img, space) {
+    (ImageLuma8(_), _) => (gl::RED, gl::R8),
+    (ImageLumaA8(_), _) => (gl::RG, gl::RG8),
+    (ImageRgb8(_), ColourSpace::Srgb) => (gl::RGB, gl::SRGB8),
+    (ImageRgb8(_), ColourSpace::Linear) => (gl::RGB, gl::RGB8),
+    (ImageRgba8(_), ColourSpace::Srgb) => (gl::RGBA, gl::SRGB8_ALPHA8),
+    (ImageRgba8(_), ColourSpace::Linear) => (gl::RGBA, gl::RGBA8),
+  };
+
+  let data = img.raw_pixels();
+  gl::TexImage2D(gl::TEXTURE_2D, 0, internalFormat as i32, img.width() as i32, img.height() as i32, 0, format, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const c_void);
+  // Reset in case a reload replaced a compressed texture with its shorter mip chain
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 1000);
+  gl::GenerateMipmap(gl::TEXTURE_2D);
+}
+
+/// Uploads the stored mip chain as is, since drivers can't be relied on to generate mipmaps for
+/// compressed formats
+unsafe fn uploadCompressed(image: &CompressedImage, space: ColourSpace) {
+  let format = image.format.glFormat(space);
+  for (level, data) in image.levels.iter().enumerate() {
+    let (width, height) = ((image.width >> level).max(1), (image.height >> level).max(1));
+    gl::CompressedTexImage2D(gl::TEXTURE_2D, level as i32, format, width as i32, height as i32, 0, data.len() as i32, data.as_ptr() as *const c_void);
+  }
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, image.levels.len() as i32 - 1);
+}
+
+// From EXT_texture_filter_anisotropic, core only since 4.6
+const TEXTURE_MAX_ANISOTROPY: u32 = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: u32 = 0x84FF;
+/// Beyond this the sharpening is hard to see but still costs bandwidth
+const ANISOTROPY_LIMIT: f32 = 16.0;
+
+thread_local! {
+  static ANISOTROPY: Cell<Option<f32>> = const { Cell::new(None) };
+}
+
+/// Highest anisotropic filtering level to use, or 1 if the driver doesn't support it
+unsafe fn maxAnisotropy() -> f32 {
+  ANISOTROPY.with(|cached| {
+    if let Some(level) = cached.get() {
+      return level;
+    }
+    let mut level = 1.0;
+    if hasExtension("GL_EXT_texture_filter_anisotropic") || hasExtension("GL_ARB_texture_filter_anisotropic") {
+      gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut level);
+      level = level.min(ANISOTROPY_LIMIT);
+    }
+    cached.set(Some(level));
+    level
+  })
+}
+
+pub unsafe fn hasExtension(name: &str) -> bool {
+  let mut count = 0;
+  gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+  (0..count as u32).any(|i| {
+    let extension = gl::GetStringi(gl::EXTENSIONS, i);
+    !extension.is_null() && CStr::from_ptr(extension as *const _).to_bytes() == name.as_bytes()
+  })
+}
+
+pub unsafe fn cubemapFromFiles(faces: &[&str], directory: &str) -> Result<u32, AssetError> {
+  // Decode every face before touching GL so a bad face doesn't leave a half-built cubemap behind
+  let mut images = Vec::with_capacity(faces.len());
+  for face in faces {
+    let filename = format!("{}/{}", directory, face);
+    println!("Filename: {}", filename);
+    let img = image::open(&Path::new(&filename)).map_err(|e| AssetError::fromImage(&filename, e))?;
+    images.push(img.to_rgb());
+  }
+
+  let mut textureID = 0;
+  gl::GenTextures(1, &mut textureID);
+  gl::BindTexture(gl::TEXTURE_CUBE_MAP, textureID);
+
+  // Cubemap faces are addressed top-down so unlike 2D textures they aren't flipped
+  for (i, img) in images.iter().enumerate() {
+    gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32, 0, gl::SRGB8 as i32, img.width() as i32, img.height() as i32, 0, gl::RGB, gl::UNSIGNED_BYTE, img.as_ptr() as *const c_void);
+  }
+  setCubemapParameters();
+  Ok(textureID)
+}
+
+unsafe fn setCubemapParameters() {
+  gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+  gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+  gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+  gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+}
+
+/// Loads a colour grading LUT stored as a horizontal strip of `size` slices, each `size`x`size`,
+/// with red along x, green along y and one slice per blue value
+pub unsafe fn lutFromFile(path: &str) -> Result<(u32, i32), AssetError> {
+  println!("Filename: {}", path);
+  let img = image::open(&Path::new(path)).map_err(|e| AssetError::fromImage(path, e))?.to_rgb();
+  let size = img.height();
+  if img.width() != size * size {
+    let reason = format!("LUT must be {}x{} for a {} slice strip", size * size, size, size);
+    return Err(AssetError::Decode { path: path.into(), error: ImageError::FormatError(reason) });
+  }
+
+  // Rearrange the strip so each blue slice is contiguous for a 3D upload
+  let mut data = Vec::with_capacity((size * size * size * 3) as usize);
+  for b in 0..size {
+    for g in 0..size {
+      for r in 0..size {
+        data.extend_from_slice(&img.get_pixel(b * size + r, g).data);
+      }
+    }
+  }
+  Ok((uploadLut(&data, size), size as i32))
+}
+
+/// 2x2x2 LUT that maps every colour to itself, used when a grading LUT fails to load
+pub unsafe fn neutralLut() -> (u32, i32) {
+  let mut data = Vec::with_capacity(8 * 3);
+  for b in 0..2 {
+    for g in 0..2 {
+      for r in 0..2 {
+        data.extend_from_slice(&[r * 255, g * 255, b * 255]);
+      }
+    }
+  }
+  (uploadLut(&data, 2), 2)
+}
+
+unsafe fn uploadLut(data: &[u8], size: u32) -> u32 {
+  let mut textureID = 0;
+  gl::GenTextures(1, &mut textureID);
+  gl::BindTexture(gl::TEXTURE_3D, textureID);
+  gl::TexImage3D(gl::TEXTURE_3D, 0, gl::RGB8 as i32, size as i32, size as i32, size as i32, 0, gl::RGB, gl::UNSIGNED_BYTE, &data[0] as *const u8 as *const c_void);
+  gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+  gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+  gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+  gl::BindTexture(gl::TEXTURE_3D, 0);
   textureID
 }
@@ -0,0 +1,223 @@
+#![allow(non_snake_case)]
+use std::convert::TryInto;
+
+use super::common::ColourSpace;
+
+/// Block compressed formats that can be uploaded without decoding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockFormat {
+  Bc1,
+  Bc2,
+  Bc3,
+  Bc7
+}
+
+impl BlockFormat {
+  fn blockBytes(self) -> usize {
+    match self {
+      BlockFormat::Bc1 => 8,
+      _ => 16
+    }
+  }
+
+  /// GL internal format, from EXT_texture_compression_s3tc, EXT_texture_sRGB and ARB_texture_compression_bptc
+  pub fn glFormat(self, space: ColourSpace) -> u32 {
+    match (self, space) {
+      (BlockFormat::Bc1, ColourSpace::Linear) => 0x83F1,
+      (BlockFormat::Bc1, ColourSpace::Srgb) => 0x8C4D,
+      (BlockFormat::Bc2, ColourSpace::Linear) => 0x83F2,
+      (BlockFormat::Bc2, ColourSpace::Srgb) => 0x8C4E,
+      (BlockFormat::Bc3, ColourSpace::Linear) => 0x83F3,
+      (BlockFormat::Bc3, ColourSpace::Srgb) => 0x8C4F,
+      (BlockFormat::Bc7, ColourSpace::Linear) => 0x8E8C,
+      (BlockFormat::Bc7, ColourSpace::Srgb) => 0x8E8D
+    }
+  }
+
+  /// Extension the driver has to report for `glFormat` to be usable
+  pub fn extension(self) -> &'static str {
+    match self {
+      BlockFormat::Bc7 => "GL_ARB_texture_compression_bptc",
+      _ => "GL_EXT_texture_compression_s3tc"
+    }
+  }
+}
+
+/// Pre-compressed texture with its mip chain, largest level first. The blocks are uploaded as
+/// stored, so files need to be saved bottom row first to match the flipped PNG/JPG textures
+pub struct CompressedImage {
+  pub width: u32,
+  pub height: u32,
+  pub format: BlockFormat,
+  pub levels: Vec<Vec<u8>>
+}
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+pub fn isCompressed(bytes: &[u8]) -> bool {
+  bytes.starts_with(DDS_MAGIC) || bytes.starts_with(&KTX2_IDENTIFIER)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<CompressedImage, String> {
+  if bytes.starts_with(DDS_MAGIC) {
+    parseDds(bytes)
+  } else if bytes.starts_with(&KTX2_IDENTIFIER) {
+    parseKtx2(bytes)
+  } else {
+    Err("not a DDS or KTX2 file".into())
+  }
+}
+
+fn parseDds(bytes: &[u8]) -> Result<CompressedImage, String> {
+  let header = bytes.get(..128).ok_or("truncated DDS header")?;
+  let (height, width, mipCount) = (u32At(header, 12), u32At(header, 16), u32At(header, 28).max(1));
+  let (format, dataStart) = match &header[84..88] {
+    b"DXT1" => (BlockFormat::Bc1, 128),
+    b"DXT3" => (BlockFormat::Bc2, 128),
+    b"DXT5" => (BlockFormat::Bc3, 128),
+    b"DX10" => {
+      let dxgi = bytes.get(128..132).ok_or("truncated DX10 header")?;
+      let format = match u32At(dxgi, 0) {
+        71 | 72 => BlockFormat::Bc1,
+        74 | 75 => BlockFormat::Bc2,
+        77 | 78 => BlockFormat::Bc3,
+        98 | 99 => BlockFormat::Bc7,
+        other => return Err(format!("unsupported DXGI format {}", other))
+      };
+      (format, 148)
+    }
+    other => return Err(format!("unsupported DDS format {:?}", String::from_utf8_lossy(other)))
+  };
+
+  checkLevels(mipCount, width, height)?;
+  let mut levels = Vec::with_capacity(mipCount as usize);
+  let mut offset: usize = dataStart;
+  for level in 0..mipCount {
+    let size = levelSize(format, width, height, level).ok_or("DDS level too large")?;
+    let end = offset.checked_add(size).ok_or("truncated DDS data")?;
+    levels.push(bytes.get(offset..end).ok_or("truncated DDS data")?.to_vec());
+    offset = end;
+  }
+  Ok(CompressedImage { width, height, format, levels })
+}
+
+fn parseKtx2(bytes: &[u8]) -> Result<CompressedImage, String> {
+  let header = bytes.get(..80).ok_or("truncated KTX2 header")?;
+  let format = match u32At(header, 12) {
+    131..=134 => BlockFormat::Bc1,
+    135 | 136 => BlockFormat::Bc2,
+    137 | 138 => BlockFormat::Bc3,
+    145 | 146 => BlockFormat::Bc7,
+    other => return Err(format!("unsupported vkFormat {}", other))
+  };
+  let (width, height, depth) = (u32At(header, 20), u32At(header, 24), u32At(header, 28));
+  let (layers, faces, levelCount) = (u32At(header, 32), u32At(header, 36), u32At(header, 40).max(1));
+  if depth > 1 || layers > 1 || faces != 1 {
+    return Err("only single 2D images are supported".into());
+  }
+  if u32At(header, 44) != 0 {
+    return Err("supercompressed KTX2 files are not supported".into());
+  }
+
+  checkLevels(levelCount, width, height)?;
+  let mut levels = Vec::with_capacity(levelCount as usize);
+  for level in 0..levelCount {
+    let index = 80 + level as usize * 24;
+    let entry = bytes.get(index..index + 24).ok_or("truncated KTX2 level index")?;
+    let offset = u64At(entry, 0) as usize;
+    let length = u64At(entry, 8) as usize;
+    if Some(length) != levelSize(format, width, height, level) {
+      return Err(format!("level {} is {} bytes, which doesn't match its size", level, length));
+    }
+    let end = offset.checked_add(length).ok_or("truncated KTX2 data")?;
+    levels.push(bytes.get(offset..end).ok_or("truncated KTX2 data")?.to_vec());
+  }
+  Ok(CompressedImage { width, height, format, levels })
+}
+
+/// Headers are untrusted, so a chain can't go past the 1x1 level or start from an empty image
+fn checkLevels(count: u32, width: u32, height: u32) -> Result<(), String> {
+  let most = 32 - width.max(height).leading_zeros();
+  if count > most {
+    return Err(format!("{} mip levels for a {}x{} image, which has at most {}", count, width, height, most));
+  }
+  Ok(())
+}
+
+/// Bytes in mip `level`, or None if they wouldn't fit in memory
+fn levelSize(format: BlockFormat, width: u32, height: u32, level: u32) -> Option<usize> {
+  let blocks = |size: u32| size.checked_shr(level).map(|size| size.max(1).div_ceil(4) as usize);
+  blocks(width)?.checked_mul(blocks(height)?)?.checked_mul(format.blockBytes())
+}
+
+fn u32At(bytes: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64At(bytes: &[u8], offset: usize) -> u64 {
+  u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn dds(fourCC: &[u8; 4], width: u32, height: u32, mips: u32, data: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; 128];
+    bytes[..4].copy_from_slice(DDS_MAGIC);
+    bytes[4..8].copy_from_slice(&124u32.to_le_bytes());
+    bytes[12..16].copy_from_slice(&height.to_le_bytes());
+    bytes[16..20].copy_from_slice(&width.to_le_bytes());
+    bytes[28..32].copy_from_slice(&mips.to_le_bytes());
+    bytes[84..88].copy_from_slice(fourCC);
+    bytes.extend(vec![0xAA; data]);
+    bytes
+  }
+
+  #[test]
+  fn ddsMipChain() {
+    // 8x8 BC1 with three levels: 4 blocks, then 1, then 1
+    let image = parse(&dds(b"DXT1", 8, 8, 3, 32 + 8 + 8)).unwrap();
+    assert_eq!(image.format, BlockFormat::Bc1);
+    assert_eq!(image.levels.iter().map(|l| l.len()).collect::<Vec<_>>(), vec![32, 8, 8]);
+  }
+
+  #[test]
+  fn truncatedDdsIsRejected() {
+    assert!(parse(&dds(b"DXT5", 8, 8, 1, 63)).is_err());
+    assert!(parse(&dds(b"ATI2", 8, 8, 1, 64)).is_err());
+  }
+
+  #[test]
+  fn bogusMipCountsAreRejected() {
+    // A 1x1 image only has the one level
+    assert!(parse(&dds(b"DXT1", 1, 1, 1, 8)).is_ok());
+    assert!(parse(&dds(b"DXT1", 1, 1, 40, 8)).err().unwrap().contains("mip levels"));
+    assert!(parse(&dds(b"DXT1", 1, 1, u32::MAX, 8)).is_err());
+    assert!(parse(&dds(b"DXT1", 0, 0, 1, 8)).is_err());
+    // Huge dimensions run out of data rather than overflowing
+    assert!(parse(&dds(b"DXT1", u32::MAX, u32::MAX, 32, 8)).is_err());
+  }
+
+  #[test]
+  fn ktx2Levels() {
+    let mut bytes = vec![0u8; 80 + 2 * 24];
+    bytes[..12].copy_from_slice(&KTX2_IDENTIFIER);
+    for (offset, value) in &[(12, 146u32), (20, 4), (24, 4), (36, 1), (40, 2)] {
+      bytes[*offset..*offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    // Levels are stored smallest first but indexed largest first
+    for (level, (offset, length)) in [(144u64, 16u64), (128, 16)].iter().enumerate() {
+      bytes[80 + level * 24..88 + level * 24].copy_from_slice(&offset.to_le_bytes());
+      bytes[88 + level * 24..96 + level * 24].copy_from_slice(&length.to_le_bytes());
+    }
+    bytes.extend(vec![1u8; 16]);
+    bytes.extend(vec![2u8; 16]);
+
+    let image = parse(&bytes).unwrap();
+    assert_eq!(image.format, BlockFormat::Bc7);
+    assert_eq!((image.width, image.height), (4, 4));
+    assert_eq!(image.levels, vec![vec![2u8; 16], vec![1u8; 16]]);
+  }
+}
@@ -0,0 +1,92 @@
+#![allow(non_snake_case)]
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use image::ImageError;
+use tobj::LoadError;
+
+use super::glsl::SourceLine;
+
+/// Everything that can go wrong turning a file on disk into something the GPU can use
+#[derive(Debug)]
+pub enum AssetError {
+  Io { path: String, error: io::Error },
+  Decode { path: String, error: ImageError },
+  Shader(ShaderError),
+  ObjParse { path: String, error: LoadError },
+  BadCache { path: String, reason: String },
+  BadFont { path: String, reason: String },
+  BadScene { path: String, reason: String },
+  BadHeightmap { path: String, reason: String },
+  /// A loader thread panicked on the file, or none were left to take it
+  Loader { path: String, reason: String },
+  Unsupported { path: String, line: usize, reason: String }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShaderStage {
+  Vertex,
+  Fragment,
+  Link
+}
+
+/// Compile or link failure with the driver's log and the source lines it complained about
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+  pub stage: ShaderStage,
+  pub path: String,
+  pub log: String,
+  pub lines: Vec<SourceLine>
+}
+
+impl AssetError {
+  /// Splits the image crate's I/O failures from genuine decode failures
+  pub fn fromImage(path: &str, error: ImageError) -> AssetError {
+    match error {
+      ImageError::IoError(error) => AssetError::Io { path: path.into(), error },
+      error => AssetError::Decode { path: path.into(), error }
+    }
+  }
+
+  pub fn fromObj(path: &str, error: LoadError) -> AssetError {
+    match error {
+      LoadError::OpenFileFailed => AssetError::Io { path: path.into(), error: io::Error::new(io::ErrorKind::NotFound, error.to_string()) },
+      error => AssetError::ObjParse { path: path.into(), error }
+    }
+  }
+}
+
+impl fmt::Display for AssetError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      AssetError::Io { path, error } => write!(f, "Failed to read {}: {}", path, error),
+      AssetError::Decode { path, error } => write!(f, "Failed to decode {}: {}", path, error),
+      AssetError::Shader(error) => write!(f, "{}", error),
+      AssetError::ObjParse { path, error } => write!(f, "Failed to parse {}: {}", path, error),
+      AssetError::BadCache { path, reason } => write!(f, "Invalid mesh cache {}: {}", path, reason),
+      AssetError::BadFont { path, reason } => write!(f, "Invalid font {}: {}", path, reason),
+      AssetError::BadScene { path, reason } => write!(f, "Invalid scene {}: {}", path, reason),
+      AssetError::BadHeightmap { path, reason } => write!(f, "Invalid heightmap {}: {}", path, reason),
+      AssetError::Loader { path, reason } => write!(f, "Failed to load {}: {}", path, reason),
+      AssetError::Unsupported { path, line, reason } => write!(f, "{}:{}: {} aren't supported, only triangles and polygons", path, line, reason)
+    }
+  }
+}
+
+impl Error for AssetError {}
+
+impl fmt::Display for ShaderError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.stage {
+      ShaderStage::Vertex => writeln!(f, "Failed to compile vertex shader {}", self.path)?,
+      ShaderStage::Fragment => writeln!(f, "Failed to compile fragment shader {}", self.path)?,
+      ShaderStage::Link => writeln!(f, "Failed to link {}", self.path)?
+    }
+    write!(f, "{}", self.log.trim_end())?;
+    for line in &self.lines {
+      write!(f, "\n  {}:{}: {}", line.path, line.line, line.text)?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,175 @@
+#![allow(non_snake_case)]
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::error::AssetError;
+
+/// GLSL source with every `#include "file"` expanded in place, remembering which file and line
+/// each expanded line came from so driver errors can be pointed back at the real source
+pub struct Source {
+  pub code: String,
+  pub files: Vec<String>,
+  origins: Vec<(usize, usize)>
+}
+
+/// A line of the original source, as quoted in compile errors
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+  pub path: String,
+  pub line: usize,
+  pub text: String
+}
+
+impl Source {
+  /// Reads `path` and expands its includes, each file being included at most once
+  pub fn load(path: &str) -> Result<Source, AssetError> {
+    let mut source = Source { code: String::new(), files: vec![], origins: vec![] };
+    source.append(path)?;
+    Ok(source)
+  }
+
+  /// Maps a 1-based line number in the expanded code back to where it was written
+  pub fn locate(&self, line: usize) -> Option<SourceLine> {
+    let &(file, fileLine) = self.origins.get(line.checked_sub(1)?)?;
+    let text = self.code.lines().nth(line - 1)?;
+    Some(SourceLine { path: self.files[file].clone(), line: fileLine, text: text.trim().into() })
+  }
+
+  fn append(&mut self, path: &str) -> Result<(), AssetError> {
+    if self.files.iter().any(|f| f == path) {
+      return Ok(());
+    }
+
+    let mut code = String::new();
+    File::open(path)
+      .and_then(|mut f| f.read_to_string(&mut code))
+      .map_err(|error| AssetError::Io { path: path.into(), error })?;
+    let file = self.files.len();
+    self.files.push(path.into());
+
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    for (i, line) in code.lines().enumerate() {
+      match includePath(line) {
+        Some(include) => {
+          let include = directory.join(include).to_string_lossy().replace('\\', "/");
+          self.append(&include)?;
+        }
+        None => {
+          self.code.push_str(line);
+          self.code.push('\n');
+          self.origins.push((file, i + 1));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn includePath(line: &str) -> Option<&str> {
+  let line = line.trim();
+  if !line.starts_with("#include") {
+    return None;
+  }
+  let rest = line["#include".len()..].trim();
+  if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+    Some(&rest[1..rest.len() - 1])
+  } else {
+    None
+  }
+}
+
+/// Line numbers referenced by a driver's info log. Drivers disagree on the format, e.g.
+/// NVIDIA writes `0(12) : error`, Mesa `0:12(5): error` and AMD `ERROR: 0:12: ...`
+pub fn errorLines(log: &str) -> Vec<usize> {
+  let mut lines: Vec<usize> = log.lines().filter_map(errorLine).collect();
+  lines.dedup();
+  lines
+}
+
+fn errorLine(message: &str) -> Option<usize> {
+  let bytes = message.as_bytes();
+  for i in 0..bytes.len().saturating_sub(2) {
+    let startsToken = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+    if startsToken && bytes[i] == b'0' && (bytes[i + 1] == b'(' || bytes[i + 1] == b':') {
+      let digits: String = message[i + 2..].chars().take_while(|c| c.is_ascii_digit()).collect();
+      if let Ok(line) = digits.parse() {
+        return Some(line);
+      }
+    }
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  /// Writes `files` into a directory of their own and returns its path with forward slashes
+  fn shaderDir(name: &str, files: &[(&str, &str)]) -> String {
+    let directory = std::env::temp_dir().join(format!("glsl-{}-{}", std::process::id(), name));
+    for (file, code) in files {
+      let path = directory.join(file);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, code).unwrap();
+    }
+    directory.to_string_lossy().replace('\\', "/")
+  }
+
+  #[test]
+  fn nestedIncludesAreTracked() {
+    let dir = shaderDir("nested", &[
+      ("main.fs", "#version 330 core\n#include \"lib/lighting.glsl\"\nvoid main() {}\n"),
+      ("lib/lighting.glsl", "#include \"common.glsl\"\nfloat light;\n"),
+      ("lib/common.glsl", "float common;\n")
+    ]);
+    let source = Source::load(&format!("{}/main.fs", dir)).unwrap();
+    assert_eq!(source.code, "#version 330 core\nfloat common;\nfloat light;\nvoid main() {}\n");
+    assert_eq!(source.files, vec![format!("{}/main.fs", dir), format!("{}/lib/lighting.glsl", dir), format!("{}/lib/common.glsl", dir)]);
+
+    let at = |line: usize| source.locate(line).map(|l| (l.path, l.line, l.text));
+    assert_eq!(at(2), Some((format!("{}/lib/common.glsl", dir), 1, "float common;".to_string())));
+    assert_eq!(at(3), Some((format!("{}/lib/lighting.glsl", dir), 2, "float light;".to_string())));
+    // Lines after an include keep their own numbering
+    assert_eq!(at(4), Some((format!("{}/main.fs", dir), 3, "void main() {}".to_string())));
+    assert!(at(0).is_none() && at(5).is_none());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn includesOnceAndStopsCycles() {
+    let dir = shaderDir("once", &[
+      ("main.fs", "#include \"a.glsl\"\n#include \"b.glsl\"\n#include \"a.glsl\"\nvoid main() {}\n"),
+      ("a.glsl", "#include \"b.glsl\"\nfloat a;\n"),
+      ("b.glsl", "#include \"a.glsl\"\nfloat b;\n")
+    ]);
+    let source = Source::load(&format!("{}/main.fs", dir)).unwrap();
+    assert_eq!(source.code, "float b;\nfloat a;\nvoid main() {}\n");
+    assert_eq!(source.files.len(), 3);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn missingIncludeNamesTheFile() {
+    let dir = shaderDir("missing", &[("main.fs", "#include \"gone.glsl\"\n")]);
+    match Source::load(&format!("{}/main.fs", dir)) {
+      Err(AssetError::Io { path, .. }) => assert_eq!(path, format!("{}/gone.glsl", dir)),
+      Err(e) => panic!("unexpected error {}", e),
+      Ok(_) => panic!("loaded without the include")
+    }
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn driverLogFormats() {
+    assert_eq!(includePath("  #include \"lights.glsl\" "), Some("lights.glsl"));
+    assert_eq!(includePath("#include <lights.glsl>"), None);
+    let log = "0(12) : error C1008: undefined variable \"x\"\n\
+      0:7(5): error: syntax error\n\
+      ERROR: 0:30: 'y' : undeclared identifier\n\
+      ERROR: 0:30: '' : compilation terminated\n\
+      warning: nothing to see in file 10(3)\n";
+    assert_eq!(errorLines(log), vec![12, 7, 30]);
+  }
+}
@@ -1,30 +1,16 @@
 #![allow(non_snake_case)]
-use cgmath::{vec3, SquareMatrix, InnerSpace};
+use std::collections::HashMap;
+
+use cgmath::{vec3, Angle, Deg, SquareMatrix, InnerSpace};
 
 use crate::mesh::Vertex;
 use crate::camera::Camera;
 use crate::types::*;
-use crate::{SCR_WIDTH, SCR_HEIGHT, DRAW_DISTANCE};
-
-pub static BOUNDING_BOX: [Vertex; 8] = [
-  Vertex { Position: Vector3 { x: -0.5, y: -0.5, z: -0.5 }, Normal: Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: 0.5, y: -0.5, z: -0.5 }, Normal:  Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: 0.5, y: 0.5, z: -0.5 }, Normal:   Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: -0.5, y: 0.5, z: -0.5 }, Normal:  Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: -0.5, y: -0.5, z: 0.5 }, Normal:  Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: 0.5, y: -0.5, z: 0.5 }, Normal:   Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: 0.5, y: 0.5, z: 0.5 }, Normal:    Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } },
-  Vertex { Position: Vector3 { x: -0.5, y: 0.5, z: 0.5 }, Normal:   Vector3 { x: 0.0, y: 0.0, z: 0.0 }, TexCoords: Vector2 { x: 0.0, y: 0.0 } }
-];
-
-pub static BOUNDING_BOX_INDICES: [u32; 16] = [
-  0, 1, 2, 3,
-  4, 5, 6, 7,
-  0, 4, 1, 5, 2, 6, 3, 7
-];
-
-fn getNormalisedDeviceCoords(mouseX: f32, mouseY: f32) -> Vector2 {
-  Vector2 { x: (mouseX*2.0 / SCR_WIDTH as f32) - 1.0, y: 1.0 - (mouseY*2.0 / SCR_HEIGHT as f32) }
+use crate::DRAW_DISTANCE;
+
+/// `windowSize` is in the same screen coordinates as the cursor, which may differ from pixels
+fn getNormalisedDeviceCoords(mouseX: f32, mouseY: f32, windowSize: (f32, f32)) -> Vector2 {
+  Vector2 { x: (mouseX*2.0 / windowSize.0) - 1.0, y: 1.0 - (mouseY*2.0 / windowSize.1) }
 }
 
 fn toEyeCoords(clipCoords: Vector4, projectionMatrix: Matrix4) -> Vector4 {
@@ -40,8 +26,8 @@ fn toWorldCoords(eyeCoords: Vector4, viewMatrix: Matrix4) -> Vector3 {
   result.normalize()
 }
 
-pub fn translateCoords(xpos: f32, ypos: f32, projectionMatrix: &Matrix4, cam: &Camera) -> (Vector3, Vector3) {
-  let normalisedCoords = getNormalisedDeviceCoords(xpos, ypos);
+pub fn translateCoords(xpos: f32, ypos: f32, windowSize: (f32, f32), projectionMatrix: &Matrix4, cam: &Camera) -> (Vector3, Vector3) {
+  let normalisedCoords = getNormalisedDeviceCoords(xpos, ypos, windowSize);
   let clipCoords = Vector4 { x: normalisedCoords.x, y: normalisedCoords.y, z: -1.0, w: 1.0 };
   let eyeCoords = toEyeCoords(clipCoords, *projectionMatrix);
   let worldCoords = toWorldCoords(eyeCoords, cam.getViewMatrix());
@@ -71,4 +57,81 @@ pub fn computeBoundingBoxTransform(min: Vector4, max: Vector4) -> Matrix4 {
   let size = vec3(max.x-min.x, max.y-min.y, max.z-min.z);
   let center = vec3((min.x+max.x)/2.0, (min.y+max.y)/2.0, (min.z+max.z)/2.0);
   Matrix4::from_translation(center) * Matrix4::from_nonuniform_scale(size[0], size[1], size[2])
-}
\ No newline at end of file
+}
+/// Unit UV sphere wound counter-clockwise when seen from outside, used as a light volume
+pub fn genSphere(rings: u32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+  let mut vertices = Vec::with_capacity(((rings + 1) * (segments + 1)) as usize);
+  for r in 0..=rings {
+    let phi = std::f32::consts::PI * r as f32 / rings as f32;
+    for s in 0..=segments {
+      let theta = 2.0 * std::f32::consts::PI * s as f32 / segments as f32;
+      let p = vec3(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+      vertices.push(Vertex { Position: p, Normal: p, TexCoords: Vector2 { x: s as f32 / segments as f32, y: r as f32 / rings as f32 } });
+    }
+  }
+
+  let mut indices = Vec::with_capacity((6 * rings * segments) as usize);
+  for r in 0..rings {
+    for s in 0..segments {
+      let current = r * (segments + 1) + s;
+      let next = current + segments + 1;
+      indices.extend_from_slice(&[current, current + 1, next, current + 1, next + 1, next]);
+    }
+  }
+
+  (vertices, indices)
+}
+
+/// Exact bit pattern of a point for use as a hash key, with -0.0 folded into 0.0
+pub fn positionKey(p: &Vector3) -> [u32; 3] {
+  [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()]
+}
+
+/// Replaces the normals of a triangle list with ones built from its faces. Faces meeting at less
+/// than `creaseAngle` are smoothed together and vertices are split along sharper edges, so 0° gives
+/// flat shading and 180° smooths everything
+pub fn generateNormals(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, creaseAngle: Deg<f32>) {
+  let unit = |n: Vector3| if n.magnitude2() > 0.0 { n.normalize() } else { n };
+  let position = |i: u32| vertices[i as usize].Position;
+
+  let faceNormals: Vec<Vector3> = indices.chunks(3).map(|f| {
+    unit((position(f[1]) - position(f[0])).cross(position(f[2]) - position(f[0])))
+  }).collect();
+
+  // Each face is weighted by its angle at the corner, so the result doesn't depend on how
+  // polygons were split into triangles. Faces are grouped by position rather than index so
+  // smoothing carries across UV seams
+  let mut facesAt: HashMap<[u32; 3], Vec<(usize, f32)>> = HashMap::new();
+  for (face, corners) in indices.chunks(3).enumerate() {
+    for c in 0..3 {
+      let p = position(corners[c]);
+      let (toNext, toPrev) = (unit(position(corners[(c + 1) % 3]) - p), unit(position(corners[(c + 2) % 3]) - p));
+      let angle = toNext.dot(toPrev).clamp(-1.0, 1.0).acos();
+      facesAt.entry(positionKey(&p)).or_default().push((face, angle));
+    }
+  }
+
+  let minCos = creaseAngle.cos();
+  let mut output: Vec<Vertex> = Vec::with_capacity(vertices.len());
+  let mut outputIndices = Vec::with_capacity(indices.len());
+  let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+  for (corner, &i) in indices.iter().enumerate() {
+    let face = corner / 3;
+    let vertex = &vertices[i as usize];
+    let mut normal = Vector3::new(0.0, 0.0, 0.0);
+    for &(other, angle) in &facesAt[&positionKey(&vertex.Position)] {
+      if other == face || faceNormals[other].dot(faceNormals[face]) >= minCos {
+        normal += faceNormals[other] * angle;
+      }
+    }
+    let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { vec3(0.0, 1.0, 0.0) };
+
+    let index = *split.entry((i, positionKey(&normal))).or_insert_with(|| {
+      output.push(Vertex { Normal: normal, ..vertex.clone() });
+      (output.len() - 1) as u32
+    });
+    outputIndices.push(index);
+  }
+  *vertices = output;
+  *indices = outputIndices;
+}
@@ -0,0 +1,255 @@
+#![allow(non_snake_case)]
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cgmath::{vec2, vec3};
+
+use super::error::AssetError;
+use super::model::MeshData;
+use crate::mesh::{Material, Vertex};
+use crate::types::Vector4;
+
+const MAGIC: &[u8; 4] = b"RSMC";
+/// Bump whenever the layout below changes so stale caches are rebuilt from the OBJ
+const VERSION: u32 = 4;
+const HEADER_SIZE: usize = 16;
+// Texture types are stored as an index into this list
+const TEXTURE_TYPES: [&str; 4] = ["texture_diffuse", "texture_specular", "texture_normal", "texture_alpha"];
+
+// Layout, all little-endian:
+//   header:  magic[4] version:u32 checksum:u64 (FNV-1a of everything after the header)
+//   payload: meshCount:u32, then per mesh
+//            vertexCount:u32 indexCount:u32 textureCount:u32 min:[f32;3] max:[f32;3]
+//            diffuse:[f32;3] specular:[f32;3] shininess:f32 opacity:f32
+//            vertices:[position:[f32;3] normal:[f32;3] texCoords:[f32;2]]
+//            indices:[u32]
+//            lodCount:u32 lods:[count:u32 indices:[u32]]
+//            textures:[type:u8 length:u32 path:[u8]]
+
+/// Where the baked copy of an OBJ lives, next to the source file
+pub fn cachePath(objPath: &str) -> PathBuf {
+  Path::new(objPath).with_extension("meshcache")
+}
+
+/// True if a cache exists and was written after the OBJ, and the MTL of the same name if there
+/// is one, were last modified
+pub fn isFresh(objPath: &str) -> bool {
+  let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+  let materials = Path::new(objPath).with_extension("mtl");
+  match (modified(&cachePath(objPath)), modified(Path::new(objPath))) {
+    (Some(cache), Some(source)) => cache >= source && modified(&materials).is_none_or(|m| cache >= m),
+    _ => false
+  }
+}
+
+pub fn write(objPath: &str, meshes: &[MeshData]) -> Result<(), AssetError> {
+  let path = cachePath(objPath);
+  let payload = encode(meshes);
+  let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+  bytes.extend_from_slice(MAGIC);
+  bytes.extend_from_slice(&VERSION.to_le_bytes());
+  bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+  bytes.extend_from_slice(&payload);
+  fs::write(&path, bytes).map_err(|error| AssetError::Io { path: path.to_string_lossy().into(), error })
+}
+
+pub fn read(objPath: &str) -> Result<Vec<MeshData>, AssetError> {
+  let path = cachePath(objPath);
+  let pathName = path.to_string_lossy().to_string();
+  let bytes = fs::read(&path).map_err(|error| AssetError::Io { path: pathName.clone(), error })?;
+  let bad = |reason: &str| AssetError::BadCache { path: pathName.clone(), reason: reason.into() };
+
+  if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+    return Err(bad("not a mesh cache"));
+  }
+  let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+  if version != VERSION {
+    return Err(bad(&format!("version {} but expected {}", version, VERSION)));
+  }
+  let payload = &bytes[HEADER_SIZE..];
+  if u64::from_le_bytes(bytes[8..16].try_into().unwrap()) != checksum(payload) {
+    return Err(bad("checksum mismatch"));
+  }
+  decode(payload).ok_or_else(|| bad("truncated"))
+}
+
+fn encode(meshes: &[MeshData]) -> Vec<u8> {
+  let mut out = Vec::new();
+  let u32s = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+  let f32s = |out: &mut Vec<u8>, values: &[f32]| for v in values { out.extend_from_slice(&v.to_le_bytes()) };
+
+  u32s(&mut out, meshes.len() as u32);
+  for mesh in meshes {
+    let (min, max) = mesh.bounds;
+    u32s(&mut out, mesh.vertices.len() as u32);
+    u32s(&mut out, mesh.indices.len() as u32);
+    u32s(&mut out, mesh.textures.len() as u32);
+    f32s(&mut out, &[min.x, min.y, min.z, max.x, max.y, max.z]);
+    let m = &mesh.material;
+    f32s(&mut out, &[m.diffuse.x, m.diffuse.y, m.diffuse.z, m.specular.x, m.specular.y, m.specular.z, m.shininess, m.opacity]);
+    for v in &mesh.vertices {
+      f32s(&mut out, &[v.Position.x, v.Position.y, v.Position.z, v.Normal.x, v.Normal.y, v.Normal.z, v.TexCoords.x, v.TexCoords.y]);
+    }
+    for &i in &mesh.indices {
+      u32s(&mut out, i);
+    }
+    u32s(&mut out, mesh.lods.len() as u32);
+    for lod in &mesh.lods {
+      u32s(&mut out, lod.len() as u32);
+      for &i in lod {
+        u32s(&mut out, i);
+      }
+    }
+    for (file, typeName) in &mesh.textures {
+      out.push(TEXTURE_TYPES.iter().position(|t| t == typeName).unwrap_or(0) as u8);
+      u32s(&mut out, file.len() as u32);
+      out.extend_from_slice(file.as_bytes());
+    }
+  }
+  out
+}
+
+fn decode(payload: &[u8]) -> Option<Vec<MeshData>> {
+  let mut r = Reader { bytes: payload, pos: 0 };
+  let count = r.u32()?;
+  let mut meshes = Vec::with_capacity(count as usize);
+  for _ in 0..count {
+    let (vertexCount, indexCount, textureCount) = (r.u32()?, r.u32()?, r.u32()?);
+    let min = Vector4::new(r.f32()?, r.f32()?, r.f32()?, 1.0);
+    let max = Vector4::new(r.f32()?, r.f32()?, r.f32()?, 1.0);
+    let material = Material {
+      diffuse: vec3(r.f32()?, r.f32()?, r.f32()?),
+      specular: vec3(r.f32()?, r.f32()?, r.f32()?),
+      shininess: r.f32()?,
+      opacity: r.f32()?
+    };
+
+    let mut vertices = Vec::with_capacity(vertexCount as usize);
+    for _ in 0..vertexCount {
+      vertices.push(Vertex {
+        Position: vec3(r.f32()?, r.f32()?, r.f32()?),
+        Normal: vec3(r.f32()?, r.f32()?, r.f32()?),
+        TexCoords: vec2(r.f32()?, r.f32()?)
+      });
+    }
+    let indices = r.u32s(indexCount as usize)?;
+    let mut lods = vec![];
+    for _ in 0..r.u32()? {
+      let count = r.u32()? as usize;
+      lods.push(r.u32s(count)?);
+    }
+    let mut textures = Vec::with_capacity(textureCount as usize);
+    for _ in 0..textureCount {
+      let typeName = *TEXTURE_TYPES.get(r.take(1)?[0] as usize)?;
+      let length = r.u32()? as usize;
+      let file = String::from_utf8(r.take(length)?.to_vec()).ok()?;
+      textures.push((file, typeName));
+    }
+    meshes.push(MeshData { vertices, indices, lods, textures, material, bounds: (min, max) });
+  }
+  Some(meshes)
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+    let slice = self.bytes.get(self.pos..self.pos + n)?;
+    self.pos += n;
+    Some(slice)
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    self.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+  }
+
+  fn u32s(&mut self, count: usize) -> Option<Vec<u32>> {
+    (0..count).map(|_| self.u32()).collect()
+  }
+
+  fn f32(&mut self) -> Option<f32> {
+    self.u32().map(f32::from_bits)
+  }
+}
+
+/// 64-bit FNV-1a, enough to catch truncated or corrupted files
+fn checksum(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn meshes() -> Vec<MeshData> {
+    let vertex = |x: f32| Vertex { Position: vec3(x, 1.0, 2.0), Normal: vec3(0.0, 1.0, 0.0), TexCoords: vec2(x, 0.5) };
+    vec![MeshData {
+      vertices: vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+      indices: vec![0, 1, 2],
+      lods: vec![vec![0, 2, 1]],
+      textures: vec![("textures/a b.png".into(), "texture_diffuse"), ("n.png".into(), "texture_normal")],
+      material: Material { diffuse: vec3(0.5, 0.25, 1.0), specular: vec3(1.0, 1.0, 1.0), shininess: 32.0, opacity: 0.6 },
+      bounds: (Vector4::new(0.0, 1.0, 2.0, 1.0), Vector4::new(2.0, 1.0, 2.0, 1.0))
+    }]
+  }
+
+  /// An OBJ path of its own in the temp directory, so tests running at once don't share a cache
+  fn objPath(name: &str) -> String {
+    std::env::temp_dir().join(format!("meshcache-{}-{}.obj", std::process::id(), name)).to_string_lossy().into()
+  }
+
+  fn readError(obj: &str) -> String {
+    match read(obj) {
+      Ok(_) => panic!("{} read back", obj),
+      Err(e) => e.to_string()
+    }
+  }
+
+  #[test]
+  fn writeThenRead() {
+    let obj = objPath("roundTrip");
+    write(&obj, &meshes()).unwrap();
+    let loaded = read(&obj).unwrap();
+    fs::remove_file(cachePath(&obj)).unwrap();
+    // Encoding covers every field, so equal bytes mean nothing was lost
+    assert_eq!(encode(&loaded), encode(&meshes()));
+    assert_eq!(loaded[0].textures[1], ("n.png".to_string(), "texture_normal"));
+  }
+
+  #[test]
+  fn rejectsDamagedFiles() {
+    let obj = objPath("damaged");
+    write(&obj, &meshes()).unwrap();
+    let bytes = fs::read(cachePath(&obj)).unwrap();
+
+    let mut flipped = bytes.clone();
+    flipped[HEADER_SIZE + 10] ^= 1;
+    fs::write(cachePath(&obj), &flipped).unwrap();
+    assert!(readError(&obj).contains("checksum mismatch"));
+
+    fs::write(cachePath(&obj), &bytes[..bytes.len() - 3]).unwrap();
+    assert!(readError(&obj).contains("checksum mismatch"));
+    fs::write(cachePath(&obj), &bytes[..HEADER_SIZE - 1]).unwrap();
+    assert!(readError(&obj).contains("not a mesh cache"));
+    fs::remove_file(cachePath(&obj)).unwrap();
+    // The checksum catches truncation first, but the decoder mustn't read past the end either
+    let payload = &bytes[HEADER_SIZE..];
+    assert!(decode(&payload[..payload.len() - 3]).is_none());
+  }
+
+  #[test]
+  fn rejectsOtherVersions() {
+    let obj = objPath("version");
+    write(&obj, &meshes()).unwrap();
+    let mut bytes = fs::read(cachePath(&obj)).unwrap();
+    bytes[4..8].copy_from_slice(&(VERSION - 1).to_le_bytes());
+    fs::write(cachePath(&obj), &bytes).unwrap();
+    let error = readError(&obj);
+    fs::remove_file(cachePath(&obj)).unwrap();
+    assert!(error.contains(&format!("version {} but expected {}", VERSION - 1, VERSION)), "{}", error);
+  }
+}
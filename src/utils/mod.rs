@@ -1,5 +1,16 @@
 pub mod common;
+pub mod compressed;
+pub mod error;
+pub mod font8x8;
+pub mod glsl;
 pub mod macros;
 pub mod model;
+pub mod quadbatch;
 pub mod shader;
+pub mod simplify;
 pub mod maths;
+pub mod meshcache;
+pub mod target;
+pub mod truetype;
+pub mod uniforms;
+pub mod watcher;
@@ -1,87 +1,230 @@
 #![allow(non_snake_case)]
-use std::collections::HashMap;
+use std::fs;
 use std::path::Path;
 
-use cgmath::{vec2, vec3};
+use cgmath::{vec2, vec3, Deg};
 use tobj;
 
-use super::common::*;
-use crate::mesh::{Mesh, Texture, Vertex};
+use super::common::ColourSpace;
+use super::error::AssetError;
+use super::maths::{computeBoundingBox, generateNormals};
+use super::meshcache;
+use super::simplify::lodChain;
+use crate::assets::AssetManager;
+use crate::mesh::{Material, Mesh, Texture, Vertex};
+use crate::types::Vector4;
+
+/// Faces meeting at a sharper angle than this get a hard edge when an OBJ has no normals
+const CREASE_ANGLE: Deg<f32> = Deg(60.0);
+/// Triangle counts of the generated LODs relative to the full mesh
+pub const LOD_RATIOS: [f32; 3] = [0.5, 0.25, 0.1];
+/// Share of the screen height a model must cover to be drawn above each LOD in `LOD_RATIOS`
+pub const LOD_SCREEN_SIZES: [f32; 3] = [0.4, 0.2, 0.08];
 
 pub struct Model {
-  pub meshes: Vec<Mesh>,
-  pub texturesLoaded: HashMap<String, Texture>,
-  directory: String,
+  pub meshes: Vec<Mesh>
+}
+
+/// CPU side of a mesh, produced off the main thread and uploaded later
+pub struct MeshData {
+  pub vertices: Vec<Vertex>,
+  pub indices: Vec<u32>,
+  /// Simplified index lists into the same vertices, see `LOD_RATIOS`
+  pub lods: Vec<Vec<u32>>,
+  /// Texture files relative to the working directory, with the sampler type each is bound as
+  pub textures: Vec<(String, &'static str)>,
+  pub material: Material,
+  pub bounds: (Vector4, Vector4)
 }
 
 impl Model {
-  pub fn new(path: &str) -> Model {
-    let mut model = Model { meshes: vec![], texturesLoaded: HashMap::default(), directory: String::default() };
-    model.loadModel(path);
-    model
+  /// Reads the baked mesh cache if it is newer than the OBJ, otherwise parses the OBJ itself
+  pub fn load(path: &str) -> Result<Vec<MeshData>, AssetError> {
+    if meshcache::isFresh(path) {
+      match meshcache::read(path) {
+        Ok(meshes) => return Ok(meshes),
+        Err(e) => println!("{}, parsing {} instead", e, path)
+      }
+    }
+    Model::parse(path)
   }
 
-  fn loadModel(&mut self, path: &str) {
-    let path = Path::new(path);
-    self.directory = path.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
-    let obj = tobj::load_obj(path);
+  /// Reads the OBJ and its materials without touching GL, so this can run on a worker thread
+  pub fn parse(path: &str) -> Result<Vec<MeshData>, AssetError> {
+    let source = fs::read_to_string(path).map_err(|error| AssetError::Io { path: path.into(), error })?;
+    checkPrimitives(path, &source)?;
+    let parent = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let (models, materials) = tobj::load_obj_buf(&mut source.as_bytes(), |mtl| tobj::load_mtl(&parent.join(mtl)))
+      .map_err(|e| AssetError::fromObj(path, e))?;
+    let directory = parent.to_string_lossy();
+    let mut meshes = Vec::with_capacity(models.len());
 
-    let (models, materials) = obj.unwrap();
     for model in models {
       let mesh = &model.mesh;
       let num_vertices = mesh.positions.len() / 3;
 
       let mut vertices: Vec<Vertex> = Vec::with_capacity(num_vertices);
-      let indices: Vec<u32> = mesh.indices.clone();
+      let mut indices: Vec<u32> = mesh.indices.clone();
 
+      // tobj only fills in the normals and texcoords that faces reference, so anything short of
+      // one per vertex means some faces went without and they can't be lined up with positions
       let (p, n, t) = (&mesh.positions, &mesh.normals, &mesh.texcoords);
+      let hasNormals = n.len() == num_vertices * 3;
+      let hasTexCoords = t.len() == num_vertices * 2;
       for i in 0..num_vertices {
         vertices.push(Vertex {
           Position: vec3(p[i*3], p[i*3+1], p[i*3+2]),
-          Normal: vec3(n[i * 3], n[i * 3 + 1], n[i * 3 + 2]),
-          TexCoords: vec2(t[i * 2], t[i * 2 + 1]),
-          ..Vertex::default()
+          Normal: if hasNormals { vec3(n[i * 3], n[i * 3 + 1], n[i * 3 + 2]) } else { vec3(0.0, 0.0, 0.0) },
+          TexCoords: if hasTexCoords { vec2(t[i * 2], t[i * 2 + 1]) } else { vec2(0.0, 0.0) }
         })
       }
+      if !hasNormals {
+        generateNormals(&mut vertices, &mut indices, CREASE_ANGLE);
+      }
 
       let mut textures = Vec::new();
+      let mut material = Material::default();
       if let Some(material_id) = mesh.material_id {
-        let material = &materials[material_id];
-
-        if !material.diffuse_texture.is_empty() {
-          let texture = self.loadMaterialTexture(&material.diffuse_texture, "texture_diffuse");
-          textures.push(texture);
+        let mtl = &materials[material_id];
+        let maps = [
+          (&mtl.diffuse_texture, "texture_diffuse"),
+          (&mtl.specular_texture, "texture_specular"),
+          (&mtl.normal_texture, "texture_normal"),
+          (&mtl.dissolve_texture, "texture_alpha")
+        ];
+        for (file, typeName) in maps.iter().filter(|(file, _)| !file.is_empty()) {
+          textures.push((format!("{}/{}", directory, file), *typeName));
         }
+        material = Material {
+          diffuse: mtl.diffuse.into(),
+          specular: mtl.specular.into(),
+          shininess: mtl.shininess,
+          opacity: mtl.dissolve
+        };
+      }
 
-        if !material.specular_texture.is_empty() {
-          let texture = self.loadMaterialTexture(&material.specular_texture, "texture_specular");
-          textures.push(texture);
-        }
+      let bounds = computeBoundingBox(&vertices);
+      let lods = lodChain(&vertices, &indices, &LOD_RATIOS);
+      meshes.push(MeshData { vertices, indices, lods, textures, material, bounds });
+    }
+    Ok(meshes)
+  }
 
-        if !material.normal_texture.is_empty() {
-          let texture = self.loadMaterialTexture(&material.normal_texture, "texture_normal");
-          textures.push(texture);
-        }
-      }
+  /// Wraps meshes built in code, e.g. the terrain, so they can be used like a loaded model
+  pub fn fromMeshes(meshes: Vec<Mesh>) -> Model {
+    Model { meshes }
+  }
+}
+
+/// tobj passes lines through as two-index "triangles" and silently drops points, so both are
+/// turned away up front rather than drawn as garbage
+fn checkPrimitives(path: &str, source: &str) -> Result<(), AssetError> {
+  for (number, line) in source.lines().enumerate() {
+    let mut words = line.split_whitespace();
+    let reason = match words.next() {
+      Some("l") => "lines",
+      Some("p") => "points",
+      Some("f") if words.count() < 3 => "faces with fewer than three vertices",
+      _ => continue
+    };
+    return Err(AssetError::Unsupported { path: path.into(), line: number + 1, reason: reason.into() });
+  }
+  Ok(())
+}
+
+impl MeshData {
+  pub fn upload(self, assets: &mut AssetManager) -> Mesh {
+    let textures = self.textures.iter()
+      .map(|(path, typeName)| {
+        // Only colour maps are sRGB, normals, specular intensity and alpha masks are plain data
+        let space = if *typeName == "texture_diffuse" { ColourSpace::Srgb } else { ColourSpace::Linear };
+        Texture { resource: assets.texture(path, space), type_: typeName.to_string() }
+      })
+      .collect();
+    let mut mesh = Mesh::withLods(self.vertices, self.indices, self.lods, textures, self.bounds);
+    mesh.material = Some(self.material);
+    mesh
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{vec2, vec3, Deg, InnerSpace};
+
+  use super::*;
+  use crate::types::Vector3;
+
+  const FIXTURES: &str = "resources/objects/fixtures";
+
+  fn parse(name: &str) -> Result<Vec<MeshData>, AssetError> {
+    Model::parse(&format!("{}/{}", FIXTURES, name))
+  }
 
-      self.meshes.push(Mesh::new(vertices, indices, textures));
+  fn assertClose(a: Vector3, b: Vector3) {
+    assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+  }
+
+  #[test]
+  fn missingNormalsAndTexCoordsAreFilledIn() {
+    let meshes = parse("triangle.obj").unwrap();
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0].indices.len(), 3);
+    for v in &meshes[0].vertices {
+      assertClose(v.Normal, vec3(0.0, 0.0, 1.0));
+      assert_eq!(v.TexCoords, vec2(0.0, 0.0));
     }
   }
 
-  fn loadMaterialTexture(&mut self, path: &str, typeName: &str) -> Texture {
-    {
-      let texOpt = self.texturesLoaded.get(path);
-      if let Some(tex) = texOpt {
-        return tex.clone();
-      }
+  #[test]
+  fn quadsAreTriangulatedAndKeepTheirTexCoords() {
+    let meshes = parse("quad.obj").unwrap();
+    let mesh = &meshes[0];
+    assert_eq!(mesh.indices.len(), 6);
+    // Both triangles are coplanar so the shared corners aren't split
+    assert_eq!(mesh.vertices.len(), 4);
+    for v in &mesh.vertices {
+      assertClose(v.Normal, vec3(0.0, 1.0, 0.0));
     }
+    assert!(mesh.vertices.iter().any(|v| v.TexCoords == vec2(1.0, 1.0)));
+  }
 
-    let texture = Texture {
-      id: unsafe { textureFromFile(path, &self.directory) },
-      type_: typeName.into(),
-      path: path.into(),
-    };
-    self.texturesLoaded.insert(path.into(), texture.clone());
-    texture
+  #[test]
+  fn creasesSplitCubeCorners() {
+    let meshes = parse("cube.obj").unwrap();
+    let mesh = &meshes[0];
+    assert_eq!(mesh.indices.len(), 36);
+    assert_eq!(mesh.vertices.len(), 24);
+    for v in &mesh.vertices {
+      let n = v.Normal;
+      // Every normal points straight out of one face
+      assert!((n.x.abs() + n.y.abs() + n.z.abs() - 1.0).abs() < 1e-5, "{:?}", n);
+      assert!(n.dot(v.Position) > 0.0);
+    }
+  }
+
+  #[test]
+  fn smoothNormalsAverageAcrossFaces() {
+    let mut mesh = parse("cube.obj").unwrap().remove(0);
+    generateNormals(&mut mesh.vertices, &mut mesh.indices, Deg(180.0));
+    for v in &mesh.vertices {
+      assertClose(v.Normal, v.Position.normalize());
+    }
+  }
+
+  #[test]
+  fn existingNormalsAreKept() {
+    let meshes = parse("normals.obj").unwrap();
+    for v in &meshes[0].vertices {
+      assertClose(v.Normal, vec3(0.0, 0.6, 0.8));
+    }
+  }
+
+  #[test]
+  fn linesAreRejected() {
+    match parse("lines.obj") {
+      Err(AssetError::Unsupported { line, .. }) => assert_eq!(line, 6),
+      Err(e) => panic!("unexpected error {}", e),
+      Ok(_) => panic!("lines.obj should not load")
+    }
   }
 }
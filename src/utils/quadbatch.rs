@@ -0,0 +1,94 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ffi::CStr;
+use std::mem::{size_of, size_of_val};
+use std::os::raw::c_void;
+use std::ptr;
+
+use cgmath::vec2;
+
+use crate::assets::{AssetManager, ShaderHandle};
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UiVertex {
+  pub position: [f32; 2],
+  pub texCoords: [f32; 2],
+  pub colour: [f32; 4]
+}
+
+/// Two triangles covering `(x0, y0)` to `(x1, y1)` in framebuffer pixels, with `uv` as
+/// `[u0, v0, u1, v1]` in the same corners
+pub fn pushQuad(vertices: &mut Vec<UiVertex>, (x0, y0, x1, y1): (f32, f32, f32, f32), uv: [f32; 4], colour: [f32; 4]) {
+  let corner = |x: f32, y: f32, u: f32, v: f32| UiVertex { position: [x, y], texCoords: [u, v], colour };
+  vertices.extend_from_slice(&[
+    corner(x0, y0, uv[0], uv[1]), corner(x0, y1, uv[0], uv[3]), corner(x1, y1, uv[2], uv[3]),
+    corner(x0, y0, uv[0], uv[1]), corner(x1, y1, uv[2], uv[3]), corner(x1, y0, uv[2], uv[1])
+  ]);
+}
+
+/// Vertex buffer and shader for screen-space quads tinted by a single channel coverage texture,
+/// shared by the debug UI and text rendering
+pub struct QuadBatch {
+  shader: ShaderHandle,
+  VAO: u32,
+  VBO: u32
+}
+
+impl QuadBatch {
+  pub fn new(assets: &mut AssetManager) -> QuadBatch {
+    let mut batch = QuadBatch { shader: assets.shader("src/shaders/uiVertex.vs", "src/shaders/uiFragment.fs"), VAO: 0, VBO: 0 };
+    unsafe {
+      gl::GenVertexArrays(1, &mut batch.VAO);
+      gl::GenBuffers(1, &mut batch.VBO);
+      gl::BindVertexArray(batch.VAO);
+      gl::BindBuffer(gl::ARRAY_BUFFER, batch.VBO);
+      let stride = size_of::<UiVertex>() as i32;
+      gl::EnableVertexAttribArray(0);
+      gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+      gl::EnableVertexAttribArray(1);
+      gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (2 * size_of::<f32>()) as *const c_void);
+      gl::EnableVertexAttribArray(2);
+      gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, (4 * size_of::<f32>()) as *const c_void);
+      gl::BindVertexArray(0);
+    }
+    batch
+  }
+
+  /// Draws `vertices` over whatever framebuffer is bound, blended and without depth testing
+  pub unsafe fn render(&self, vertices: &[UiVertex], texture: u32, screen: (f32, f32)) {
+    if vertices.is_empty() {
+      return;
+    }
+    gl::Disable(gl::DEPTH_TEST);
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+    self.shader.useProgram();
+    self.shader.setVector2(c_str!("screenSize"), &vec2(screen.0, screen.1));
+    self.shader.setInt(c_str!("font"), 0);
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+
+    gl::BindVertexArray(self.VAO);
+    gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
+    let size = size_of_val(vertices) as isize;
+    gl::BufferData(gl::ARRAY_BUFFER, size, vertices.as_ptr() as *const c_void, gl::STREAM_DRAW);
+    gl::DrawArrays(gl::TRIANGLES, 0, vertices.len() as i32);
+    gl::BindVertexArray(0);
+
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+    gl::Disable(gl::BLEND);
+    gl::Enable(gl::DEPTH_TEST);
+  }
+}
+
+impl Drop for QuadBatch {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteBuffers(1, &self.VBO);
+      gl::DeleteVertexArrays(1, &self.VAO);
+    }
+  }
+}
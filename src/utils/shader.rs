@@ -2,156 +2,377 @@
 use gl;
 use gl::types::*;
 
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::Read;
-use std::{ptr, str};
+use std::path::Path;
+use std::ptr;
 
 use cgmath::prelude::*;
 
+use super::error::{AssetError, ShaderError, ShaderStage};
+use super::glsl::{errorLines, Source};
+use super::uniforms::FRAME_BINDING;
+use crate::mesh::Material;
 use crate::types::*;
 
+// Stand-in program for shaders that fail to build, drawing geometry in flat magenta
+const ERROR_VERTEX: &str = "#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (std140) uniform Frame { mat4 view; mat4 projection; } frame;
+uniform mat4 model;
+void main() { gl_Position = frame.projection * frame.view * model * vec4(aPos, 1.0); }
+";
+const ERROR_FRAGMENT: &str = "#version 330 core
+out vec4 FragColor;
+void main() { FragColor = vec4(1.0, 0.0, 1.0, 1.0); }
+";
+
+/// Last value written to a uniform, replayed into the new program after a hot reload
+#[derive(Clone, Copy)]
+enum UniformValue {
+  Int(i32),
+  Float(f32),
+  Vector2(Vector2),
+  Vector3(Vector3),
+  Vector4(Vector4),
+  Mat4(Matrix4)
+}
+
 pub struct Shader {
-  pub ID: u32
+  ID: Cell<u32>,
+  vertexPath: String,
+  fragmentPath: String,
+  uniforms: RefCell<HashMap<CString, UniformValue>>,
+  /// Locations of every active uniform, looked up once per link
+  locations: RefCell<HashMap<CString, i32>>,
+  unknown: RefCell<HashSet<CString>>,
+  /// Every file the last build read, including `#include`d ones
+  files: RefCell<Vec<String>>,
+  /// Most recent compile/link failure, cleared once a build succeeds
+  pub error: RefCell<Option<AssetError>>
 }
 
 #[allow(dead_code)]
 impl Shader {
   pub fn new(vertexPath: &str, fragmentPath: &str) -> Shader {
-    let mut shader = Shader { ID: 0 };
+    let shader = Shader {
+      ID: Cell::new(0),
+      vertexPath: vertexPath.into(),
+      fragmentPath: fragmentPath.into(),
+      uniforms: RefCell::new(HashMap::default()),
+      locations: RefCell::new(HashMap::default()),
+      unknown: RefCell::new(HashSet::default()),
+      files: RefCell::new(vec![vertexPath.into(), fragmentPath.into()]),
+      error: RefCell::new(None)
+    };
 
-    let mut vShaderFile = File::open(vertexPath).unwrap_or_else(|_| panic!("Failed to open {}", vertexPath));
-    let mut fShaderFile = File::open(fragmentPath).unwrap_or_else(|_| panic!("Failed to open {}", fragmentPath));
+    let ID = match shader.compile() {
+      Ok(ID) => ID,
+      Err(e) => {
+        println!("{}", e);
+        *shader.error.borrow_mut() = Some(e);
+        unsafe { errorProgram() }
+      }
+    };
+    unsafe { shader.setProgram(ID) };
+    shader
+  }
 
-    let mut vertexCode = String::new();
-    let mut fragmentCode = String::new();
-    vShaderFile
-      .read_to_string(&mut vertexCode)
-      .expect("Failed to read vertex shader");
-    fShaderFile
-      .read_to_string(&mut fragmentCode)
-      .expect("Failed to read fragment shader");
+  pub fn id(&self) -> u32 {
+    self.ID.get()
+  }
 
-    let vShaderCode = CString::new(vertexCode.as_bytes()).unwrap();
-    let fShaderCode = CString::new(fragmentCode.as_bytes()).unwrap();
+  /// True if `path` is one of the source files this program is built from
+  pub fn watches(&self, path: &Path) -> bool {
+    self.files.borrow().iter().any(|f| Path::new(f) == path)
+  }
 
-    unsafe {
-      let vertex = gl::CreateShader(gl::VERTEX_SHADER);
-      gl::ShaderSource(vertex, 1, &vShaderCode.as_ptr(), ptr::null());
-      gl::CompileShader(vertex);
-      shader.checkCompileErrors(vertex, "VERTEX");
-
-      let fragment = gl::CreateShader(gl::FRAGMENT_SHADER);
-      gl::ShaderSource(fragment, 1, &fShaderCode.as_ptr(), ptr::null());
-      gl::CompileShader(fragment);
-      shader.checkCompileErrors(fragment, "FRAGMENT");
-
-      let ID = gl::CreateProgram();
-      gl::AttachShader(ID, vertex);
-      gl::AttachShader(ID, fragment);
-      gl::LinkProgram(ID);
-      shader.checkCompileErrors(ID, "PROGRAM");
-
-      gl::DeleteShader(vertex);
-      gl::DeleteShader(fragment);
-      shader.ID = ID;
+  pub fn sourcePaths(&self) -> Vec<String> {
+    self.files.borrow().clone()
+  }
+
+  /// Recompiles from disk. On failure the previous program stays bound and the log is kept
+  /// in `error`; on success the previous program is deleted and every uniform set so far is
+  /// uploaded again to the new one
+  pub fn reload(&self) -> bool {
+    match self.compile() {
+      Ok(ID) => {
+        unsafe {
+          gl::DeleteProgram(self.ID.get());
+          self.setProgram(ID);
+          self.unknown.borrow_mut().clear();
+          self.useProgram();
+          for (name, value) in self.uniforms.borrow().iter() {
+            self.upload(name, *value);
+          }
+        }
+        *self.error.borrow_mut() = None;
+        true
+      }
+      Err(e) => {
+        println!("{}", e);
+        *self.error.borrow_mut() = Some(e);
+        false
+      }
     }
+  }
 
-    shader
+  unsafe fn setProgram(&self, ID: u32) {
+    self.ID.set(ID);
+    *self.locations.borrow_mut() = activeUniforms(ID);
+    let block = gl::GetUniformBlockIndex(ID, c_str!("Frame").as_ptr());
+    if block != gl::INVALID_INDEX {
+      gl::UniformBlockBinding(ID, block, FRAME_BINDING);
+    }
+  }
+
+  fn compile(&self) -> Result<u32, AssetError> {
+    let vertexSource = Source::load(&self.vertexPath)?;
+    let fragmentSource = Source::load(&self.fragmentPath)?;
+    {
+      let mut files = self.files.borrow_mut();
+      files.clear();
+      for file in vertexSource.files.iter().chain(fragmentSource.files.iter()) {
+        if !files.contains(file) {
+          files.push(file.clone());
+        }
+      }
+    }
+
+    unsafe {
+      let vertex = compileStage(ShaderStage::Vertex, &self.vertexPath, &vertexSource)?;
+      let fragment = match compileStage(ShaderStage::Fragment, &self.fragmentPath, &fragmentSource) {
+        Ok(f) => f,
+        Err(e) => {
+          gl::DeleteShader(vertex);
+          return Err(e);
+        }
+      };
+
+      linkProgram(vertex, fragment).map_err(|log| AssetError::Shader(ShaderError {
+        stage: ShaderStage::Link,
+        path: format!("{} + {}", self.vertexPath, self.fragmentPath),
+        log,
+        lines: vec![]
+      }))
+    }
   }
 
-  pub fn initShader(&self, model: &Matrix4, view: &Matrix4, projection: &Matrix4) {
+  pub fn updateModel(&self, model: &Matrix4) {
     unsafe {
       self.useProgram();
       self.setMat4(c_str!("model"), model);
-      self.setMat4(c_str!("view"), view);
-      self.setMat4(c_str!("projection"), projection);
     }
   }
-  
-  pub fn updateModel(&self, model: &Matrix4) {
+
+  pub fn loadFog(&self, colour: &Vector3, density: f32, gradient: f32) {
     unsafe {
       self.useProgram();
-      self.setMat4(c_str!("model"), model);
+      self.setVector3(c_str!("skyColour"), colour);
+      self.setFloat(c_str!("fogDensity"), density);
+      self.setFloat(c_str!("fogGradient"), gradient);
     }
   }
 
-  pub fn loadLight(&self, position: &Vector3, colour: &Vector3, attenuation: &Vector3) {
+  pub fn loadClipPlane(&self, plane: &Vector4) {
     unsafe {
       self.useProgram();
-      self.setVector3(c_str!("lightPosition"), position);
-      self.setVector3(c_str!("lightColour"), colour);
-      self.setVector3(c_str!("attenuation"), attenuation);
+      self.setVector4(c_str!("plane"), plane);
     }
   }
 
-  pub fn loadShine(&self, damper: f32, reflectivity: f32) {
+  pub fn loadWater(&self, height: f32, tint: &Vector3) {
     unsafe {
       self.useProgram();
-      self.setFloat(c_str!("shineDamper"), damper);
-      self.setFloat(c_str!("reflectivity"), reflectivity);
+      self.setFloat(c_str!("waterHeight"), height);
+      self.setVector3(c_str!("waterTint"), tint);
+    }
+  }
+
+  pub fn loadMaterial(&self, material: &Material) {
+    unsafe {
+      self.useProgram();
+      self.setVector3(c_str!("material.diffuse"), &material.diffuse);
+      self.setVector3(c_str!("material.specular"), &material.specular);
+      self.setFloat(c_str!("material.shininess"), material.shininess);
+      self.setFloat(c_str!("material.opacity"), material.opacity);
     }
   }
 
   pub unsafe fn useProgram(&self) {
-    gl::UseProgram(self.ID)
+    gl::UseProgram(self.ID.get())
   }
 
   pub unsafe fn setBool(&self, name: &CStr, value: bool) {
-    gl::Uniform1i(gl::GetUniformLocation(self.ID, name.as_ptr()), value as i32);
+    self.set(name, UniformValue::Int(value as i32));
   }
 
   pub unsafe fn setInt(&self, name: &CStr, value: i32) {
-    gl::Uniform1i(gl::GetUniformLocation(self.ID, name.as_ptr()), value);
+    self.set(name, UniformValue::Int(value));
   }
 
   pub unsafe fn setFloat(&self, name: &CStr, value: f32) {
-    gl::Uniform1f(gl::GetUniformLocation(self.ID, name.as_ptr()), value);
+    self.set(name, UniformValue::Float(value));
+  }
+
+  pub unsafe fn setVector2(&self, name: &CStr, value: &Vector2) {
+    self.set(name, UniformValue::Vector2(*value));
   }
 
   pub unsafe fn setVector3(&self, name: &CStr, value: &Vector3) {
-    gl::Uniform3fv(gl::GetUniformLocation(self.ID, name.as_ptr()), 1, value.as_ptr());
+    self.set(name, UniformValue::Vector3(*value));
+  }
+
+  pub unsafe fn setVector4(&self, name: &CStr, value: &Vector4) {
+    self.set(name, UniformValue::Vector4(*value));
   }
 
   pub unsafe fn setVec3(&self, name: &CStr, x: f32, y: f32, z: f32) {
-    gl::Uniform3f(gl::GetUniformLocation(self.ID, name.as_ptr()), x, y, z);
+    self.set(name, UniformValue::Vector3(Vector3 { x, y, z }));
   }
 
   pub unsafe fn setMat4(&self, name: &CStr, mat: &Matrix4) {
-    gl::UniformMatrix4fv(
-      gl::GetUniformLocation(self.ID, name.as_ptr()),
-      1,
-      gl::FALSE,
-      mat.as_ptr(),
-    );
-  }
-
-  unsafe fn checkCompileErrors(&self, shader: u32, type_: &str) {
-    let mut success = gl::FALSE as GLint;
-    let mut infoLog = Vec::with_capacity(1024);
-    infoLog.set_len(1024 - 1); // subtract 1 to skip the trailing null character
-    if type_ != "PROGRAM" {
-      gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-      if success != gl::TRUE as GLint {
-        gl::GetShaderInfoLog(shader, 1024, ptr::null_mut(), infoLog.as_mut_ptr() as *mut GLchar);
-        println!(
-          "ERROR::SHADER_COMPILATION_ERROR of type: {}\n{}\n \
-                          -- --------------------------------------------------- -- ",
-          type_,
-          str::from_utf8(&infoLog).unwrap()
-        );
-      }
+    self.set(name, UniformValue::Mat4(*mat));
+  }
+
+  unsafe fn set(&self, name: &CStr, value: UniformValue) {
+    self.upload(name, value);
+    let mut uniforms = self.uniforms.borrow_mut();
+    match uniforms.get_mut(name) {
+      Some(v) => *v = value,
+      None => { uniforms.insert(name.to_owned(), value); }
+    }
+  }
+
+  unsafe fn upload(&self, name: &CStr, value: UniformValue) {
+    let location = match self.locations.borrow().get(name) {
+      Some(location) => *location,
+      None => return self.warnUnknown(name)
+    };
+    match value {
+      UniformValue::Int(v) => gl::Uniform1i(location, v),
+      UniformValue::Float(v) => gl::Uniform1f(location, v),
+      UniformValue::Vector2(v) => gl::Uniform2fv(location, 1, v.as_ptr()),
+      UniformValue::Vector3(v) => gl::Uniform3fv(location, 1, v.as_ptr()),
+      UniformValue::Vector4(v) => gl::Uniform4fv(location, 1, v.as_ptr()),
+      UniformValue::Mat4(m) => gl::UniformMatrix4fv(location, 1, gl::FALSE, m.as_ptr())
+    }
+  }
+
+  /// Names the program doesn't use (or the driver optimised away) are reported once per build
+  fn warnUnknown(&self, name: &CStr) {
+    // The error program only knows about `model`, so there's nothing useful to say
+    if self.error.borrow().is_some() || self.unknown.borrow().contains(name) {
+      return;
+    }
+    println!("{} + {}: no active uniform named {:?}", self.vertexPath, self.fragmentPath, name);
+    self.unknown.borrow_mut().insert(name.to_owned());
+  }
+}
+
+impl Drop for Shader {
+  fn drop(&mut self) {
+    unsafe { gl::DeleteProgram(self.ID.get()) }
+  }
+}
+
+/// Locations of every active uniform outside a block, with array elements listed individually
+unsafe fn activeUniforms(ID: u32) -> HashMap<CString, i32> {
+  let mut count = 0;
+  let mut maxLength = 0;
+  gl::GetProgramiv(ID, gl::ACTIVE_UNIFORMS, &mut count);
+  gl::GetProgramiv(ID, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut maxLength);
+
+  let mut locations = HashMap::default();
+  for i in 0..count as u32 {
+    let mut name = vec![0u8; maxLength.max(1) as usize];
+    let (mut length, mut size, mut type_) = (0, 0, 0);
+    gl::GetActiveUniform(ID, i, maxLength, &mut length, &mut size, &mut type_, name.as_mut_ptr() as *mut GLchar);
+    name.truncate(length as usize);
+    let name = String::from_utf8_lossy(&name).into_owned();
+
+    // Arrays are reported once as `name[0]`, but are set element by element
+    let base = if name.ends_with("[0]") { &name[..name.len() - 3] } else { &name[..] };
+    let names = if size > 1 || base.len() != name.len() {
+      let mut names: Vec<String> = (0..size).map(|e| format!("{}[{}]", base, e)).collect();
+      names.push(base.into());
+      names
     } else {
-      gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success);
-      if success != gl::TRUE as GLint {
-        gl::GetProgramInfoLog(shader, 1024, ptr::null_mut(), infoLog.as_mut_ptr() as *mut GLchar);
-        println!(
-          "ERROR::PROGRAM_LINKING_ERROR of type: {}\n{}\n \
-                          -- --------------------------------------------------- -- ",
-          type_,
-          str::from_utf8(&infoLog).unwrap()
-        );
+      vec![name.clone()]
+    };
+
+    for name in names {
+      let name = CString::new(name).unwrap();
+      let location = gl::GetUniformLocation(ID, name.as_ptr());
+      // Block members have no location of their own
+      if location != -1 {
+        locations.insert(name, location);
       }
     }
   }
+  locations
+}
+
+/// Compiles one stage, quoting the source lines the driver's log points at on failure
+unsafe fn compileStage(stage: ShaderStage, path: &str, source: &Source) -> Result<u32, AssetError> {
+  let type_ = if stage == ShaderStage::Vertex { gl::VERTEX_SHADER } else { gl::FRAGMENT_SHADER };
+  compileSource(type_, &source.code).map_err(|log| {
+    let lines = errorLines(&log).into_iter().filter_map(|l| source.locate(l)).collect();
+    AssetError::Shader(ShaderError { stage, path: path.into(), log, lines })
+  })
+}
+
+unsafe fn compileSource(type_: GLenum, code: &str) -> Result<u32, String> {
+  let code = CString::new(code.as_bytes()).map_err(|_| "source contains a nul byte".to_string())?;
+  let shader = gl::CreateShader(type_);
+  gl::ShaderSource(shader, 1, &code.as_ptr(), ptr::null());
+  gl::CompileShader(shader);
+
+  let mut success = gl::FALSE as GLint;
+  gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+  if success != gl::TRUE as GLint {
+    let mut length = 0;
+    gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0u8; length.max(1) as usize];
+    gl::GetShaderInfoLog(shader, length, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+    gl::DeleteShader(shader);
+    return Err(infoLog(log));
+  }
+  Ok(shader)
+}
+
+unsafe fn linkProgram(vertex: u32, fragment: u32) -> Result<u32, String> {
+  let ID = gl::CreateProgram();
+  gl::AttachShader(ID, vertex);
+  gl::AttachShader(ID, fragment);
+  gl::LinkProgram(ID);
+  gl::DeleteShader(vertex);
+  gl::DeleteShader(fragment);
+
+  let mut success = gl::FALSE as GLint;
+  gl::GetProgramiv(ID, gl::LINK_STATUS, &mut success);
+  if success != gl::TRUE as GLint {
+    let mut length = 0;
+    gl::GetProgramiv(ID, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0u8; length.max(1) as usize];
+    gl::GetProgramInfoLog(ID, length, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+    gl::DeleteProgram(ID);
+    return Err(infoLog(log));
+  }
+  Ok(ID)
+}
+
+fn infoLog(mut log: Vec<u8>) -> String {
+  // Drop the trailing nul the driver writes
+  while log.last() == Some(&0) {
+    log.pop();
+  }
+  String::from_utf8_lossy(&log).into_owned()
+}
+
+unsafe fn errorProgram() -> u32 {
+  let vertex = compileSource(gl::VERTEX_SHADER, ERROR_VERTEX).expect("Built-in error shader failed to compile");
+  let fragment = compileSource(gl::FRAGMENT_SHADER, ERROR_FRAGMENT).expect("Built-in error shader failed to compile");
+  linkProgram(vertex, fragment).expect("Built-in error shader failed to link")
 }
@@ -0,0 +1,284 @@
+#![allow(non_snake_case)]
+use std::collections::{HashMap, HashSet};
+
+use cgmath::InnerSpace;
+
+use super::maths::positionKey;
+use crate::mesh::Vertex;
+use crate::types::Vector3;
+
+/// Symmetric 4x4 matrix summing squared distances to a set of planes (Garland & Heckbert)
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+  a: [f64; 10]
+}
+
+impl Quadric {
+  fn fromPlane(n: Vector3, d: f32, weight: f32) -> Quadric {
+    let (x, y, z, d, w) = (n.x as f64, n.y as f64, n.z as f64, d as f64, weight as f64);
+    Quadric { a: [x*x*w, x*y*w, x*z*w, x*d*w, y*y*w, y*z*w, y*d*w, z*z*w, z*d*w, d*d*w] }
+  }
+
+  fn add(&mut self, other: &Quadric) {
+    for i in 0..10 {
+      self.a[i] += other.a[i];
+    }
+  }
+
+  fn error(&self, p: Vector3) -> f64 {
+    let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+    let a = &self.a;
+    let e = a[0]*x*x + 2.0*a[1]*x*y + 2.0*a[2]*x*z + 2.0*a[3]*x
+          + a[4]*y*y + 2.0*a[5]*y*z + 2.0*a[6]*y
+          + a[7]*z*z + 2.0*a[8]*z
+          + a[9];
+    e.max(0.0)
+  }
+}
+
+/// Reduces a triangle list to at most `targetTriangles` where it can, by collapsing vertices into
+/// a neighbour in order of least quadric error. Only the index list changes so every LOD can share
+/// the original vertex buffer. Vertices on open borders or UV/normal seams never move, which keeps
+/// the outline and texture mapping intact but can stop short of the target
+pub fn simplify(vertices: &[Vertex], indices: &[u32], targetTriangles: usize) -> Vec<u32> {
+  let mut indices = indices.to_vec();
+
+  // Vertices split only by their attributes share a position and are locked as seams
+  let mut firstAt: HashMap<[u32; 3], u32> = HashMap::new();
+  let mut locked = vec![false; vertices.len()];
+  let mut canonical = vec![0u32; vertices.len()];
+  for &i in &indices {
+    let first = *firstAt.entry(positionKey(&vertices[i as usize].Position)).or_insert(i);
+    canonical[i as usize] = first;
+    if first != i {
+      locked[i as usize] = true;
+      locked[first as usize] = true;
+    }
+  }
+
+  // Edges used by a single triangle are borders
+  let mut edgeUses: HashMap<(u32, u32), u32> = HashMap::new();
+  for t in indices.chunks(3) {
+    for e in 0..3 {
+      let (a, b) = (canonical[t[e] as usize], canonical[t[(e + 1) % 3] as usize]);
+      *edgeUses.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+    }
+  }
+  for (&(a, b), &uses) in &edgeUses {
+    if uses == 1 {
+      locked[a as usize] = true;
+      locked[b as usize] = true;
+    }
+  }
+
+  let mut quadrics = vec![Quadric::default(); vertices.len()];
+  for t in indices.chunks(3) {
+    let (p0, p1, p2) = (vertices[t[0] as usize].Position, vertices[t[1] as usize].Position, vertices[t[2] as usize].Position);
+    let cross = (p1 - p0).cross(p2 - p0);
+    let area = cross.magnitude() / 2.0;
+    if area == 0.0 {
+      continue;
+    }
+    let n = cross.normalize();
+    let q = Quadric::fromPlane(n, -n.dot(p0), area);
+    for &i in t {
+      quadrics[canonical[i as usize] as usize].add(&q);
+    }
+  }
+
+  // Each pass collapses the cheapest edges that don't touch one another, then rebuilds the
+  // adjacency, until the target is met or nothing more can move
+  indices = withoutDegenerates(&indices, &canonical);
+  let mut triangles = indices.len() / 3;
+  while triangles > targetTriangles {
+    let mut adjacency: Vec<Vec<usize>> = vec![vec![]; vertices.len()];
+    for (t, corners) in indices.chunks(3).enumerate() {
+      for &i in corners {
+        adjacency[canonical[i as usize] as usize].push(t);
+      }
+    }
+
+    let mut candidates: Vec<(f64, u32, u32)> = vec![];
+    for t in indices.chunks(3) {
+      for e in 0..3 {
+        for &(from, to) in &[(t[e], t[(e + 1) % 3]), (t[(e + 1) % 3], t[e])] {
+          if locked[from as usize] {
+            continue;
+          }
+          let mut q = quadrics[from as usize];
+          q.add(&quadrics[canonical[to as usize] as usize]);
+          candidates.push((q.error(vertices[to as usize].Position), from, to));
+        }
+      }
+    }
+    candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut touched: HashSet<u32> = HashSet::new();
+    let mut collapses: HashMap<u32, u32> = HashMap::new();
+    for (_, from, to) in candidates {
+      if triangles <= targetTriangles {
+        break;
+      }
+      let toCanonical = canonical[to as usize];
+      if touched.contains(&from) || touched.contains(&toCanonical) {
+        continue;
+      }
+      let faces = &adjacency[from as usize];
+      if flips(vertices, &indices, faces, from, &canonical, to) {
+        continue;
+      }
+
+      // Everything around the collapse is off limits until the next pass, as its quadrics and
+      // neighbourhood are now stale
+      for &t in faces {
+        for &i in &indices[t * 3..t * 3 + 3] {
+          touched.insert(canonical[i as usize]);
+        }
+      }
+      let removed = faces.iter().filter(|&&t| indices[t * 3..t * 3 + 3].iter().any(|&i| canonical[i as usize] == toCanonical)).count();
+      triangles -= removed;
+      let q = quadrics[from as usize];
+      quadrics[toCanonical as usize].add(&q);
+      collapses.insert(from, to);
+    }
+    if collapses.is_empty() {
+      break;
+    }
+
+    for i in indices.iter_mut() {
+      if let Some(&to) = collapses.get(i) {
+        *i = to;
+      }
+    }
+    indices = withoutDegenerates(&indices, &canonical);
+    triangles = indices.len() / 3;
+  }
+  indices
+}
+
+/// Drops triangles with two corners at the same position
+fn withoutDegenerates(indices: &[u32], canonical: &[u32]) -> Vec<u32> {
+  indices.chunks(3)
+    .filter(|t| {
+      let (a, b, c) = (canonical[t[0] as usize], canonical[t[1] as usize], canonical[t[2] as usize]);
+      a != b && b != c && a != c
+    })
+    .flat_map(|t| t.to_vec())
+    .collect()
+}
+
+/// True if moving `from` onto `to` would turn any surviving triangle around it upside down
+fn flips(vertices: &[Vertex], indices: &[u32], faces: &[usize], from: u32, canonical: &[u32], to: u32) -> bool {
+  let target = vertices[to as usize].Position;
+  let toCanonical = canonical[to as usize];
+  faces.iter().any(|&t| {
+    let corners = &indices[t * 3..t * 3 + 3];
+    if corners.iter().any(|&i| canonical[i as usize] == toCanonical) {
+      return false;
+    }
+    let p: Vec<Vector3> = corners.iter().map(|&i| vertices[i as usize].Position).collect();
+    let moved: Vec<Vector3> = corners.iter().map(|&i| if i == from { target } else { vertices[i as usize].Position }).collect();
+    let before = (p[1] - p[0]).cross(p[2] - p[0]);
+    let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+    before.dot(after) <= 0.0
+  })
+}
+
+/// Successively simpler index lists for `ratios` of the original triangle count, each built from
+/// the one before. LODs that fail to get any smaller than the previous are dropped
+pub fn lodChain(vertices: &[Vertex], indices: &[u32], ratios: &[f32]) -> Vec<Vec<u32>> {
+  let triangles = indices.len() / 3;
+  let mut lods: Vec<Vec<u32>> = vec![];
+  for ratio in ratios {
+    let source = lods.last().map(|l| l.as_slice()).unwrap_or(indices);
+    let lod = simplify(vertices, source, (triangles as f32 * ratio) as usize);
+    if lod.len() < source.len() {
+      lods.push(lod);
+    }
+  }
+  lods
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{vec2, vec3};
+
+  use super::*;
+
+  /// Flat `n` x `n` grid of quads in the XZ plane facing up
+  fn grid(n: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = vec![];
+    for z in 0..=n {
+      for x in 0..=n {
+        vertices.push(Vertex { Position: vec3(x as f32, 0.0, z as f32), Normal: vec3(0.0, 1.0, 0.0), TexCoords: vec2(0.0, 0.0) });
+      }
+    }
+    let mut indices = vec![];
+    for z in 0..n {
+      for x in 0..n {
+        let i = z * (n + 1) + x;
+        indices.extend_from_slice(&[i, i + n + 1, i + 1, i + 1, i + n + 1, i + n + 2]);
+      }
+    }
+    (vertices, indices)
+  }
+
+  fn normal(vertices: &[Vertex], t: &[u32]) -> Vector3 {
+    let p = |i: u32| vertices[i as usize].Position;
+    (p(t[1]) - p(t[0])).cross(p(t[2]) - p(t[0]))
+  }
+
+  #[test]
+  fn flatGridReachesTarget() {
+    let (vertices, indices) = grid(10);
+    let simplified = simplify(&vertices, &indices, 50);
+    assert_eq!(simplified.len() % 3, 0);
+    assert!(simplified.len() / 3 <= 50, "{} triangles", simplified.len() / 3);
+    for t in simplified.chunks(3) {
+      let n = normal(&vertices, t);
+      assert!(n.y > 0.0, "degenerate or flipped triangle {:?}", t);
+    }
+  }
+
+  #[test]
+  fn bordersStayPut() {
+    let (vertices, indices) = grid(6);
+    let simplified = simplify(&vertices, &indices, 0);
+    let used: HashSet<u32> = simplified.iter().cloned().collect();
+    for (i, v) in vertices.iter().enumerate() {
+      let p = v.Position;
+      if p.x == 0.0 || p.z == 0.0 || p.x == 6.0 || p.z == 6.0 {
+        assert!(used.contains(&(i as u32)), "border vertex {} was removed", i);
+      }
+    }
+    // Area is preserved exactly as the grid is flat and the outline fixed
+    let area: f32 = simplified.chunks(3).map(|t| normal(&vertices, t).magnitude() / 2.0).sum();
+    assert!((area - 36.0).abs() < 1e-3, "area {}", area);
+  }
+
+  #[test]
+  fn sphereKeepsItsShape() {
+    let (vertices, indices) = crate::utils::maths::genSphere(16, 24);
+    let simplified = simplify(&vertices, &indices, indices.len() / 3 / 4);
+    assert!(simplified.len() < indices.len() / 2);
+    // Nothing moves off the unit sphere since collapses only pick existing vertices, and the
+    // surviving triangles still face outwards, or at worst edge on along the seam
+    for t in simplified.chunks(3) {
+      let n = normal(&vertices, t);
+      let centre = vertices[t[0] as usize].Position + vertices[t[1] as usize].Position + vertices[t[2] as usize].Position;
+      assert!(n.dot(centre) > -1e-4);
+    }
+  }
+
+  #[test]
+  fn lodChainShrinks() {
+    let (vertices, indices) = grid(16);
+    let lods = lodChain(&vertices, &indices, &[0.5, 0.25, 0.1]);
+    assert!(!lods.is_empty());
+    let mut previous = indices.len();
+    for lod in &lods {
+      assert!(lod.len() < previous);
+      previous = lod.len();
+    }
+  }
+}
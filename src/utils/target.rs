@@ -0,0 +1,190 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ptr;
+
+#[derive(Clone, Copy)]
+pub struct TextureFormat {
+  pub internal: u32,
+  pub format: u32,
+  pub type_: u32
+}
+
+pub const R8: TextureFormat = TextureFormat { internal: gl::R8, format: gl::RED, type_: gl::UNSIGNED_BYTE };
+pub const RG8: TextureFormat = TextureFormat { internal: gl::RG8, format: gl::RG, type_: gl::UNSIGNED_BYTE };
+pub const RGB8: TextureFormat = TextureFormat { internal: gl::RGB8, format: gl::RGB, type_: gl::UNSIGNED_BYTE };
+pub const RGBA8: TextureFormat = TextureFormat { internal: gl::RGBA8, format: gl::RGBA, type_: gl::UNSIGNED_BYTE };
+pub const RGBA16F: TextureFormat = TextureFormat { internal: gl::RGBA16F, format: gl::RGBA, type_: gl::FLOAT };
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DepthAttachment {
+  None,
+  /// Write-only depth, cheaper when nothing needs to sample it
+  RenderBuffer,
+  /// Depth that can be bound and sampled by a later pass
+  Texture
+}
+
+/// An offscreen framebuffer with any number of colour textures and an optional depth attachment
+pub struct RenderTarget {
+  pub FBO: u32,
+  pub width: i32,
+  pub height: i32,
+  pub colourTextures: Vec<u32>,
+  pub depthTexture: u32,
+  depthBuffer: u32,
+  formats: Vec<TextureFormat>,
+  depth: DepthAttachment,
+  samples: i32
+}
+
+impl RenderTarget {
+  pub fn new(width: i32, height: i32, formats: &[TextureFormat], depth: DepthAttachment) -> RenderTarget {
+    RenderTarget::multisampled(width, height, formats, depth, 0)
+  }
+
+  /// With `samples` above zero the colour attachments are multisampled textures, which can't be
+  /// sampled as usual and have to be resolved into a plain target first. Depth is then always a
+  /// renderbuffer
+  pub fn multisampled(width: i32, height: i32, formats: &[TextureFormat], depth: DepthAttachment, samples: i32) -> RenderTarget {
+    let mut target = RenderTarget {
+      FBO: 0, width, height, colourTextures: vec![], depthTexture: 0, depthBuffer: 0, formats: formats.to_vec(), depth, samples
+    };
+    unsafe { target.setupTarget() };
+    target
+  }
+
+  /// Recreates the attachments at a new size, dropping their contents
+  pub fn resize(&mut self, width: i32, height: i32) {
+    if (width, height) == (self.width, self.height) {
+      return;
+    }
+    self.width = width;
+    self.height = height;
+    unsafe {
+      self.release();
+      self.setupTarget();
+    }
+  }
+
+  /// Copies the first colour attachment into `target`, averaging the samples if this is multisampled
+  pub unsafe fn resolveInto(&self, target: &RenderTarget) {
+    gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.FBO);
+    gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.FBO);
+    gl::BlitFramebuffer(0, 0, self.width, self.height, 0, 0, target.width, target.height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+  }
+
+  unsafe fn setupTarget(&mut self) {
+    gl::GenFramebuffers(1, &mut self.FBO);
+    gl::BindFramebuffer(gl::FRAMEBUFFER, self.FBO);
+
+    let mut drawBuffers = Vec::with_capacity(self.formats.len());
+    for (i, format) in self.formats.iter().enumerate() {
+      let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
+      self.colourTextures.push(if self.samples > 0 {
+        createMultisampledColourAttachment(attachment, *format, self.width, self.height, self.samples)
+      } else {
+        createColourAttachment(attachment, *format, self.width, self.height)
+      });
+      drawBuffers.push(attachment);
+    }
+
+    if drawBuffers.is_empty() {
+      gl::DrawBuffer(gl::NONE);
+      gl::ReadBuffer(gl::NONE);
+    } else {
+      gl::DrawBuffers(drawBuffers.len() as i32, drawBuffers.as_ptr());
+    }
+
+    match self.depth {
+      DepthAttachment::None => {}
+      _ if self.samples > 0 => self.depthBuffer = createDepthBufferAttachment(self.width, self.height, self.samples),
+      DepthAttachment::RenderBuffer => self.depthBuffer = createDepthBufferAttachment(self.width, self.height, 0),
+      DepthAttachment::Texture => self.depthTexture = createDepthTextureAttachment(self.width, self.height)
+    }
+
+    if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+      println!("ERROR::FRAMEBUFFER:: {}x{} render target is not complete", self.width, self.height);
+    }
+    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+  }
+
+  pub fn colourTexture(&self) -> u32 {
+    self.colourTextures[0]
+  }
+
+  pub unsafe fn bind(&self) {
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+    gl::BindFramebuffer(gl::FRAMEBUFFER, self.FBO);
+    gl::Viewport(0, 0, self.width, self.height);
+  }
+
+  /// Rebinds the window's framebuffer
+  pub unsafe fn bindDefault(width: i32, height: i32) {
+    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    gl::Viewport(0, 0, width, height);
+  }
+
+  unsafe fn release(&mut self) {
+    gl::DeleteFramebuffers(1, &self.FBO);
+    gl::DeleteTextures(self.colourTextures.len() as i32, self.colourTextures.as_ptr());
+    gl::DeleteTextures(1, &self.depthTexture);
+    gl::DeleteRenderbuffers(1, &self.depthBuffer);
+    self.colourTextures.clear();
+    self.depthTexture = 0;
+    self.depthBuffer = 0;
+  }
+}
+
+impl Drop for RenderTarget {
+  fn drop(&mut self) {
+    unsafe { self.release() }
+  }
+}
+
+unsafe fn createColourAttachment(attachment: u32, format: TextureFormat, width: i32, height: i32) -> u32 {
+  let mut texture = 0;
+  gl::GenTextures(1, &mut texture);
+  gl::BindTexture(gl::TEXTURE_2D, texture);
+  gl::TexImage2D(gl::TEXTURE_2D, 0, format.internal as i32, width, height, 0, format.format, format.type_, ptr::null());
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+  gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, gl::TEXTURE_2D, texture, 0);
+  texture
+}
+
+unsafe fn createMultisampledColourAttachment(attachment: u32, format: TextureFormat, width: i32, height: i32, samples: i32) -> u32 {
+  let mut texture = 0;
+  gl::GenTextures(1, &mut texture);
+  gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, texture);
+  gl::TexImage2DMultisample(gl::TEXTURE_2D_MULTISAMPLE, samples, format.internal, width, height, gl::TRUE);
+  gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, 0);
+  gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, gl::TEXTURE_2D_MULTISAMPLE, texture, 0);
+  texture
+}
+
+unsafe fn createDepthTextureAttachment(width: i32, height: i32) -> u32 {
+  let mut texture = 0;
+  gl::GenTextures(1, &mut texture);
+  gl::BindTexture(gl::TEXTURE_2D, texture);
+  // Same format as the renderbuffers so multisampled depth can be resolved into it
+  gl::TexImage2D(gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT24 as i32, width, height, 0, gl::DEPTH_COMPONENT, gl::FLOAT, ptr::null());
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+  gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+  gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, texture, 0);
+  texture
+}
+
+unsafe fn createDepthBufferAttachment(width: i32, height: i32, samples: i32) -> u32 {
+  let mut buffer = 0;
+  gl::GenRenderbuffers(1, &mut buffer);
+  gl::BindRenderbuffer(gl::RENDERBUFFER, buffer);
+  gl::RenderbufferStorageMultisample(gl::RENDERBUFFER, samples, gl::DEPTH_COMPONENT24, width, height);
+  gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, buffer);
+  buffer
+}
@@ -0,0 +1,562 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+// Quadratic curves are flattened into this many segments, plenty at the sizes text is baked at
+const CURVE_SEGMENTS: usize = 8;
+// Composite glyphs can nest, but never this deep in a sane font
+const MAX_COMPONENT_DEPTH: usize = 8;
+const ATLAS_WIDTH: usize = 512;
+// Gap between glyphs so linear filtering doesn't bleed neighbours in
+const ATLAS_PADDING: usize = 1;
+
+/// The tables of a TrueType font needed to lay out and rasterise its glyphs. Only `glyf`
+/// outlines are read, so CFF based OpenType fonts are rejected
+pub struct FontFile {
+  data: Vec<u8>,
+  pub unitsPerEm: f32,
+  pub ascent: f32,
+  pub descent: f32,
+  pub lineGap: f32,
+  numGlyphs: u16,
+  numHMetrics: u16,
+  longLoca: bool,
+  cmap: usize,
+  glyf: usize,
+  loca: usize,
+  hmtx: usize
+}
+
+/// One contour as a closed polyline, in font units with y up
+pub type Contour = Vec<(f32, f32)>;
+
+/// Coverage of one glyph, `left` and `top` placing its top left corner relative to the pen on the baseline
+pub struct GlyphBitmap {
+  pub width: usize,
+  pub height: usize,
+  pub left: f32,
+  pub top: f32,
+  pub pixels: Vec<u8>
+}
+
+/// Where a glyph sits in an atlas and how to place it, all in pixels at the baked size
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasGlyph {
+  pub x: usize,
+  pub y: usize,
+  pub width: usize,
+  pub height: usize,
+  pub left: f32,
+  pub top: f32,
+  pub advance: f32
+}
+
+/// Single channel coverage texture holding a set of characters, row 0 at the top
+pub struct Atlas {
+  pub width: usize,
+  pub height: usize,
+  pub pixels: Vec<u8>,
+  pub glyphs: HashMap<char, AtlasGlyph>,
+  pub pixelHeight: f32,
+  pub ascent: f32,
+  pub lineHeight: f32
+}
+
+impl FontFile {
+  pub fn parse(data: Vec<u8>) -> Result<FontFile, String> {
+    let version = u32At(&data, 0)?;
+    if version != 0x00010000 && &data[..4] != b"true" {
+      return Err("not a TrueType font".into());
+    }
+    let numTables = u16At(&data, 4)? as usize;
+    let mut tables = HashMap::new();
+    for i in 0..numTables {
+      let record = 12 + i * 16;
+      let tag = data.get(record..record + 4).ok_or("truncated table directory")?;
+      tables.insert(tag.to_vec(), u32At(&data, record + 8)? as usize);
+    }
+    let table = |tag: &[u8; 4]| tables.get(&tag[..]).cloned().ok_or_else(|| format!("missing {} table", String::from_utf8_lossy(tag)));
+    let (head, hhea, maxp) = (table(b"head")?, table(b"hhea")?, table(b"maxp")?);
+
+    let font = FontFile {
+      unitsPerEm: u16At(&data, head + 18)? as f32,
+      ascent: i16At(&data, hhea + 4)? as f32,
+      descent: i16At(&data, hhea + 6)? as f32,
+      lineGap: i16At(&data, hhea + 8)? as f32,
+      numHMetrics: u16At(&data, hhea + 34)?,
+      numGlyphs: u16At(&data, maxp + 4)?,
+      longLoca: i16At(&data, head + 50)? == 1,
+      cmap: findUnicodeCmap(&data, table(b"cmap")?)?,
+      glyf: table(b"glyf")?,
+      loca: table(b"loca")?,
+      hmtx: table(b"hmtx")?,
+      data
+    };
+    if font.unitsPerEm == 0.0 || font.numHMetrics == 0 {
+      return Err("invalid head or hhea table".into());
+    }
+    Ok(font)
+  }
+
+  /// Glyph for a character, 0 being the font's "missing" glyph
+  pub fn glyphIndex(&self, c: char) -> u16 {
+    let c = c as u32;
+    if c > 0xFFFF {
+      return 0;
+    }
+    self.lookupFormat4(c as u16).unwrap_or(0)
+  }
+
+  fn lookupFormat4(&self, c: u16) -> Result<u16, String> {
+    let (data, table) = (&self.data, self.cmap);
+    let segCount = u16At(data, table + 6)? as usize / 2;
+    let endCodes = table + 14;
+    let startCodes = endCodes + segCount * 2 + 2;
+    let idDeltas = startCodes + segCount * 2;
+    let idRangeOffsets = idDeltas + segCount * 2;
+    for segment in 0..segCount {
+      if u16At(data, endCodes + segment * 2)? < c {
+        continue;
+      }
+      let start = u16At(data, startCodes + segment * 2)?;
+      if start > c {
+        return Ok(0);
+      }
+      let delta = u16At(data, idDeltas + segment * 2)?;
+      let rangeOffsetAt = idRangeOffsets + segment * 2;
+      let rangeOffset = u16At(data, rangeOffsetAt)? as usize;
+      if rangeOffset == 0 {
+        return Ok(c.wrapping_add(delta));
+      }
+      let glyph = u16At(data, rangeOffsetAt + rangeOffset + (c - start) as usize * 2)?;
+      return Ok(if glyph == 0 { 0 } else { glyph.wrapping_add(delta) });
+    }
+    Ok(0)
+  }
+
+  /// Horizontal advance in font units
+  pub fn advance(&self, glyph: u16) -> f32 {
+    let metric = glyph.min(self.numHMetrics - 1) as usize;
+    u16At(&self.data, self.hmtx + metric * 4).unwrap_or(0) as f32
+  }
+
+  pub fn outline(&self, glyph: u16) -> Result<Vec<Contour>, String> {
+    self.outlineAt(glyph, 0)
+  }
+
+  fn outlineAt(&self, glyph: u16, depth: usize) -> Result<Vec<Contour>, String> {
+    if glyph >= self.numGlyphs {
+      return Err(format!("glyph {} out of range", glyph));
+    }
+    if depth > MAX_COMPONENT_DEPTH {
+      return Err("composite glyphs nested too deeply".into());
+    }
+    let (start, end) = if self.longLoca {
+      (u32At(&self.data, self.loca + glyph as usize * 4)? as usize, u32At(&self.data, self.loca + glyph as usize * 4 + 4)? as usize)
+    } else {
+      (u16At(&self.data, self.loca + glyph as usize * 2)? as usize * 2, u16At(&self.data, self.loca + glyph as usize * 2 + 2)? as usize * 2)
+    };
+    // Empty glyphs such as the space have no outline at all
+    if end <= start {
+      return Ok(vec![]);
+    }
+    let offset = self.glyf + start;
+    let contours = i16At(&self.data, offset)?;
+    if contours >= 0 {
+      self.simpleOutline(offset, contours as usize)
+    } else {
+      self.compositeOutline(offset, depth)
+    }
+  }
+
+  fn simpleOutline(&self, offset: usize, contourCount: usize) -> Result<Vec<Contour>, String> {
+    let data = &self.data;
+    let mut endPoints = Vec::with_capacity(contourCount);
+    for i in 0..contourCount {
+      endPoints.push(u16At(data, offset + 10 + i * 2)? as usize);
+    }
+    let pointCount = endPoints.last().map_or(0, |&e| e + 1);
+    let instructions = u16At(data, offset + 10 + contourCount * 2)? as usize;
+    let mut at = offset + 12 + contourCount * 2 + instructions;
+
+    let mut flags = Vec::with_capacity(pointCount);
+    while flags.len() < pointCount {
+      let flag = byteAt(data, at)?;
+      at += 1;
+      flags.push(flag);
+      // Bit 3 repeats the flag the number of times in the next byte
+      if flag & 8 != 0 {
+        let repeat = byteAt(data, at)?;
+        at += 1;
+        for _ in 0..repeat {
+          flags.push(flag);
+        }
+      }
+    }
+    flags.truncate(pointCount);
+
+    // Coordinates are deltas, either one unsigned byte with the sign in the flags or a signed
+    // word, or unchanged from the previous point
+    let mut readAxis = |short: u8, same: u8| -> Result<Vec<f32>, String> {
+      let mut value = 0i32;
+      let mut values = Vec::with_capacity(pointCount);
+      for &flag in &flags {
+        if flag & short != 0 {
+          let delta = byteAt(data, at)? as i32;
+          at += 1;
+          value += if flag & same != 0 { delta } else { -delta };
+        } else if flag & same == 0 {
+          value += i16At(data, at)? as i32;
+          at += 2;
+        }
+        values.push(value as f32);
+      }
+      Ok(values)
+    };
+    let xs = readAxis(2, 16)?;
+    let ys = readAxis(4, 32)?;
+
+    let mut contours = Vec::with_capacity(contourCount);
+    let mut first = 0;
+    for &last in &endPoints {
+      if last < first || last >= pointCount {
+        return Err("invalid contour end point".into());
+      }
+      let points: Vec<(f32, f32, bool)> = (first..=last).map(|i| (xs[i], ys[i], flags[i] & 1 != 0)).collect();
+      contours.push(flattenContour(&points));
+      first = last + 1;
+    }
+    Ok(contours)
+  }
+
+  fn compositeOutline(&self, offset: usize, depth: usize) -> Result<Vec<Contour>, String> {
+    const ARGS_ARE_WORDS: u16 = 0x0001;
+    const ARGS_ARE_XY: u16 = 0x0002;
+    const HAVE_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const HAVE_XY_SCALE: u16 = 0x0040;
+    const HAVE_2X2: u16 = 0x0080;
+
+    let data = &self.data;
+    let mut contours = vec![];
+    let mut at = offset + 10;
+    loop {
+      let flags = u16At(data, at)?;
+      let glyph = u16At(data, at + 2)?;
+      at += 4;
+      let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+        at += 4;
+        (i16At(data, at - 4)? as f32, i16At(data, at - 2)? as f32)
+      } else {
+        at += 2;
+        (byteAt(data, at - 2)? as i8 as f32, byteAt(data, at - 1)? as i8 as f32)
+      };
+      // Components positioned by matching points aren't supported and are left where they are
+      let (dx, dy) = if flags & ARGS_ARE_XY != 0 { (dx, dy) } else { (0.0, 0.0) };
+      let (mut a, mut b, mut c, mut d) = (1.0, 0.0, 0.0, 1.0);
+      if flags & HAVE_SCALE != 0 {
+        a = f2dot14At(data, at)?;
+        d = a;
+        at += 2;
+      } else if flags & HAVE_XY_SCALE != 0 {
+        a = f2dot14At(data, at)?;
+        d = f2dot14At(data, at + 2)?;
+        at += 4;
+      } else if flags & HAVE_2X2 != 0 {
+        a = f2dot14At(data, at)?;
+        b = f2dot14At(data, at + 2)?;
+        c = f2dot14At(data, at + 4)?;
+        d = f2dot14At(data, at + 6)?;
+        at += 8;
+      }
+      for contour in self.outlineAt(glyph, depth + 1)? {
+        contours.push(contour.into_iter().map(|(x, y)| (a * x + c * y + dx, b * x + d * y + dy)).collect());
+      }
+      if flags & MORE_COMPONENTS == 0 {
+        return Ok(contours);
+      }
+    }
+  }
+
+  pub fn rasterise(&self, glyph: u16, scale: f32) -> Result<GlyphBitmap, String> {
+    let contours = self.outline(glyph)?;
+    let points = contours.iter().flatten();
+    let (mut minX, mut minY, mut maxX, mut maxY) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for &(x, y) in points {
+      minX = minX.min(x * scale);
+      maxX = maxX.max(x * scale);
+      minY = minY.min(y * scale);
+      maxY = maxY.max(y * scale);
+    }
+    if minX > maxX {
+      return Ok(GlyphBitmap { width: 0, height: 0, left: 0.0, top: 0.0, pixels: vec![] });
+    }
+    let (left, top) = (minX.floor(), maxY.ceil());
+    let width = (maxX.ceil() - left) as usize + 1;
+    let height = (top - minY.floor()) as usize + 1;
+    // Flip y to put row 0 at the top
+    let placed: Vec<Contour> = contours.iter()
+      .map(|contour| contour.iter().map(|&(x, y)| (x * scale - left, top - y * scale)).collect())
+      .collect();
+    Ok(GlyphBitmap { width, height, left, top, pixels: rasteriseContours(&placed, width, height) })
+  }
+
+  /// Rasterises `chars` at `pixelHeight` pixels per em and packs them into rows of an atlas.
+  /// Characters the font lacks get its missing glyph
+  pub fn bakeAtlas(&self, pixelHeight: f32, chars: &[char]) -> Result<Atlas, String> {
+    let scale = pixelHeight / self.unitsPerEm;
+    let mut bitmaps = Vec::with_capacity(chars.len());
+    for &c in chars {
+      let glyph = self.glyphIndex(c);
+      bitmaps.push((c, self.rasterise(glyph, scale)?, self.advance(glyph) * scale));
+    }
+
+    let mut glyphs = HashMap::new();
+    let (mut x, mut y, mut rowHeight) = (ATLAS_PADDING, ATLAS_PADDING, 0);
+    for (c, bitmap, advance) in &bitmaps {
+      if x + bitmap.width + ATLAS_PADDING > ATLAS_WIDTH {
+        x = ATLAS_PADDING;
+        y += rowHeight + ATLAS_PADDING;
+        rowHeight = 0;
+      }
+      glyphs.insert(*c, AtlasGlyph { x, y, width: bitmap.width, height: bitmap.height, left: bitmap.left, top: bitmap.top, advance: *advance });
+      x += bitmap.width + ATLAS_PADDING;
+      rowHeight = rowHeight.max(bitmap.height);
+    }
+    let height = (y + rowHeight + ATLAS_PADDING).next_power_of_two();
+
+    let mut pixels = vec![0u8; ATLAS_WIDTH * height];
+    for (c, bitmap, _) in &bitmaps {
+      let glyph = glyphs[c];
+      for row in 0..bitmap.height {
+        let target = (glyph.y + row) * ATLAS_WIDTH + glyph.x;
+        pixels[target..target + bitmap.width].copy_from_slice(&bitmap.pixels[row * bitmap.width..(row + 1) * bitmap.width]);
+      }
+    }
+    Ok(Atlas {
+      width: ATLAS_WIDTH,
+      height,
+      pixels,
+      glyphs,
+      pixelHeight,
+      ascent: self.ascent * scale,
+      lineHeight: (self.ascent - self.descent + self.lineGap) * scale
+    })
+  }
+}
+
+/// Picks the Windows or Unicode platform BMP subtable, which is format 4 in every font that has one
+fn findUnicodeCmap(data: &[u8], cmap: usize) -> Result<usize, String> {
+  let count = u16At(data, cmap + 2)? as usize;
+  for i in 0..count {
+    let record = cmap + 4 + i * 8;
+    let (platform, encoding) = (u16At(data, record)?, u16At(data, record + 2)?);
+    let subtable = cmap + u32At(data, record + 4)? as usize;
+    if ((platform == 3 && encoding == 1) || platform == 0) && u16At(data, subtable)? == 4 {
+      return Ok(subtable);
+    }
+  }
+  Err("no Unicode BMP character map".into())
+}
+
+/// Turns on- and off-curve points into a polyline, adding the on-curve midpoint TrueType implies
+/// between two consecutive off-curve points
+fn flattenContour(points: &[(f32, f32, bool)]) -> Contour {
+  let mut contour = vec![];
+  if points.is_empty() {
+    return contour;
+  }
+  let midpoint = |a: (f32, f32, bool), b: (f32, f32, bool)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+  // Start from an on-curve point, or a midpoint if every point is off the curve
+  let startIndex = points.iter().position(|p| p.2);
+  let start = match startIndex {
+    Some(i) => (points[i].0, points[i].1),
+    None => midpoint(points[points.len() - 1], points[0])
+  };
+  let offset = startIndex.map_or(0, |i| i + 1);
+  contour.push(start);
+
+  let mut control: Option<(f32, f32)> = None;
+  for i in 0..points.len() {
+    let point = points[(offset + i) % points.len()];
+    match (point.2, control) {
+      (true, None) => contour.push((point.0, point.1)),
+      (true, Some(c)) => {
+        quadratic(&mut contour, c, (point.0, point.1));
+        control = None;
+      }
+      (false, None) => control = Some((point.0, point.1)),
+      (false, Some(c)) => {
+        let mid = ((c.0 + point.0) / 2.0, (c.1 + point.1) / 2.0);
+        quadratic(&mut contour, c, mid);
+        control = Some((point.0, point.1));
+      }
+    }
+  }
+  if let Some(c) = control {
+    quadratic(&mut contour, c, start);
+  }
+  contour
+}
+
+fn quadratic(contour: &mut Contour, control: (f32, f32), end: (f32, f32)) {
+  let start = *contour.last().unwrap();
+  for i in 1..=CURVE_SEGMENTS {
+    let t = i as f32 / CURVE_SEGMENTS as f32;
+    let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * (1.0 - t) * t, t * t);
+    contour.push((a * start.0 + b * control.0 + c * end.0, a * start.1 + b * control.1 + c * end.1));
+  }
+}
+
+/// Anti-aliased coverage of closed contours given in pixels, y down. Each edge adds the signed
+/// area it covers to the cells it crosses, and a running sum along the rows gives the coverage
+pub fn rasteriseContours(contours: &[Contour], width: usize, height: usize) -> Vec<u8> {
+  // One spare cell for edges in the last column spilling right
+  let mut area = vec![0.0f32; width * height + 1];
+  for contour in contours {
+    for i in 0..contour.len() {
+      accumulateEdge(&mut area, width, height, contour[i], contour[(i + 1) % contour.len()]);
+    }
+  }
+  let mut sum = 0.0;
+  area[..width * height].iter().map(|a| {
+    sum += a;
+    (sum.abs().min(1.0) * 255.0 + 0.5) as u8
+  }).collect()
+}
+
+fn accumulateEdge(area: &mut [f32], width: usize, height: usize, from: (f32, f32), to: (f32, f32)) {
+  if from.1 == to.1 {
+    return;
+  }
+  let (direction, top, bottom) = if from.1 < to.1 { (1.0, from, to) } else { (-1.0, to, from) };
+  let dxdy = (bottom.0 - top.0) / (bottom.1 - top.1);
+  let mut x = top.0;
+  if top.1 < 0.0 {
+    x -= top.1 * dxdy;
+  }
+  let firstRow = top.1.max(0.0) as usize;
+  let lastRow = (bottom.1.ceil() as usize).min(height);
+  for row in firstRow..lastRow {
+    let rowStart = row * width;
+    let dy = (row as f32 + 1.0).min(bottom.1) - (row as f32).max(top.1);
+    let xNext = x + dxdy * dy;
+    let d = dy * direction;
+    let (x0, x1) = if x < xNext { (x, xNext) } else { (xNext, x) };
+    let x0Floor = x0.floor();
+    let x0i = x0Floor as usize;
+    let x1Ceil = x1.ceil();
+    let x1i = x1Ceil as usize;
+    if x1i <= x0i + 1 {
+      // Within one cell, split between it and the next by where the edge crosses on average
+      let xm = (x + xNext) / 2.0 - x0Floor;
+      area[rowStart + x0i] += d - d * xm;
+      area[rowStart + x0i + 1] += d * xm;
+    } else {
+      let s = 1.0 / (x1 - x0);
+      let x0f = x0 - x0Floor;
+      let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+      let x1f = x1 - x1Ceil + 1.0;
+      let am = 0.5 * s * x1f * x1f;
+      area[rowStart + x0i] += d * a0;
+      if x1i == x0i + 2 {
+        area[rowStart + x0i + 1] += d * (1.0 - a0 - am);
+      } else {
+        let a1 = s * (1.5 - x0f);
+        area[rowStart + x0i + 1] += d * (a1 - a0);
+        for xi in x0i + 2..x1i - 1 {
+          area[rowStart + xi] += d * s;
+        }
+        let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+        area[rowStart + x1i - 1] += d * (1.0 - a2 - am);
+      }
+      area[rowStart + x1i] += d * am;
+    }
+    x = xNext;
+  }
+}
+
+fn byteAt(data: &[u8], offset: usize) -> Result<u8, String> {
+  data.get(offset).cloned().ok_or_else(|| "unexpected end of font".to_string())
+}
+
+fn u16At(data: &[u8], offset: usize) -> Result<u16, String> {
+  let bytes = data.get(offset..offset + 2).ok_or("unexpected end of font")?;
+  Ok(u16::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn i16At(data: &[u8], offset: usize) -> Result<i16, String> {
+  u16At(data, offset).map(|v| v as i16)
+}
+
+fn u32At(data: &[u8], offset: usize) -> Result<u32, String> {
+  let bytes = data.get(offset..offset + 4).ok_or("unexpected end of font")?;
+  Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn f2dot14At(data: &[u8], offset: usize) -> Result<f32, String> {
+  i16At(data, offset).map(|v| v as f32 / 16384.0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FONT: &str = "resources/fonts/DejaVuSans.ttf";
+
+  fn font() -> FontFile {
+    FontFile::parse(std::fs::read(FONT).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn squareCoverage() {
+    // 2x2 square from (1, 1) to (3, 3), then one whose edges cut pixels in half
+    let square = vec![(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)];
+    let pixels = rasteriseContours(&[square], 4, 4);
+    assert_eq!(pixels, vec![0, 0, 0, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 0, 0, 0]);
+
+    let half = vec![(0.5, 0.0), (1.5, 0.0), (1.5, 1.0), (0.5, 1.0)];
+    assert_eq!(rasteriseContours(&[half], 3, 1), vec![128, 128, 0]);
+  }
+
+  #[test]
+  fn offCurvePointsImplyMidpoints() {
+    // Two control points in a row put an on-curve point between them
+    let contour = flattenContour(&[(0.0, 0.0, true), (0.0, 10.0, false), (10.0, 10.0, false), (10.0, 0.0, true)]);
+    assert_eq!(contour.len(), 2 + 2 * CURVE_SEGMENTS);
+    assert_eq!(contour[CURVE_SEGMENTS], (5.0, 10.0));
+    assert_eq!(*contour.last().unwrap(), (0.0, 0.0));
+  }
+
+  #[test]
+  fn parsesBundledFont() {
+    let font = font();
+    assert_eq!(font.unitsPerEm, 2048.0);
+    assert!(font.glyphIndex('A') != 0);
+    assert_eq!(font.glyphIndex('\u{E000}'), 0);
+    assert!(font.advance(font.glyphIndex('W')) > font.advance(font.glyphIndex('l')));
+    // 'O' has an outer and an inner contour, 'Ä' is a composite of 'A' and a diaeresis
+    assert_eq!(font.outline(font.glyphIndex('O')).unwrap().len(), 2);
+    assert!(font.outline(font.glyphIndex('Ä')).unwrap().len() > font.outline(font.glyphIndex('A')).unwrap().len());
+    assert!(font.outline(font.glyphIndex(' ')).unwrap().is_empty());
+  }
+
+  #[test]
+  fn bakesAtlas() {
+    let chars: Vec<char> = (32u8..127).map(char::from).collect();
+    let atlas = font().bakeAtlas(32.0, &chars).unwrap();
+    assert_eq!(atlas.glyphs.len(), chars.len());
+    assert!(atlas.height.is_power_of_two());
+
+    let space = atlas.glyphs[&' '];
+    assert_eq!((space.width, space.height), (0, 0));
+    assert!(space.advance > 0.0);
+
+    // A capital reaches about the cap height, with solid stems and nothing between them
+    let h = atlas.glyphs[&'H'];
+    assert!(h.top > 20.0 && h.top < atlas.ascent + 1.0);
+    let pixel = |x: usize, y: usize| atlas.pixels[(h.y + y) * atlas.width + h.x + x];
+    assert_eq!(pixel(1, h.height / 4), 255);
+    assert_eq!(pixel(h.width / 2, h.height / 4), 0);
+  }
+}
@@ -0,0 +1,96 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::mem::size_of_val;
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::daynight::Sky;
+use crate::light::{Light, LightingSettings, MAX_LIGHTS};
+use crate::types::*;
+
+/// Binding point of the `Frame` block declared in frame.glsl
+pub const FRAME_BINDING: u32 = 0;
+
+// std140 offsets of the `Frame` block members, in bytes
+const VIEW: usize = 0;
+const PROJECTION: usize = 64;
+const CAMERA_POSITION: usize = 128;
+const LIGHT_POSITION: usize = 144;
+const LIGHT_COLOUR: usize = LIGHT_POSITION + 16 * MAX_LIGHTS;
+const ATTENUATION: usize = LIGHT_COLOUR + 16 * MAX_LIGHTS;
+const LIGHT_COUNT: usize = ATTENUATION + 16 * MAX_LIGHTS;
+const SKY_LIGHT_DIRECTION: usize = LIGHT_COUNT + 16;
+const SKY_LIGHT_COLOUR: usize = SKY_LIGHT_DIRECTION + 16;
+const AMBIENT_SKY: usize = SKY_LIGHT_COLOUR + 16;
+const AMBIENT_GROUND: usize = AMBIENT_SKY + 16;
+const SIZE: usize = AMBIENT_GROUND + 16;
+
+/// Uniform buffer shared by every program, holding what stays constant across a render pass
+pub struct FrameUniforms {
+  UBO: u32
+}
+
+impl FrameUniforms {
+  pub fn new() -> FrameUniforms {
+    let mut UBO = 0;
+    unsafe {
+      gl::GenBuffers(1, &mut UBO);
+      gl::BindBuffer(gl::UNIFORM_BUFFER, UBO);
+      gl::BufferData(gl::UNIFORM_BUFFER, SIZE as isize, ptr::null(), gl::DYNAMIC_DRAW);
+      gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+      gl::BindBufferBase(gl::UNIFORM_BUFFER, FRAME_BINDING, UBO);
+    }
+    FrameUniforms { UBO }
+  }
+
+  /// Called once per pass, since the water passes render from a mirrored camera
+  pub fn setCamera(&self, view: &Matrix4, projection: &Matrix4, position: &Point3) {
+    let position = [position.x, position.y, position.z, 1.0];
+    unsafe {
+      gl::BindBuffer(gl::UNIFORM_BUFFER, self.UBO);
+      write(VIEW, &[*view]);
+      write(PROJECTION, &[*projection]);
+      write(CAMERA_POSITION, &[position]);
+      gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+    }
+  }
+
+  /// Uploads up to `MAX_LIGHTS` lights
+  pub fn setLights(&self, lights: &[Light]) {
+    let count = lights.len().min(MAX_LIGHTS);
+    let pad = |v: &Vector3| [v.x, v.y, v.z, 0.0];
+    let positions: Vec<[f32; 4]> = lights.iter().take(count).map(|l| pad(&l.position)).collect();
+    let colours: Vec<[f32; 4]> = lights.iter().take(count).map(|l| pad(&l.colour)).collect();
+    let attenuations: Vec<[f32; 4]> = lights.iter().take(count).map(|l| pad(&l.attenuation)).collect();
+    unsafe {
+      gl::BindBuffer(gl::UNIFORM_BUFFER, self.UBO);
+      write(LIGHT_POSITION, &positions);
+      write(LIGHT_COLOUR, &colours);
+      write(ATTENUATION, &attenuations);
+      write(LIGHT_COUNT, &[count as i32]);
+      gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+    }
+  }
+
+  /// The sun or moon and the ambient light, changing with the time of day
+  pub fn setSky(&self, sky: &Sky, lighting: &LightingSettings) {
+    let pad = |v: &Vector3| [v.x, v.y, v.z, 0.0];
+    let (ambientSky, ambientGround) = lighting.hemisphereColours(sky.ambient);
+    unsafe {
+      gl::BindBuffer(gl::UNIFORM_BUFFER, self.UBO);
+      write(SKY_LIGHT_DIRECTION, &[pad(&sky.lightDirection)]);
+      write(SKY_LIGHT_COLOUR, &[pad(&sky.lightColour)]);
+      write(AMBIENT_SKY, &[pad(&ambientSky)]);
+      write(AMBIENT_GROUND, &[pad(&ambientGround)]);
+      gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+    }
+  }
+}
+
+unsafe fn write<T>(offset: usize, data: &[T]) {
+  if !data.is_empty() {
+    let size = size_of_val(data) as isize;
+    gl::BufferSubData(gl::UNIFORM_BUFFER, offset as isize, size, data.as_ptr() as *const c_void);
+  }
+}
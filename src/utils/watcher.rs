@@ -0,0 +1,47 @@
+#![allow(non_snake_case)]
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Polls modification times of a set of files, reporting the ones that changed since the last poll
+pub struct FileWatcher {
+  files: HashMap<PathBuf, Option<SystemTime>>,
+  interval: Duration,
+  lastPoll: Instant
+}
+
+impl FileWatcher {
+  pub fn new(interval: Duration) -> FileWatcher {
+    FileWatcher { files: HashMap::default(), interval, lastPoll: Instant::now() }
+  }
+
+  pub fn watch<P: AsRef<Path>>(&mut self, path: P) {
+    let path = path.as_ref().to_path_buf();
+    let modified = modifiedTime(&path);
+    self.files.entry(path).or_insert(modified);
+  }
+
+  /// Returns nothing until `interval` has passed since the previous poll so it's cheap to call every frame
+  pub fn poll(&mut self) -> Vec<PathBuf> {
+    if self.lastPoll.elapsed() < self.interval {
+      return vec![];
+    }
+    self.lastPoll = Instant::now();
+
+    let mut changed = vec![];
+    for (path, lastModified) in self.files.iter_mut() {
+      let modified = modifiedTime(path);
+      // Editors often replace files on save, so a briefly missing file isn't reported
+      if modified.is_some() && modified != *lastModified {
+        *lastModified = modified;
+        changed.push(path.clone());
+      }
+    }
+    changed
+  }
+}
+
+fn modifiedTime(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|m| m.modified()).ok()
+}
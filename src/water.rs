@@ -0,0 +1,145 @@
+#![allow(non_snake_case)]
+use gl;
+
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+
+use cgmath::vec3;
+
+use crate::assets::{AssetManager, TextureHandle};
+use crate::utils::shader::Shader;
+use crate::utils::common::ColourSpace;
+use crate::utils::target::{RenderTarget, DepthAttachment, RGB8};
+use crate::types::*;
+use crate::c_str;
+
+const REFLECTION_WIDTH: i32 = 1280;
+const REFLECTION_HEIGHT: i32 = 720;
+const REFRACTION_WIDTH: i32 = 1920;
+const REFRACTION_HEIGHT: i32 = 1080;
+const WAVE_SPEED: f32 = 0.03;
+
+// Unit quad in the xz plane, scaled up to the water's extent by the model matrix
+static WATER_VERTICES: [f32; 12] = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+
+pub struct WaterFrameBuffers {
+  pub reflection: RenderTarget,
+  pub refraction: RenderTarget
+}
+
+pub struct Water {
+  pub height: f32,
+  pub size: f32,
+  pub fbos: WaterFrameBuffers,
+  pub tint: Vector3,
+  dudvTexture: TextureHandle,
+  normalTexture: TextureHandle,
+  moveFactor: f32,
+  VAO: u32,
+  VBO: u32
+}
+
+impl WaterFrameBuffers {
+  pub fn new() -> WaterFrameBuffers {
+    WaterFrameBuffers {
+      reflection: RenderTarget::new(REFLECTION_WIDTH, REFLECTION_HEIGHT, &[RGB8], DepthAttachment::RenderBuffer),
+      refraction: RenderTarget::new(REFRACTION_WIDTH, REFRACTION_HEIGHT, &[RGB8], DepthAttachment::Texture)
+    }
+  }
+}
+
+impl Water {
+  pub fn new(height: f32, size: f32, tint: Vector3, assets: &mut AssetManager) -> Water {
+    let dir = "resources/textures";
+    let mut water = Water {
+      height,
+      size,
+      fbos: WaterFrameBuffers::new(),
+      tint,
+      dudvTexture: assets.texture(&format!("{}/waterDUDV.png", dir), ColourSpace::Linear),
+      normalTexture: assets.texture(&format!("{}/waterNormal.png", dir), ColourSpace::Linear),
+      moveFactor: 0.0,
+      VAO: 0,
+      VBO: 0
+    };
+    unsafe { water.setupWater() };
+    water
+  }
+
+  unsafe fn setupWater(&mut self) {
+    gl::GenVertexArrays(1, &mut self.VAO);
+    gl::GenBuffers(1, &mut self.VBO);
+
+    gl::BindVertexArray(self.VAO);
+    gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
+    let size = (WATER_VERTICES.len() * size_of::<f32>()) as isize;
+    let data = &WATER_VERTICES[0] as *const f32 as *const c_void;
+    gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+
+    gl::EnableVertexAttribArray(0);
+    gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 2 * size_of::<f32>() as i32, ptr::null());
+    gl::BindVertexArray(0);
+  }
+
+  /// Clip plane keeping everything above the surface, used when rendering the reflection
+  pub fn reflectionPlane(&self) -> Vector4 {
+    Vector4 { x: 0.0, y: 1.0, z: 0.0, w: -self.height }
+  }
+
+  /// Clip plane keeping everything below the surface, used when rendering the refraction
+  pub fn refractionPlane(&self) -> Vector4 {
+    Vector4 { x: 0.0, y: -1.0, z: 0.0, w: self.height }
+  }
+
+  pub fn update(&mut self, deltaTime: f32) {
+    self.moveFactor = (self.moveFactor + WAVE_SPEED * deltaTime) % 1.0;
+  }
+
+  fn getModelMatrix(&self) -> Matrix4 {
+    Matrix4::from_translation(vec3(0.0, self.height, 0.0)) * Matrix4::from_nonuniform_scale(self.size, 1.0, self.size)
+  }
+
+  /// Specular highlights come from the sun or moon in the frame uniforms
+  pub unsafe fn draw(&self, shader: &Shader) {
+    shader.updateModel(&self.getModelMatrix());
+    shader.setFloat(c_str!("moveFactor"), self.moveFactor);
+    shader.setInt(c_str!("reflectionTexture"), 0);
+    shader.setInt(c_str!("refractionTexture"), 1);
+    shader.setInt(c_str!("dudvMap"), 2);
+    shader.setInt(c_str!("normalMap"), 3);
+    shader.setInt(c_str!("depthMap"), 4);
+
+    let textures = [
+      self.fbos.reflection.colourTexture(), self.fbos.refraction.colourTexture(),
+      self.dudvTexture.id, self.normalTexture.id, self.fbos.refraction.depthTexture
+    ];
+    for (i, texture) in textures.iter().enumerate() {
+      gl::ActiveTexture(gl::TEXTURE0 + i as u32);
+      gl::BindTexture(gl::TEXTURE_2D, *texture);
+    }
+
+    gl::Enable(gl::BLEND);
+    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    gl::BindVertexArray(self.VAO);
+    gl::DrawArrays(gl::TRIANGLES, 0, (WATER_VERTICES.len() / 2) as i32);
+    gl::BindVertexArray(0);
+    gl::Disable(gl::BLEND);
+
+    for i in 0..textures.len() {
+      gl::ActiveTexture(gl::TEXTURE0 + i as u32);
+      gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+    gl::ActiveTexture(gl::TEXTURE0);
+  }
+}
+
+impl Drop for Water {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteBuffers(1, &self.VBO);
+      gl::DeleteVertexArrays(1, &self.VAO);
+    }
+  }
+}